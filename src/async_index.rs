@@ -0,0 +1,49 @@
+use crate::document::DocumentId;
+use crate::index::InvertedIndex;
+use futures_core::Stream;
+use std::future::poll_fn;
+use std::pin::pin;
+
+/// Documents indexed between yields to the async runtime.
+const YIELD_EVERY: usize = 64;
+
+impl InvertedIndex {
+    /// Indexes `(title, content)` pairs as `stream` produces them, returning
+    /// the new ids in order. Indexing is synchronous per document, so the
+    /// task yields to the runtime every few dozen documents to keep large
+    /// batches from starving other tasks.
+    pub async fn add_document_stream<S>(&mut self, stream: S) -> Vec<DocumentId>
+    where
+        S: Stream<Item = (String, String)>,
+    {
+        let mut stream = pin!(stream);
+        let mut ids = Vec::new();
+        while let Some((title, content)) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+            ids.push(self.add_document(title, content));
+            if ids.len() % YIELD_EVERY == 0 {
+                tokio::task::yield_now().await;
+            }
+        }
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_add_document_stream_indexes_everything() {
+        let mut index = InvertedIndex::new();
+        let documents = (0..150).map(|i| (format!("Doc {}", i), format!("streamed item{}", i)));
+
+        let ids = index
+            .add_document_stream(tokio_stream::iter(documents))
+            .await;
+
+        assert_eq!(ids.len(), 150);
+        assert_eq!(index.total_documents(), 150);
+        assert_eq!(index.search("streamed").len(), 150);
+        assert_eq!(index.search("item149"), vec![ids[149]]);
+    }
+}
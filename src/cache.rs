@@ -0,0 +1,142 @@
+use crate::search::SearchResult;
+use std::collections::HashMap;
+
+/// Hit and miss counters for a searcher's result cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub entries: usize,
+}
+
+/// A least-recently-used map from query keys to ranked results, valid for a
+/// single index generation.
+#[derive(Debug, Clone)]
+pub(crate) struct ResultCache {
+    capacity: usize,
+    generation: u64,
+    clock: u64,
+    entries: HashMap<String, (u64, Vec<SearchResult>)>,
+    hits: usize,
+    misses: usize,
+}
+
+impl ResultCache {
+    pub(crate) fn new(capacity: usize, generation: u64) -> Self {
+        Self {
+            capacity,
+            generation,
+            clock: 0,
+            entries: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Drops every entry if the index has changed since they were stored.
+    pub(crate) fn sync_generation(&mut self, generation: u64) {
+        if self.generation != generation {
+            self.entries.clear();
+            self.generation = generation;
+        }
+    }
+
+    pub(crate) fn get(&mut self, key: &str) -> Option<Vec<SearchResult>> {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some((last_used, results)) => {
+                *last_used = self.clock;
+                self.hits += 1;
+                Some(results.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub(crate) fn insert(&mut self, key: String, results: Vec<SearchResult>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (last_used, _))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(key, (self.clock, results));
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.entries.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(doc_id: usize) -> Vec<SearchResult> {
+        vec![SearchResult {
+            doc_id,
+            score: 1.0,
+            title: String::new(),
+            snippet: String::new(),
+        }]
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = ResultCache::new(2, 0);
+        cache.insert("a".to_string(), result(1));
+        cache.insert("b".to_string(), result(2));
+        assert!(cache.get("a").is_some());
+
+        cache.insert("c".to_string(), result(3));
+
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+        assert_eq!(cache.stats().entries, 2);
+    }
+
+    #[test]
+    fn test_generation_change_clears_entries() {
+        let mut cache = ResultCache::new(4, 0);
+        cache.insert("a".to_string(), result(1));
+
+        cache.sync_generation(0);
+        assert_eq!(cache.stats().entries, 1);
+
+        cache.sync_generation(1);
+        assert_eq!(cache.stats().entries, 0);
+        assert!(cache.get("a").is_none());
+    }
+
+    #[test]
+    fn test_zero_capacity_stores_nothing() {
+        let mut cache = ResultCache::new(0, 0);
+        cache.insert("a".to_string(), result(1));
+
+        assert!(cache.get("a").is_none());
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 0,
+                misses: 1,
+                entries: 0
+            }
+        );
+    }
+}
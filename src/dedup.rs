@@ -0,0 +1,103 @@
+use crate::document::DocumentId;
+use crate::index::Fnv1aHasher;
+use crate::tokenizer::Token;
+use std::hash::Hasher;
+
+/// Number of hash functions in a MinHash signature.
+const SIGNATURE_LEN: usize = 64;
+/// Words per shingle.
+const SHINGLE_LEN: usize = 3;
+
+/// Outcome of [`crate::InvertedIndex::add_document_dedup`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum DedupResult {
+    /// The document was new enough to be indexed under this id.
+    Indexed(DocumentId),
+    /// The document was skipped as a near-duplicate of `existing`.
+    Duplicate {
+        existing: DocumentId,
+        similarity: f64,
+    },
+}
+
+/// MinHash signature over the word shingles of `tokens`. Equal positions in
+/// two signatures estimate the Jaccard similarity of their shingle sets.
+pub(crate) fn minhash_signature(tokens: &[Token]) -> Vec<u64> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+
+    let mut signature = vec![u64::MAX; SIGNATURE_LEN];
+    for shingle in tokens.windows(SHINGLE_LEN.min(tokens.len())) {
+        let mut hasher = Fnv1aHasher::default();
+        for token in shingle {
+            hasher.write(token.text.as_bytes());
+            hasher.write_u8(0);
+        }
+        let base = hasher.finish();
+
+        for (seed, slot) in signature.iter_mut().enumerate() {
+            *slot = (*slot).min(splitmix64(
+                base ^ (seed as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15),
+            ));
+        }
+    }
+    signature
+}
+
+/// Fraction of matching signature slots; 0.0 if either signature is empty.
+pub(crate) fn signature_similarity(a: &[u64], b: &[u64]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let matching = a.iter().zip(b).filter(|(x, y)| x == y).count();
+    matching as f64 / a.len().min(b.len()) as f64
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    fn signature(text: &str) -> Vec<u64> {
+        minhash_signature(&Tokenizer::new().tokenize(text))
+    }
+
+    #[test]
+    fn test_identical_text_has_full_similarity() {
+        let a = signature("rust makes systems programming safe and fast");
+
+        assert_eq!(a.len(), SIGNATURE_LEN);
+        assert_eq!(signature_similarity(&a, &a), 1.0);
+    }
+
+    #[test]
+    fn test_similarity_orders_by_overlap() {
+        let base = signature("rust makes systems programming safe and fast for everyone today");
+        let close = signature("rust makes systems programming safe and fast for everybody today");
+        let far = signature("python is a popular scripting language for data science work");
+
+        let close_similarity = signature_similarity(&base, &close);
+        let far_similarity = signature_similarity(&base, &far);
+        assert!(close_similarity > far_similarity);
+        assert!(far_similarity < 0.2);
+    }
+
+    #[test]
+    fn test_empty_text_is_never_similar() {
+        let empty = signature("");
+
+        assert!(empty.is_empty());
+        assert_eq!(
+            signature_similarity(&empty, &signature("anything here")),
+            0.0
+        );
+    }
+}
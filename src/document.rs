@@ -1,13 +1,19 @@
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
 
 pub type DocumentId = usize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Document {
     pub id: DocumentId,
     pub title: String,
     pub content: String,
     pub metadata: HashMap<String, String>,
+    /// When the document was added to a [`DocumentStore`]; `None` for
+    /// documents built directly.
+    #[serde(default)]
+    pub indexed_at: Option<SystemTime>,
 }
 
 impl Document {
@@ -17,6 +23,7 @@ impl Document {
             title,
             content,
             metadata: HashMap::new(),
+            indexed_at: None,
         }
     }
 
@@ -26,14 +33,100 @@ impl Document {
     }
 
     pub fn full_text(&self) -> String {
-        format!("{} {}", self.title, self.content)
+        self.full_text_with_separator(" ")
+    }
+
+    /// Title and content joined by `separator`. Joining with
+    /// [`crate::tokenizer::FIELD_SEPARATOR`] keeps phrases from matching
+    /// across the two when the result is tokenized.
+    pub fn full_text_with_separator(&self, separator: &str) -> String {
+        format!("{}{}{}", self.title, separator, self.content)
+    }
+
+    /// Time elapsed since the document was indexed, or `None` if it never
+    /// was.
+    pub fn age(&self) -> Option<Duration> {
+        self.indexed_at
+            .map(|at| SystemTime::now().duration_since(at).unwrap_or_default())
     }
 }
 
-#[derive(Debug)]
+/// Conditions on [`Document::metadata`], all of which must hold. A document
+/// lacking a key fails any condition on it.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[must_use]
+pub struct MetadataFilter {
+    conditions: Vec<MetadataCondition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum MetadataCondition {
+    Eq(String, String),
+    In(String, Vec<String>),
+    /// Inclusive bounds; values that don't parse as numbers never match.
+    Range {
+        key: String,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+}
+
+impl MetadataFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires `key` to be exactly `value`.
+    pub fn eq(mut self, key: &str, value: &str) -> Self {
+        self.conditions
+            .push(MetadataCondition::Eq(key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Requires `key` to be one of `values`.
+    pub fn is_in(mut self, key: &str, values: &[&str]) -> Self {
+        self.conditions.push(MetadataCondition::In(
+            key.to_string(),
+            values.iter().map(|v| v.to_string()).collect(),
+        ));
+        self
+    }
+
+    /// Requires `key` to parse as a number within `min..=max`; a `None`
+    /// bound is open.
+    pub fn range(mut self, key: &str, min: Option<f64>, max: Option<f64>) -> Self {
+        self.conditions.push(MetadataCondition::Range {
+            key: key.to_string(),
+            min,
+            max,
+        });
+        self
+    }
+
+    pub fn matches(&self, doc: &Document) -> bool {
+        self.conditions.iter().all(|condition| match condition {
+            MetadataCondition::Eq(key, value) => doc.metadata.get(key) == Some(value),
+            MetadataCondition::In(key, values) => doc
+                .metadata
+                .get(key)
+                .is_some_and(|value| values.contains(value)),
+            MetadataCondition::Range { key, min, max } => doc
+                .metadata
+                .get(key)
+                .and_then(|value| value.trim().parse::<f64>().ok())
+                .is_some_and(|n| min.is_none_or(|min| n >= min) && max.is_none_or(|max| n <= max)),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DocumentStore {
     documents: HashMap<DocumentId, Document>,
     next_id: DocumentId,
+    /// Latest timestamp handed out, so `indexed_at` never decreases even if
+    /// the system clock steps backwards.
+    #[serde(default)]
+    last_indexed_at: Option<SystemTime>,
 }
 
 impl DocumentStore {
@@ -41,13 +134,27 @@ impl DocumentStore {
         Self {
             documents: HashMap::new(),
             next_id: 0,
+            last_indexed_at: None,
         }
     }
 
     pub fn add_document(&mut self, title: String, content: String) -> DocumentId {
+        self.add_document_with_metadata(title, content, HashMap::new())
+    }
+
+    pub fn add_document_with_metadata(
+        &mut self,
+        title: String,
+        content: String,
+        metadata: HashMap<String, String>,
+    ) -> DocumentId {
         let id = self.next_id;
         self.next_id += 1;
-        let doc = Document::new(id, title, content);
+        let now = SystemTime::now().max(self.last_indexed_at.unwrap_or(SystemTime::UNIX_EPOCH));
+        self.last_indexed_at = Some(now);
+        let mut doc = Document::new(id, title, content);
+        doc.metadata = metadata;
+        doc.indexed_at = Some(now);
         self.documents.insert(id, doc);
         id
     }
@@ -56,6 +163,15 @@ impl DocumentStore {
         self.documents.get(&id)
     }
 
+    pub fn get_document_mut(&mut self, id: DocumentId) -> Option<&mut Document> {
+        self.documents.get_mut(&id)
+    }
+
+    /// Removes and returns the document. Its id is never handed out again.
+    pub fn remove_document(&mut self, id: DocumentId) -> Option<Document> {
+        self.documents.remove(&id)
+    }
+
     pub fn total_documents(&self) -> usize {
         self.documents.len()
     }
@@ -65,6 +181,12 @@ impl DocumentStore {
     }
 }
 
+impl Default for DocumentStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,6 +214,46 @@ mod tests {
         assert_eq!(doc.metadata.len(), 2);
     }
 
+    #[test]
+    fn test_metadata_filter_conditions() {
+        let doc = Document::new(1, "Title".to_string(), "Content".to_string())
+            .with_metadata("lang".to_string(), "rust".to_string())
+            .with_metadata("year".to_string(), "2015".to_string());
+
+        assert!(MetadataFilter::new().matches(&doc));
+        assert!(MetadataFilter::new().eq("lang", "rust").matches(&doc));
+        assert!(!MetadataFilter::new().eq("lang", "go").matches(&doc));
+        assert!(
+            MetadataFilter::new()
+                .is_in("lang", &["go", "rust"])
+                .matches(&doc)
+        );
+        assert!(
+            MetadataFilter::new()
+                .range("year", Some(2015.0), None)
+                .matches(&doc)
+        );
+        assert!(
+            !MetadataFilter::new()
+                .range("year", None, Some(2014.0))
+                .matches(&doc)
+        );
+        // Non-numeric and missing values never satisfy a range
+        assert!(
+            !MetadataFilter::new()
+                .range("lang", None, None)
+                .matches(&doc)
+        );
+        assert!(!MetadataFilter::new().eq("author", "x").matches(&doc));
+        // Conditions are ANDed
+        assert!(
+            !MetadataFilter::new()
+                .eq("lang", "rust")
+                .range("year", Some(2020.0), None)
+                .matches(&doc)
+        );
+    }
+
     #[test]
     fn test_document_full_text() {
         let doc = Document::new(1, "Hello World".to_string(), "This is content".to_string());
@@ -135,6 +297,37 @@ mod tests {
         assert!(store.get_document(999).is_none());
     }
 
+    #[test]
+    fn test_document_store_add_document_with_metadata() {
+        let mut store = DocumentStore::new();
+        let metadata = HashMap::from([("author".to_string(), "Jane Doe".to_string())]);
+
+        let id = store.add_document_with_metadata(
+            "Title".to_string(),
+            "Content".to_string(),
+            metadata.clone(),
+        );
+
+        let doc = store.get_document(id).unwrap();
+        assert_eq!(doc.metadata, metadata);
+    }
+
+    #[test]
+    fn test_document_store_sets_indexed_at() {
+        let mut store = DocumentStore::new();
+        let first = store.add_document("First".to_string(), "one".to_string());
+        let second = store.add_document("Second".to_string(), "two".to_string());
+
+        let first = store.get_document(first).unwrap();
+        let second = store.get_document(second).unwrap();
+        assert!(second.indexed_at.unwrap() >= first.indexed_at.unwrap());
+        assert!(first.age().is_some_and(|age| age >= Duration::ZERO));
+
+        let standalone = Document::new(7, "Loose".to_string(), "not stored".to_string());
+        assert!(standalone.indexed_at.is_none());
+        assert!(standalone.age().is_none());
+    }
+
     #[test]
     fn test_document_store_iteration() {
         let mut store = DocumentStore::new();
@@ -1,27 +1,61 @@
+use crate::dedup::{DedupResult, minhash_signature, signature_similarity};
 use crate::document::{Document, DocumentId, DocumentStore};
-use crate::tokenizer::Tokenizer;
-use std::collections::HashMap;
-
-#[derive(Debug, Clone, PartialEq)]
+use crate::search::{BooleanOperator, ScoringOptions, TermIdf};
+use crate::tokenizer::{Token, Tokenizer};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum FieldType {
     Title,
     Content,
+    /// Text of the named metadata key, indexed via
+    /// [`InvertedIndex::index_metadata_key`].
+    Metadata(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TermPosition {
     pub position: usize,
     pub field: FieldType,
+    /// Sentence index within the field, as reported by the tokenizer.
+    #[serde(default)]
+    pub sentence: usize,
+    /// The word as written at this position, recorded when the tokenizer
+    /// keeps originals.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) original: Option<String>,
+    /// The word before stemming, recorded when the tokenizer dual-indexes
+    /// and stemming changed it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) exact: Option<String>,
 }
 
-#[derive(Debug)]
+impl TermPosition {
+    /// The word as written at this position, recorded when the tokenizer
+    /// keeps originals.
+    pub fn original(&self) -> Option<&str> {
+        self.original.as_deref()
+    }
+
+    /// The word before stemming, recorded when the tokenizer dual-indexes
+    /// and stemming changed it.
+    pub fn exact(&self) -> Option<&str> {
+        self.exact.as_deref()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PostingEntry {
     pub doc_id: DocumentId,
     pub term_frequency: usize,
     pub positions: Vec<TermPosition>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PostingList {
     pub term: String,
     pub document_frequency: usize,
@@ -46,29 +80,328 @@ impl PostingList {
         });
         self.document_frequency += 1;
     }
+
+    /// Evicts the lowest-tf postings (the newest on ties) until at most `cap`
+    /// remain, returning how many were removed.
+    fn prune_to(&mut self, cap: usize) -> usize {
+        let mut evicted = 0;
+        while self.postings.len() > cap {
+            let lowest = self
+                .postings
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, p)| (p.term_frequency, std::cmp::Reverse(p.doc_id)))
+                .map(|(i, _)| i)
+                .unwrap();
+            self.postings.remove(lowest);
+            self.document_frequency -= 1;
+            evicted += 1;
+        }
+        evicted
+    }
+
+    /// Appends positions to the document's existing posting, or adds a new
+    /// posting. Returns `true` when a new posting was created.
+    fn add_or_extend_posting(&mut self, doc_id: DocumentId, positions: Vec<TermPosition>) -> bool {
+        match self.postings.iter_mut().find(|p| p.doc_id == doc_id) {
+            Some(posting) => {
+                posting.term_frequency += positions.len();
+                posting.positions.extend(positions);
+                false
+            }
+            None => {
+                self.add_posting(doc_id, positions);
+                true
+            }
+        }
+    }
+}
+
+/// Read-only access to one posting that doesn't depend on how postings are
+/// stored.
+#[derive(Debug, Clone, Copy)]
+pub struct PostingView<'a> {
+    entry: &'a PostingEntry,
+}
+
+impl<'a> PostingView<'a> {
+    pub fn doc_id(&self) -> DocumentId {
+        self.entry.doc_id
+    }
+
+    pub fn term_frequency(&self) -> usize {
+        self.entry.term_frequency
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = &'a TermPosition> + 'a {
+        self.entry.positions.iter()
+    }
+}
+
+/// An inconsistency found by [`InvertedIndex::validate`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConsistencyError {
+    /// A posting references a document that isn't in the store.
+    OrphanedPosting { term: String, doc_id: DocumentId },
+    /// `document_frequency` disagrees with the number of postings.
+    DocumentFrequencyMismatch {
+        term: String,
+        recorded: usize,
+        actual: usize,
+    },
+    /// A term is present in the vocabulary with no postings.
+    EmptyPostingList { term: String },
+    /// `total_terms` disagrees with the total number of postings.
+    TotalTermsMismatch { recorded: usize, actual: usize },
+}
+
+/// Why a stored document could not be updated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpdateError {
+    DocumentNotFound(DocumentId),
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateError::DocumentNotFound(doc_id) => write!(f, "no document with id {}", doc_id),
+        }
+    }
 }
 
+impl std::error::Error for UpdateError {}
+
+/// The serialized form carries the tokenizer and scoring configuration, so a
+/// reloaded index analyzes queries exactly as the original did.
+#[derive(Serialize, Deserialize)]
 pub struct InvertedIndex {
     pub index: HashMap<String, PostingList>,
     document_store: DocumentStore,
     total_terms: usize,
     tokenizer: Tokenizer,
+    scoring: ScoringOptions,
+    metadata_keys: Vec<String>,
+    document_lengths: HashMap<DocumentId, usize>,
+    /// Indexed tokens per field of each document.
+    #[serde(default)]
+    field_lengths: HashMap<DocumentId, Vec<(FieldType, usize)>>,
+    /// Every vocabulary term reversed, kept only once
+    /// [`InvertedIndex::enable_suffix_index`] has been called.
+    suffix_index: Option<BTreeSet<String>>,
+    /// Original spellings seen for each term, kept only once
+    /// [`InvertedIndex::enable_surface_forms`] has been called.
+    #[serde(default)]
+    surface_forms: Option<HashMap<String, BTreeSet<String>>>,
+    /// Drawn from [`next_generation`], so no two indexes, nor an index and
+    /// its reloaded copy, ever share one.
+    #[serde(skip, default = "next_generation")]
+    generation: u64,
+    #[serde(default)]
+    default_operator: BooleanOperator,
+    #[serde(default)]
+    max_postings_per_term: Option<usize>,
+    #[serde(default)]
+    max_phrase_terms: Option<usize>,
+    /// Fields kept out of the postings; see
+    /// [`InvertedIndex::set_field_searchable`].
+    #[serde(default)]
+    unsearchable_fields: Vec<FieldType>,
+    /// Metadata fields a document keeps out of its own postings; see
+    /// [`DocumentBuilder::field_with_options`].
+    #[serde(default)]
+    stored_only_fields: HashMap<DocumentId, Vec<String>>,
+    /// MinHash signatures of document content for near-duplicate detection,
+    /// computed on demand.
+    #[serde(skip)]
+    content_signatures: HashMap<DocumentId, Vec<u64>>,
+    #[serde(skip)]
+    idf_cache: Option<IdfCache>,
+}
+
+/// A generation no index in this process has had yet.
+fn next_generation() -> u64 {
+    static GENERATIONS: AtomicU64 = AtomicU64::new(0);
+    GENERATIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Per-term idf computed by [`InvertedIndex::warm_up`], valid only while the
+/// index stays at `generation`.
+#[derive(Debug)]
+struct IdfCache {
+    generation: u64,
+    entries: HashMap<String, TermIdf>,
 }
 
 impl InvertedIndex {
     pub fn new() -> Self {
+        Self::with_tokenizer(Tokenizer::new())
+    }
+
+    /// Starts configuring a new index; see [`IndexBuilder`].
+    pub fn builder() -> IndexBuilder {
+        IndexBuilder::new()
+    }
+
+    pub fn with_tokenizer(tokenizer: Tokenizer) -> Self {
         Self {
             index: HashMap::new(),
             document_store: DocumentStore::new(),
             total_terms: 0,
-            tokenizer: Tokenizer::new(),
+            tokenizer,
+            scoring: ScoringOptions::default(),
+            metadata_keys: Vec::new(),
+            document_lengths: HashMap::new(),
+            field_lengths: HashMap::new(),
+            suffix_index: None,
+            surface_forms: None,
+            generation: next_generation(),
+            default_operator: BooleanOperator::And,
+            max_postings_per_term: None,
+            max_phrase_terms: None,
+            unsearchable_fields: Vec::new(),
+            stored_only_fields: HashMap::new(),
+            content_signatures: HashMap::new(),
+            idf_cache: None,
         }
     }
 
     pub fn add_document(&mut self, title: String, content: String) -> DocumentId {
+        self.insert_document(title, content, HashMap::new())
+    }
+
+    /// Adds a document carrying `metadata`. Values are stored, and indexed
+    /// for any key registered with [`Self::index_metadata_key`].
+    pub fn add_document_with_metadata(
+        &mut self,
+        title: String,
+        content: String,
+        metadata: HashMap<String, String>,
+    ) -> DocumentId {
+        self.insert_document(title, content, metadata)
+    }
+
+    /// Starts building a document with metadata and extra searchable fields.
+    pub fn add(&mut self) -> DocumentBuilder<'_> {
+        DocumentBuilder {
+            index: self,
+            title: String::new(),
+            content: String::new(),
+            metadata: HashMap::new(),
+            fields: Vec::new(),
+            stored_only: Vec::new(),
+        }
+    }
+
+    /// Adds each `(title, content)` pair in order, returning the new ids.
+    pub fn add_documents(
+        &mut self,
+        documents: impl IntoIterator<Item = (String, String)>,
+    ) -> Vec<DocumentId> {
+        documents
+            .into_iter()
+            .map(|(title, content)| self.add_document(title, content))
+            .collect()
+    }
+
+    /// Like [`Self::add_documents`], calling `on_progress(done, total)` after
+    /// each document is added.
+    pub fn add_documents_with_progress<I>(
+        &mut self,
+        documents: I,
+        mut on_progress: impl FnMut(usize, usize),
+    ) -> Vec<DocumentId>
+    where
+        I: IntoIterator<Item = (String, String)>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let documents = documents.into_iter();
+        let total = documents.len();
+        documents
+            .enumerate()
+            .map(|(i, (title, content))| {
+                let id = self.add_document(title, content);
+                on_progress(i + 1, total);
+                id
+            })
+            .collect()
+    }
+
+    /// Adds the document unless its content is a near-duplicate of an
+    /// existing document's, judged by MinHash over three-word shingles.
+    /// `similarity_threshold` is an estimated Jaccard similarity in `0.0..=1.0`.
+    pub fn add_document_dedup(
+        &mut self,
+        title: String,
+        content: String,
+        similarity_threshold: f64,
+    ) -> DedupResult {
+        let signature = minhash_signature(&self.tokenizer.tokenize(&content));
+
+        let missing: Vec<DocumentId> = self
+            .document_ids()
+            .filter(|id| !self.content_signatures.contains_key(id))
+            .collect();
+        for doc_id in missing {
+            let content = &self.document_store.get_document(doc_id).unwrap().content;
+            let existing = minhash_signature(&self.tokenizer.tokenize(content));
+            self.content_signatures.insert(doc_id, existing);
+        }
+
+        let closest = self
+            .content_signatures
+            .iter()
+            .map(|(&doc_id, existing)| (doc_id, signature_similarity(&signature, existing)))
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)));
+        if let Some((existing, similarity)) = closest
+            && similarity >= similarity_threshold
+        {
+            return DedupResult::Duplicate {
+                existing,
+                similarity,
+            };
+        }
+
+        let doc_id = self.add_document(title, content);
+        self.content_signatures.insert(doc_id, signature);
+        DedupResult::Indexed(doc_id)
+    }
+
+    fn insert_document(
+        &mut self,
+        title: String,
+        content: String,
+        metadata: HashMap<String, String>,
+    ) -> DocumentId {
+        self.insert_document_with_stored_only(title, content, metadata, Vec::new())
+    }
+
+    /// Like [`Self::insert_document`], but the metadata fields in
+    /// `stored_only` are kept out of this document's postings.
+    fn insert_document_with_stored_only(
+        &mut self,
+        title: String,
+        content: String,
+        metadata: HashMap<String, String>,
+        stored_only: Vec<String>,
+    ) -> DocumentId {
         let doc_id = self
             .document_store
-            .add_document(title.clone(), content.clone());
+            .add_document_with_metadata(title, content, metadata);
+        if !stored_only.is_empty() {
+            self.stored_only_fields.insert(doc_id, stored_only);
+        }
+        self.generation = next_generation();
+        self.index_stored_document(doc_id);
+
+        doc_id
+    }
+
+    /// Builds postings and the length entry for a document already in the
+    /// store.
+    fn index_stored_document(&mut self, doc_id: DocumentId) {
+        if self.surface_forms.is_some() {
+            self.record_surface_forms(doc_id);
+        }
         let document = self.document_store.get_document(doc_id).unwrap();
 
         let title_terms = self.extract_terms(&document.title, FieldType::Title);
@@ -76,109 +409,1427 @@ impl InvertedIndex {
 
         let mut term_positions: HashMap<String, Vec<TermPosition>> = HashMap::new();
 
-        for (term, positions) in title_terms {
-            term_positions
-                .entry(term)
-                .or_insert_with(Vec::new)
-                .extend(positions);
-        }
+        for (term, positions) in title_terms {
+            term_positions.entry(term).or_default().extend(positions);
+        }
+
+        for (term, positions) in content_terms {
+            term_positions.entry(term).or_default().extend(positions);
+        }
+
+        for key in &self.metadata_keys {
+            if self.is_stored_only(doc_id, key) {
+                continue;
+            }
+            if let Some(value) = document.metadata.get(key) {
+                for (term, positions) in self.extract_terms(value, FieldType::Metadata(key.clone()))
+                {
+                    term_positions.entry(term).or_default().extend(positions);
+                }
+            }
+        }
+
+        let length = term_positions.values().map(Vec::len).sum();
+        self.document_lengths.insert(doc_id, length);
+        for position in term_positions.values().flatten() {
+            self.add_field_length(doc_id, &position.field, 1);
+        }
+
+        let cap = self.max_postings_per_term;
+        for (term, positions) in term_positions {
+            let posting_list = self.posting_list_mut(term);
+            posting_list.add_posting(doc_id, positions);
+            let evicted = cap.map_or(0, |cap| posting_list.prune_to(cap));
+            self.total_terms = self.total_terms + 1 - evicted;
+        }
+    }
+
+    /// Replaces the tokenizer. Existing postings keep their old analysis until
+    /// [`Self::reindex`] is called.
+    pub fn set_tokenizer(&mut self, tokenizer: Tokenizer) {
+        self.tokenizer = tokenizer;
+        self.content_signatures.clear();
+        // Queries analyze differently from now on, so cached results are stale.
+        self.generation = next_generation();
+    }
+
+    /// Rebuilds every posting from the stored documents with the current
+    /// tokenizer and metadata keys. Documents are visited in ascending id
+    /// order and keep their ids; only the posting structures are cleared.
+    pub fn reindex(&mut self) {
+        self.index.clear();
+        self.total_terms = 0;
+        self.document_lengths.clear();
+        self.field_lengths.clear();
+        if let Some(suffix_index) = &mut self.suffix_index {
+            suffix_index.clear();
+        }
+        if let Some(surface_forms) = &mut self.surface_forms {
+            surface_forms.clear();
+        }
+        self.generation = next_generation();
+
+        let mut doc_ids: Vec<DocumentId> = self.document_ids().collect();
+        doc_ids.sort_unstable();
+        for doc_id in doc_ids {
+            self.index_stored_document(doc_id);
+        }
+    }
+
+    /// Rebuilds the postings of one stored document, e.g. after editing it
+    /// through [`Self::get_document_mut`], leaving every other document's
+    /// postings alone. Spellings recorded by
+    /// [`Self::enable_surface_forms`] are only ever added to.
+    pub fn refresh_document(&mut self, doc_id: DocumentId) -> Result<(), UpdateError> {
+        if self.document_store.get_document(doc_id).is_none() {
+            return Err(UpdateError::DocumentNotFound(doc_id));
+        }
+        self.unindex_documents(&HashSet::from([doc_id]));
+        self.generation = next_generation();
+        self.index_stored_document(doc_id);
+        Ok(())
+    }
+
+    /// Removes every document whose metadata `key` is exactly `value`, along
+    /// with its postings, returning how many were removed.
+    pub fn remove_by_metadata(&mut self, key: &str, value: &str) -> usize {
+        let doc_ids: HashSet<DocumentId> = self
+            .document_store
+            .all_documents()
+            .filter(|doc| doc.metadata.get(key).is_some_and(|v| v == value))
+            .map(|doc| doc.id)
+            .collect();
+        self.unindex_documents(&doc_ids);
+        for &doc_id in &doc_ids {
+            self.document_store.remove_document(doc_id);
+            self.stored_only_fields.remove(&doc_id);
+        }
+        if !doc_ids.is_empty() {
+            self.generation = next_generation();
+        }
+        doc_ids.len()
+    }
+
+    /// Drops every posting, length and signature held for `doc_ids`, and any
+    /// term left without postings, in a single pass over the vocabulary.
+    fn unindex_documents(&mut self, doc_ids: &HashSet<DocumentId>) {
+        if doc_ids.is_empty() {
+            return;
+        }
+        let mut emptied = Vec::new();
+        for (term, posting_list) in &mut self.index {
+            let before = posting_list.postings.len();
+            posting_list
+                .postings
+                .retain(|p| !doc_ids.contains(&p.doc_id));
+            let removed = before - posting_list.postings.len();
+            posting_list.document_frequency -= removed;
+            self.total_terms -= removed;
+            if posting_list.postings.is_empty() {
+                emptied.push(term.clone());
+            }
+        }
+        for term in emptied {
+            self.index.remove(&term);
+            if let Some(suffix_index) = &mut self.suffix_index {
+                suffix_index.remove(&term.chars().rev().collect::<String>());
+            }
+        }
+        for doc_id in doc_ids {
+            self.document_lengths.remove(doc_id);
+            self.field_lengths.remove(doc_id);
+            self.content_signatures.remove(doc_id);
+        }
+    }
+
+    /// Controls whether `field` is indexed. An unsearchable field is still
+    /// stored with its document but kept out of the postings, so no query
+    /// matches it; for metadata this overrides [`Self::index_metadata_key`].
+    /// Changing the flag reindexes every document.
+    pub fn set_field_searchable(&mut self, field: FieldType, searchable: bool) {
+        if searchable == self.is_field_searchable(&field) {
+            return;
+        }
+        if searchable {
+            self.unsearchable_fields.retain(|f| *f != field);
+        } else {
+            self.unsearchable_fields.push(field);
+        }
+        self.reindex();
+    }
+
+    pub fn is_field_searchable(&self, field: &FieldType) -> bool {
+        !self.unsearchable_fields.contains(field)
+    }
+
+    /// Makes the values stored under metadata `key` searchable, indexing them
+    /// under [`FieldType::Metadata`] for existing and future documents.
+    pub fn index_metadata_key(&mut self, key: &str) {
+        if self.metadata_keys.iter().any(|k| k == key) {
+            return;
+        }
+        self.metadata_keys.push(key.to_string());
+        self.generation = next_generation();
+
+        let mut additions: Vec<(DocumentId, String, Vec<TermPosition>)> = Vec::new();
+        for document in self.document_store.all_documents() {
+            if self.is_stored_only(document.id, key) {
+                continue;
+            }
+            if let Some(value) = document.metadata.get(key) {
+                let field = FieldType::Metadata(key.to_string());
+                for (term, positions) in self.extract_terms(value, field) {
+                    additions.push((document.id, term, positions));
+                }
+            }
+        }
+
+        let cap = self.max_postings_per_term;
+        for (doc_id, term, positions) in additions {
+            *self.document_lengths.entry(doc_id).or_insert(0) += positions.len();
+            self.add_field_length(
+                doc_id,
+                &FieldType::Metadata(key.to_string()),
+                positions.len(),
+            );
+            let posting_list = self.posting_list_mut(term);
+            let added = posting_list.add_or_extend_posting(doc_id, positions) as usize;
+            let evicted = cap.map_or(0, |cap| posting_list.prune_to(cap));
+            self.total_terms = self.total_terms + added - evicted;
+        }
+
+        if self.surface_forms.is_some() {
+            let doc_ids: Vec<DocumentId> = self.document_ids().collect();
+            for doc_id in doc_ids {
+                self.record_surface_forms(doc_id);
+            }
+        }
+    }
+
+    /// Whether `doc_id` was added with `key` as a stored-only field.
+    fn is_stored_only(&self, doc_id: DocumentId, key: &str) -> bool {
+        self.stored_only_fields
+            .get(&doc_id)
+            .is_some_and(|names| names.iter().any(|name| name == key))
+    }
+
+    fn add_field_length(&mut self, doc_id: DocumentId, field: &FieldType, tokens: usize) {
+        let lengths = self.field_lengths.entry(doc_id).or_default();
+        match lengths.iter_mut().find(|(f, _)| f == field) {
+            Some((_, length)) => *length += tokens,
+            None => lengths.push((field.clone(), tokens)),
+        }
+    }
+
+    /// Returns the posting list for `term`, creating it (and registering the
+    /// term with the suffix index) if it is new.
+    fn posting_list_mut(&mut self, term: String) -> &mut PostingList {
+        if let Some(suffix_index) = &mut self.suffix_index
+            && !self.index.contains_key(&term)
+        {
+            suffix_index.insert(term.chars().rev().collect());
+        }
+        self.index
+            .entry(term.clone())
+            .or_insert_with(|| PostingList::new(term))
+    }
+
+    /// Maintains a reversed copy of the vocabulary so `*suffix` wildcards
+    /// become range lookups instead of full vocabulary scans. Costs roughly one
+    /// extra copy of every term, so it is off by default.
+    pub fn enable_suffix_index(&mut self) {
+        if self.suffix_index.is_none() {
+            let reversed = self
+                .index
+                .keys()
+                .map(|t| t.chars().rev().collect())
+                .collect();
+            self.suffix_index = Some(reversed);
+        }
+    }
+
+    /// Precomputes every term's idf and builds the suffix index, so the first
+    /// queries after loading or bulk indexing don't pay for them. Calling it
+    /// again is cheap until the index changes, which also makes the idf
+    /// cache stale until the next warm-up.
+    pub fn warm_up(&mut self) {
+        self.enable_suffix_index();
+        if self
+            .idf_cache
+            .as_ref()
+            .is_some_and(|cache| cache.generation == self.generation)
+        {
+            return;
+        }
+
+        let total_documents = self.total_documents();
+        let entries = self
+            .index
+            .iter()
+            .map(|(term, posting_list)| {
+                let idf = TermIdf::new(posting_list.document_frequency, total_documents);
+                (term.clone(), idf)
+            })
+            .collect();
+        self.idf_cache = Some(IdfCache {
+            generation: self.generation,
+            entries,
+        });
+    }
+
+    /// The warmed-up idf of `term`, if the cache is current.
+    pub(crate) fn cached_idf(&self, term: &str) -> Option<TermIdf> {
+        self.idf_cache
+            .as_ref()
+            .filter(|cache| cache.generation == self.generation)
+            .and_then(|cache| cache.entries.get(term).copied())
+    }
+
+    /// Starts recording the original spellings ("Rust", "RUST") behind each
+    /// analyzed term, including those of documents already indexed. Costs a
+    /// second tokenization pass per document, so it is off by default.
+    pub fn enable_surface_forms(&mut self) {
+        if self.surface_forms.is_none() {
+            self.surface_forms = Some(HashMap::new());
+            let doc_ids: Vec<DocumentId> = self.document_ids().collect();
+            for doc_id in doc_ids {
+                self.record_surface_forms(doc_id);
+            }
+        }
+    }
+
+    /// Original spellings recorded for the analyzed form of `term`, sorted.
+    /// Empty unless [`Self::enable_surface_forms`] has been called.
+    pub fn surface_forms(&self, term: &str) -> Vec<String> {
+        self.surface_forms
+            .as_ref()
+            .and_then(|forms| forms.get(&self.normalize_term(term)))
+            .map(|forms| forms.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn record_surface_forms(&mut self, doc_id: DocumentId) {
+        let Some(document) = self.document_store.get_document(doc_id) else {
+            return;
+        };
+        let mut texts = vec![
+            (FieldType::Title, &document.title),
+            (FieldType::Content, &document.content),
+        ];
+        texts.extend(
+            self.metadata_keys
+                .iter()
+                .filter(|key| !self.is_stored_only(doc_id, key))
+                .filter_map(|key| {
+                    let value = document.metadata.get(key)?;
+                    Some((FieldType::Metadata(key.clone()), value))
+                }),
+        );
+        texts.retain(|(field, _)| self.is_field_searchable(field));
+
+        let mut observed = Vec::new();
+        for (_, text) in texts {
+            let byte_offsets: Vec<usize> = text
+                .char_indices()
+                .map(|(byte, _)| byte)
+                .chain(std::iter::once(text.len()))
+                .collect();
+            for token in self.tokenizer.tokenize_iter(text) {
+                let surface =
+                    &text[byte_offsets[token.start_offset]..byte_offsets[token.end_offset]];
+                observed.push((token.text, surface.to_string()));
+            }
+        }
+
+        if let Some(forms) = &mut self.surface_forms {
+            for (term, surface) in observed {
+                forms.entry(term).or_default().insert(surface);
+            }
+        }
+    }
+
+    /// Vocabulary terms ending in `suffix`, or `None` if the suffix index is
+    /// not enabled.
+    pub(crate) fn terms_with_suffix(&self, suffix: &str) -> Option<Vec<String>> {
+        let suffix_index = self.suffix_index.as_ref()?;
+        let reversed: String = suffix.chars().rev().collect();
+        Some(
+            suffix_index
+                .range(reversed.clone()..)
+                .take_while(|term| term.starts_with(&reversed))
+                .map(|term| term.chars().rev().collect())
+                .collect(),
+        )
+    }
+
+    /// Tokenizes one field of a document; an unsearchable field yields no
+    /// terms.
+    fn extract_terms(&self, text: &str, field: FieldType) -> HashMap<String, Vec<TermPosition>> {
+        let mut terms: HashMap<String, Vec<TermPosition>> = HashMap::new();
+        if !self.is_field_searchable(&field) {
+            return terms;
+        }
+        for token in self.tokenizer.tokenize_iter(text) {
+            let term_position = TermPosition {
+                position: token.position,
+                field: field.clone(),
+                sentence: token.sentence,
+                original: token.original,
+                exact: token.exact,
+            };
+            terms.entry(token.text).or_default().push(term_position);
+        }
+
+        terms
+    }
+
+    /// Ids of the documents containing `query`, analyzed as by
+    /// [`Self::get_analyzed_posting_list`].
+    pub fn search(&self, query: &str) -> Vec<DocumentId> {
+        if let Some(posting_list) = self.get_analyzed_posting_list(query) {
+            posting_list.postings.iter().map(|p| p.doc_id).collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Changes whenever the indexed content changes, so callers can tell when
+    /// cached results are stale. Generations are unique within the process:
+    /// no other index, including a reloaded copy of this one, has the same.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub(crate) fn tokenizer(&self) -> &Tokenizer {
+        &self.tokenizer
+    }
+
+    /// Runs `text` through the index's tokenizer exactly as a document field
+    /// would be, for seeing why a query does or doesn't match.
+    pub fn analyze(&self, text: &str) -> Vec<Token> {
+        self.tokenizer.tokenize(text)
+    }
+
+    pub fn scoring_options(&self) -> &ScoringOptions {
+        &self.scoring
+    }
+
+    pub fn set_scoring_options(&mut self, options: ScoringOptions) {
+        self.scoring = options;
+    }
+
+    pub fn default_operator(&self) -> &BooleanOperator {
+        &self.default_operator
+    }
+
+    /// Sets how whitespace-separated clauses combine in the high-level
+    /// `query` entry points and [`crate::search::Searcher::search_response`].
+    pub fn set_default_operator(&mut self, operator: BooleanOperator) {
+        self.default_operator = operator;
+    }
+
+    pub fn max_phrase_terms(&self) -> Option<usize> {
+        self.max_phrase_terms
+    }
+
+    /// Makes the fallible query paths reject phrases longer than `max`
+    /// terms with [`crate::search::QueryError::PhraseTooLong`] instead of
+    /// verifying their positions.
+    pub fn set_max_phrase_terms(&mut self, max: Option<usize>) {
+        self.max_phrase_terms = max;
+    }
+
+    pub fn max_postings_per_term(&self) -> Option<usize> {
+        self.max_postings_per_term
+    }
+
+    /// Caps how many documents each term's posting list keeps, trading recall
+    /// on very common terms for bounded memory. When a list overflows, the
+    /// posting with the lowest term frequency is evicted and the term's
+    /// document frequency drops with it. Existing lists are pruned right away.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cap is zero.
+    pub fn set_max_postings_per_term(&mut self, cap: Option<usize>) {
+        assert!(cap != Some(0), "max postings per term must be at least 1");
+        self.max_postings_per_term = cap;
+        let Some(cap) = cap else {
+            return;
+        };
+
+        let evicted: usize = self
+            .index
+            .values_mut()
+            .map(|posting_list| posting_list.prune_to(cap))
+            .sum();
+        if evicted > 0 {
+            self.total_terms -= evicted;
+            self.generation = next_generation();
+        }
+    }
+
+    pub fn get_document(&self, id: DocumentId) -> Option<&Document> {
+        self.document_store.get_document(id)
+    }
+
+    /// Mutable access to a stored document. Edits are not searchable until
+    /// [`Self::refresh_document`] is called; the id must not be changed.
+    pub fn get_document_mut(&mut self, id: DocumentId) -> Option<&mut Document> {
+        self.document_store.get_document_mut(id)
+    }
+
+    pub fn total_documents(&self) -> usize {
+        self.document_store.total_documents()
+    }
+
+    /// Number of indexed tokens in the document across all fields.
+    pub fn document_length(&self, doc_id: DocumentId) -> usize {
+        self.document_lengths.get(&doc_id).copied().unwrap_or(0)
+    }
+
+    /// Number of indexed tokens in one field of the document.
+    pub fn field_length(&self, doc_id: DocumentId, field: &FieldType) -> usize {
+        self.field_lengths
+            .get(&doc_id)
+            .and_then(|lengths| lengths.iter().find(|(f, _)| f == field))
+            .map_or(0, |(_, length)| *length)
+    }
+
+    /// Mean length of `field` over all indexed documents, counting documents
+    /// where the field is empty as zero.
+    pub fn average_field_length(&self, field: &FieldType) -> f64 {
+        if self.document_lengths.is_empty() {
+            return 0.0;
+        }
+        let total: usize = self
+            .document_lengths
+            .keys()
+            .map(|&doc_id| self.field_length(doc_id, field))
+            .sum();
+        total as f64 / self.document_lengths.len() as f64
+    }
+
+    pub fn average_document_length(&self) -> f64 {
+        if self.document_lengths.is_empty() {
+            return 0.0;
+        }
+        let total: usize = self.document_lengths.values().sum();
+        total as f64 / self.document_lengths.len() as f64
+    }
+
+    pub fn total_unique_terms(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Every indexed term, sorted. Compare snapshots with
+    /// [`vocabulary_diff`].
+    pub fn vocabulary(&self) -> BTreeSet<String> {
+        self.index.keys().cloned().collect()
+    }
+
+    /// The `n` terms found in the fewest documents, with their document
+    /// frequencies, rarest first and alphabetically among ties. One-document
+    /// terms are often typos or OCR noise.
+    pub fn rarest_terms(&self, n: usize) -> Vec<(String, usize)> {
+        let mut terms: Vec<(String, usize)> = self
+            .index
+            .iter()
+            .map(|(term, posting_list)| (term.clone(), posting_list.document_frequency))
+            .collect();
+        terms.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        terms.truncate(n);
+        terms
+    }
+
+    /// Terms found in more than `threshold_ratio` (a share in `0.0..=1.0`) of
+    /// all documents, sorted. In a focused corpus these carry as little
+    /// signal as the tokenizer's fixed stop words; searches can ignore them
+    /// with [`ScoringOptions::auto_stop_word_ratio`].
+    pub fn auto_stop_words(&self, threshold_ratio: f64) -> Vec<String> {
+        let mut terms: Vec<String> = self
+            .index
+            .keys()
+            .filter(|term| self.exceeds_document_ratio(term, threshold_ratio))
+            .cloned()
+            .collect();
+        terms.sort();
+        terms
+    }
+
+    /// Whether the indexed `term` occurs in more than `ratio` of documents.
+    pub(crate) fn exceeds_document_ratio(&self, term: &str, ratio: f64) -> bool {
+        let total = self.document_store.total_documents();
+        total > 0
+            && self.index.get(term).is_some_and(|posting_list| {
+                posting_list.document_frequency as f64 / total as f64 > ratio
+            })
+    }
+
+    /// The indexed term at `position` of `field` in `doc_id`, found by
+    /// scanning every posting list. Meant for debugging positional queries;
+    /// positions of dropped words hold nothing.
+    pub fn term_at(&self, doc_id: DocumentId, position: usize, field: &FieldType) -> Option<&str> {
+        self.index.iter().find_map(|(term, posting_list)| {
+            posting_list
+                .postings
+                .iter()
+                .find(|p| p.doc_id == doc_id)?
+                .positions
+                .iter()
+                .any(|p| p.position == position && &p.field == field)
+                .then_some(term.as_str())
+        })
+    }
+
+    /// Looks up `term` lowercased. Use [`Self::get_analyzed_posting_list`]
+    /// for query words, which may be indexed under a stem.
+    pub fn get_posting_list(&self, term: &str) -> Option<&PostingList> {
+        self.index.get(&term.to_lowercase())
+    }
+
+    /// Looks up a query term after running it through the index's tokenizer,
+    /// so it matches the form the term was indexed under.
+    pub fn get_analyzed_posting_list(&self, term: &str) -> Option<&PostingList> {
+        self.index.get(&self.normalize_term(term))
+    }
+
+    /// Looks up `term` exactly as it appears in the vocabulary, without
+    /// analysis.
+    pub fn get_indexed_posting_list(&self, term: &str) -> Option<&PostingList> {
+        self.index.get(term)
+    }
+
+    /// Ids of the documents containing a query term, in ascending order.
+    pub fn ordered_postings(&self, term: &str) -> Vec<DocumentId> {
+        let mut doc_ids = self.search(term);
+        doc_ids.sort_unstable();
+        doc_ids
+    }
+
+    /// Postings for a query term, analyzed like
+    /// [`Self::get_analyzed_posting_list`].
+    pub fn postings_for(&self, term: &str) -> impl Iterator<Item = PostingView<'_>> {
+        self.get_analyzed_posting_list(term)
+            .into_iter()
+            .flat_map(|list| list.postings.iter().map(|entry| PostingView { entry }))
+    }
+
+    /// Analyzes a single query term. Input that doesn't reduce to exactly one
+    /// token (stop words, multi-word strings) is only lowercased.
+    pub(crate) fn normalize_term(&self, term: &str) -> String {
+        let mut tokens = self.tokenizer.tokenize(term);
+        if tokens.len() == 1 {
+            tokens.remove(0).text
+        } else if self.tokenizer.lowercase() {
+            term.to_lowercase()
+        } else {
+            term.to_string()
+        }
+    }
+
+    pub fn get_term_frequency(&self, term: &str, doc_id: DocumentId) -> usize {
+        if let Some(posting_list) = self.get_analyzed_posting_list(term) {
+            posting_list
+                .postings
+                .iter()
+                .find(|p| p.doc_id == doc_id)
+                .map(|p| p.term_frequency)
+                .unwrap_or(0)
+        } else {
+            0
+        }
+    }
+
+    pub fn get_document_frequency(&self, term: &str) -> usize {
+        self.get_analyzed_posting_list(term)
+            .map(|p| p.document_frequency)
+            .unwrap_or(0)
+    }
+
+    pub fn document_term_vector(&self, doc_id: DocumentId) -> HashMap<String, usize> {
+        self.index
+            .iter()
+            .filter_map(|(term, posting_list)| {
+                posting_list
+                    .postings
+                    .iter()
+                    .find(|p| p.doc_id == doc_id)
+                    .map(|p| (term.clone(), p.term_frequency))
+            })
+            .collect()
+    }
+
+    /// Whether `doc_id` contains every one of `terms`, analyzed like query
+    /// terms, without scoring. Stops at the first missing term; a term the
+    /// tokenizer discards (such as a stop word) is never contained.
+    pub fn document_contains_all(&self, doc_id: DocumentId, terms: &[&str]) -> bool {
+        terms.iter().all(|term| {
+            self.index
+                .get(&self.normalize_term(term))
+                .is_some_and(|list| list.postings.iter().any(|p| p.doc_id == doc_id))
+        })
+    }
+
+    /// Jaccard similarity of the sets of terms indexed for `a` and `b`, from
+    /// 0.0 (disjoint) to 1.0 (same terms). Returns `NaN` if either id is not
+    /// in the index, and 0.0 if either document has no indexed terms.
+    pub fn document_similarity(&self, a: DocumentId, b: DocumentId) -> f64 {
+        if self.get_document(a).is_none() || self.get_document(b).is_none() {
+            return f64::NAN;
+        }
+
+        let mut shared = 0;
+        let mut union = 0;
+        for posting_list in self.index.values() {
+            let in_a = posting_list.postings.iter().any(|p| p.doc_id == a);
+            let in_b = posting_list.postings.iter().any(|p| p.doc_id == b);
+            shared += (in_a && in_b) as usize;
+            union += (in_a || in_b) as usize;
+        }
+
+        if union == 0 {
+            0.0
+        } else {
+            shared as f64 / union as f64
+        }
+    }
+
+    /// Checks the internal bookkeeping of the index, returning every
+    /// violation found. Terms are checked in sorted order.
+    pub fn validate(&self) -> Result<(), Vec<ConsistencyError>> {
+        let mut errors = Vec::new();
+
+        let mut terms: Vec<&PostingList> = self.index.values().collect();
+        terms.sort_by(|a, b| a.term.cmp(&b.term));
+        for posting_list in terms {
+            let term = &posting_list.term;
+
+            if posting_list.postings.is_empty() {
+                errors.push(ConsistencyError::EmptyPostingList { term: term.clone() });
+            }
+
+            if posting_list.document_frequency != posting_list.postings.len() {
+                errors.push(ConsistencyError::DocumentFrequencyMismatch {
+                    term: term.clone(),
+                    recorded: posting_list.document_frequency,
+                    actual: posting_list.postings.len(),
+                });
+            }
+
+            for posting in &posting_list.postings {
+                if self.get_document(posting.doc_id).is_none() {
+                    errors.push(ConsistencyError::OrphanedPosting {
+                        term: term.clone(),
+                        doc_id: posting.doc_id,
+                    });
+                }
+            }
+        }
+
+        let actual_terms: usize = self.index.values().map(|p| p.postings.len()).sum();
+        if self.total_terms != actual_terms {
+            errors.push(ConsistencyError::TotalTermsMismatch {
+                recorded: self.total_terms,
+                actual: actual_terms,
+            });
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Hashes the vocabulary, postings, and stored documents in a canonical
+    /// order, so indexes built from the same documents in the same order
+    /// produce the same digest regardless of `HashMap` iteration order. The
+    /// input is an explicit little-endian byte encoding rather than std
+    /// `Hash` impls, so the digest doesn't depend on the Rust release or
+    /// platform.
+    pub fn content_digest(&self) -> u64 {
+        let mut hasher = Fnv1aHasher::default();
+
+        let mut terms: Vec<&PostingList> = self.index.values().collect();
+        terms.sort_by(|a, b| a.term.cmp(&b.term));
+        hasher.write_len(terms.len());
+        for posting_list in terms {
+            hasher.write_str(&posting_list.term);
+            hasher.write_len(posting_list.document_frequency);
+
+            let mut postings: Vec<&PostingEntry> = posting_list.postings.iter().collect();
+            postings.sort_by_key(|p| p.doc_id);
+            hasher.write_len(postings.len());
+            for posting in postings {
+                hasher.write_len(posting.doc_id);
+                hasher.write_len(posting.term_frequency);
+                hasher.write_len(posting.positions.len());
+                for position in &posting.positions {
+                    match &position.field {
+                        FieldType::Title => hasher.write(&[0]),
+                        FieldType::Content => hasher.write(&[1]),
+                        FieldType::Metadata(key) => {
+                            hasher.write(&[2]);
+                            hasher.write_str(key);
+                        }
+                    }
+                    hasher.write_len(position.position);
+                }
+            }
+        }
+
+        let mut documents: Vec<&Document> = self.document_store.all_documents().collect();
+        documents.sort_by_key(|d| d.id);
+        hasher.write_len(documents.len());
+        for document in documents {
+            hasher.write_len(document.id);
+            hasher.write_str(&document.title);
+            hasher.write_str(&document.content);
+
+            let mut metadata: Vec<(&String, &String)> = document.metadata.iter().collect();
+            metadata.sort();
+            hasher.write_len(metadata.len());
+            for (key, value) in metadata {
+                hasher.write_str(key);
+                hasher.write_str(value);
+            }
+        }
+
+        hasher.finish()
+    }
+
+    pub fn document_ids(&self) -> impl Iterator<Item = DocumentId> + '_ {
+        self.document_store.all_documents().map(|doc| doc.id)
+    }
+
+    pub fn documents_without(&self, term: &str) -> HashSet<DocumentId> {
+        let containing: HashSet<DocumentId> = self.search(term).into_iter().collect();
+
+        self.document_ids()
+            .filter(|id| !containing.contains(id))
+            .collect()
+    }
+
+    /// Ids of the documents not containing a query term, in ascending order:
+    /// every document when no document contains it.
+    pub fn documents_without_term(&self, term: &str) -> Vec<DocumentId> {
+        let mut doc_ids: Vec<DocumentId> = self.documents_without(term).into_iter().collect();
+        doc_ids.sort_unstable();
+        doc_ids
+    }
+
+    /// Ids of the documents with no indexed terms in `field`, such as an
+    /// empty or all-stop-word content, in ascending order.
+    pub fn documents_without_field(&self, field: &FieldType) -> Vec<DocumentId> {
+        let mut doc_ids: Vec<DocumentId> = self
+            .document_ids()
+            .filter(|&id| self.field_length(id, field) == 0)
+            .collect();
+        doc_ids.sort_unstable();
+        doc_ids
+    }
+}
+
+/// Terms `(added, removed)` going from vocabulary `a` to vocabulary `b`.
+pub fn vocabulary_diff(
+    a: &BTreeSet<String>,
+    b: &BTreeSet<String>,
+) -> (BTreeSet<String>, BTreeSet<String>) {
+    (
+        b.difference(a).cloned().collect(),
+        a.difference(b).cloned().collect(),
+    )
+}
+
+/// 64-bit FNV-1a, used instead of `DefaultHasher`, whose algorithm may change
+/// between Rust releases. Feed it explicit bytes, e.g. via [`Self::write_str`],
+/// for a digest that is stable too.
+pub(crate) struct Fnv1aHasher(u64);
+
+impl Fnv1aHasher {
+    fn write_len(&mut self, n: usize) {
+        self.write(&(n as u64).to_le_bytes());
+    }
+
+    /// Writes `s` prefixed with its byte length, so adjacent strings can't
+    /// run together.
+    fn write_str(&mut self, s: &str) {
+        self.write_len(s.len());
+        self.write(s.as_bytes());
+    }
+}
+
+impl Default for Fnv1aHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1aHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+impl Default for InvertedIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Configures an [`InvertedIndex`] in one place before any document is
+/// added, so queries are analyzed and scored exactly as configured here.
+#[derive(Debug, Clone, Default)]
+#[must_use = "the index is only created by calling `build`"]
+pub struct IndexBuilder {
+    tokenizer: Tokenizer,
+    scoring: ScoringOptions,
+    default_operator: BooleanOperator,
+    field_boosts: Vec<(FieldType, f64)>,
+}
+
+impl IndexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Analyzer used both when indexing documents and when parsing queries.
+    pub fn tokenizer(mut self, tokenizer: Tokenizer) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    pub fn scoring(mut self, scoring: ScoringOptions) -> Self {
+        self.scoring = scoring;
+        self
+    }
+
+    pub fn default_operator(mut self, operator: BooleanOperator) -> Self {
+        self.default_operator = operator;
+        self
+    }
+
+    /// Adds to [`ScoringOptions::field_boosts`], whether set before or after
+    /// [`Self::scoring`].
+    pub fn field_boost(mut self, field: FieldType, boost: f64) -> Self {
+        self.field_boosts.push((field, boost));
+        self
+    }
+
+    pub fn build(self) -> InvertedIndex {
+        let mut index = InvertedIndex::with_tokenizer(self.tokenizer);
+        let mut scoring = self.scoring;
+        for (field, boost) in self.field_boosts {
+            scoring.field_boosts.retain(|(f, _)| *f != field);
+            scoring.field_boosts.push((field, boost));
+        }
+        index.set_scoring_options(scoring);
+        index.set_default_operator(self.default_operator);
+        index
+    }
+}
+
+/// Per-field settings for [`DocumentBuilder::field_with_options`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldOptions {
+    /// Tokenize the field into postings. When `false` the text is only
+    /// stored, e.g. a raw HTML body kept for display.
+    pub indexed: bool,
+}
+
+impl Default for FieldOptions {
+    fn default() -> Self {
+        Self { indexed: true }
+    }
+}
+
+/// Collects a document's parts for [`InvertedIndex::add`].
+#[must_use = "the document is only added by calling `insert`"]
+pub struct DocumentBuilder<'a> {
+    index: &'a mut InvertedIndex,
+    title: String,
+    content: String,
+    metadata: HashMap<String, String>,
+    fields: Vec<String>,
+    stored_only: Vec<String>,
+}
+
+impl DocumentBuilder<'_> {
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn content(mut self, content: impl Into<String>) -> Self {
+        self.content = content.into();
+        self
+    }
+
+    /// Stores `value` under `key` without making it searchable.
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Stores `text` as metadata under `name` and indexes it as
+    /// [`FieldType::Metadata`]. Like [`InvertedIndex::index_metadata_key`],
+    /// this makes `name` searchable for every document.
+    pub fn field(self, name: impl Into<String>, text: impl Into<String>) -> Self {
+        self.field_with_options(name, text, FieldOptions::default())
+    }
+
+    /// Like [`Self::field`], but a field that isn't `indexed` is only stored:
+    /// its text stays out of this document's postings, even where other
+    /// documents index `name`.
+    pub fn field_with_options(
+        mut self,
+        name: impl Into<String>,
+        text: impl Into<String>,
+        options: FieldOptions,
+    ) -> Self {
+        let name = name.into();
+        self.metadata.insert(name.clone(), text.into());
+        if options.indexed {
+            self.fields.push(name);
+        } else {
+            self.stored_only.push(name);
+        }
+        self
+    }
+
+    pub fn insert(self) -> DocumentId {
+        for name in &self.fields {
+            self.index.index_metadata_key(name);
+        }
+        self.index.insert_document_with_stored_only(
+            self.title,
+            self.content,
+            self.metadata,
+            self.stored_only,
+        )
+    }
+}
+
+impl FromIterator<(String, String)> for InvertedIndex {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(documents: I) -> Self {
+        let mut index = Self::new();
+        index.add_documents(documents);
+        index
+    }
+}
+
+impl Extend<(String, String)> for InvertedIndex {
+    fn extend<I: IntoIterator<Item = (String, String)>>(&mut self, documents: I) {
+        self.add_documents(documents);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{PorterStemmer, StemAlgorithm};
+
+    #[test]
+    fn test_term_position_creation() {
+        let pos = TermPosition {
+            position: 5,
+            field: FieldType::Title,
+            sentence: 0,
+            original: None,
+            exact: None,
+        };
+
+        assert_eq!(pos.position, 5);
+        assert_eq!(pos.field, FieldType::Title);
+    }
+
+    #[test]
+    fn test_add_documents_with_progress() {
+        let mut index = InvertedIndex::new();
+        let documents: Vec<(String, String)> = (0..4)
+            .map(|i| (format!("Doc {}", i), format!("content number {}", i)))
+            .collect();
+        let mut progress = Vec::new();
+
+        let ids = index.add_documents_with_progress(documents, |done, total| {
+            progress.push((done, total));
+        });
+
+        assert_eq!(ids.len(), 4);
+        assert_eq!(progress, vec![(1, 4), (2, 4), (3, 4), (4, 4)]);
+        assert_eq!(index.total_documents(), 4);
+    }
+
+    #[test]
+    fn test_refresh_document_after_edit() {
+        let mut index = InvertedIndex::new();
+        index.enable_suffix_index();
+        let edited = index.add_document("Pets".to_string(), "cats purr loudly".to_string());
+        let other = index.add_document("Farm".to_string(), "cats chase mice".to_string());
+
+        let doc = index.get_document_mut(edited).unwrap();
+        doc.content = "dogs bark loudly".to_string();
+        // Not searchable until refreshed
+        assert_eq!(index.search("dogs").len(), 0);
+
+        index.refresh_document(edited).unwrap();
+
+        assert_eq!(index.search("dogs"), vec![edited]);
+        assert_eq!(index.search("cats"), vec![other]);
+        assert!(index.search("purr").is_empty());
+        assert!(!index.vocabulary().contains("purr"));
+        assert_eq!(
+            index.get_posting_list("cats").unwrap().document_frequency,
+            1
+        );
+        assert!(index.validate().is_ok());
+        assert_eq!(
+            index.refresh_document(99),
+            Err(UpdateError::DocumentNotFound(99))
+        );
+    }
+
+    #[test]
+    fn test_remove_by_metadata() {
+        let mut index = InvertedIndex::new();
+        let uid = |uid: &str| HashMap::from([("uid".to_string(), uid.to_string())]);
+        let kept = index.add_document_with_metadata(
+            "Rust".to_string(),
+            "ownership rules".to_string(),
+            uid("a-1"),
+        );
+        let removed = index.add_document_with_metadata(
+            "Rust".to_string(),
+            "borrowing rules".to_string(),
+            uid("b-2"),
+        );
+        let generation = index.generation();
+
+        assert_eq!(index.remove_by_metadata("uid", "b-2"), 1);
+
+        assert!(index.get_document(removed).is_none());
+        assert!(index.get_document(kept).is_some());
+        assert_eq!(index.total_documents(), 1);
+        assert_eq!(
+            index.get_posting_list("rules").unwrap().document_frequency,
+            1
+        );
+        assert!(index.search("borrowing").is_empty());
+        assert!(index.generation() > generation);
+        assert!(index.validate().is_ok());
+        assert_eq!(index.remove_by_metadata("uid", "b-2"), 0);
+        // New documents never reuse the removed id
+        let added = index.add_document("New".to_string(), "text".to_string());
+        assert!(added > removed);
+
+        // Every document sharing the value goes at once
+        for _ in 0..3 {
+            index.add_document_with_metadata(
+                "Draft".to_string(),
+                "scratch rules".to_string(),
+                uid("tmp"),
+            );
+        }
+        assert_eq!(index.remove_by_metadata("uid", "tmp"), 3);
+        assert!(index.get_posting_list("scratch").is_none());
+        assert_eq!(
+            index.get_posting_list("rules").unwrap().document_frequency,
+            1
+        );
+        assert_eq!(index.total_documents(), 2);
+        assert!(index.validate().is_ok());
+    }
+
+    #[test]
+    fn test_possessive_indexed_without_stray_s() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_min_token_length(1);
+        let mut index = InvertedIndex::with_tokenizer(tokenizer);
+        let doc_id = index.add_document("Notes".to_string(), "Rust's API".to_string());
+
+        assert_eq!(index.search("rust"), vec![doc_id]);
+        assert_eq!(index.search("api"), vec![doc_id]);
+        assert_eq!(index.search("Rust's"), vec![doc_id]);
+        assert!(index.get_posting_list("s").is_none());
+    }
+
+    #[test]
+    fn test_reindex_preserves_document_ids() {
+        let mut index = InvertedIndex::new();
+        let walking = index.add_document("Walking".to_string(), "walking the dog".to_string());
+        let walked = index.add_document("Walked".to_string(), "she walked home".to_string());
+        index.add_document("Other".to_string(), "nothing related".to_string());
+
+        let mut before = index.search("walking");
+        before.extend(index.search("walked"));
+        assert_eq!(before, vec![walking, walked]);
+        let generation = index.generation();
+
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_stem_algorithm(StemAlgorithm::Porter);
+        index.set_tokenizer(tokenizer);
+        index.reindex();
+
+        let mut after = index.search("walk");
+        after.sort();
+        assert_eq!(after, before);
+        assert_eq!(index.get_document(walking).unwrap().title, "Walking");
+        assert_eq!(index.get_document(walked).unwrap().title, "Walked");
+        assert_eq!(index.total_documents(), 3);
+        assert!(index.generation() > generation);
+        assert!(index.validate().is_ok());
+    }
+
+    #[test]
+    fn test_document_builder_with_metadata_and_fields() {
+        let mut index = InvertedIndex::new();
+        let doc_id = index
+            .add()
+            .title("Rust Book")
+            .content("ownership and borrowing")
+            .metadata("isbn", "978-1718503106")
+            .field("author", "Klabnik Nichols")
+            .insert();
+
+        let doc = index.get_document(doc_id).unwrap();
+        assert_eq!(doc.title, "Rust Book");
+        assert_eq!(doc.metadata.get("isbn").unwrap(), "978-1718503106");
+        assert_eq!(doc.metadata.get("author").unwrap(), "Klabnik Nichols");
+
+        assert_eq!(index.search("klabnik"), vec![doc_id]);
+        assert!(index.search("1718503106").is_empty());
+        assert_eq!(index.search("ownership"), vec![doc_id]);
+        assert!(index.validate().is_ok());
+    }
+
+    #[test]
+    fn test_index_builder_configures_indexing_and_querying() {
+        use crate::search::{ScoringModel, Searcher};
+
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_stem_algorithm(StemAlgorithm::Porter);
+        let mut index = InvertedIndex::builder()
+            .tokenizer(tokenizer)
+            .scoring(ScoringOptions {
+                model: ScoringModel::bm25(),
+                ..Default::default()
+            })
+            .default_operator(BooleanOperator::Or)
+            .field_boost(FieldType::Title, 3.0)
+            .build();
+        let titled = index.add_document("Running".to_string(), "a guide".to_string());
+        let body = index.add_document("Guide".to_string(), "runs and running".to_string());
+        index.add_document("Other".to_string(), "swimming".to_string());
+
+        // Index-time stemming
+        assert!(index.index.contains_key("run"));
+        assert!(!index.index.contains_key("running"));
+        // Query-time stemming, scoring and operator
+        assert!(matches!(
+            index.scoring_options().model,
+            ScoringModel::Bm25 { .. }
+        ));
+        assert_eq!(index.default_operator(), &BooleanOperator::Or);
+        let results = Searcher::new(&index).search_response("runs swims").results;
+        assert_eq!(results.len(), 3);
+        let rank = |id| results.iter().position(|r| r.doc_id == id).unwrap();
+        // The boosted single title match beats two body matches
+        assert!(rank(titled) < rank(body));
+        index.set_scoring_options(ScoringOptions {
+            model: ScoringModel::bm25(),
+            ..Default::default()
+        });
+        let unboosted = Searcher::new(&index).search_response("runs").results;
+        assert_eq!(unboosted[0].doc_id, body);
+    }
+
+    #[test]
+    fn test_surface_forms_capture_original_casing() {
+        let mut index = InvertedIndex::new();
+        index.add_document("Rust".to_string(), "rust is fast".to_string());
+        assert!(index.surface_forms("rust").is_empty());
 
-        for (term, positions) in content_terms {
-            term_positions
-                .entry(term)
-                .or_insert_with(Vec::new)
-                .extend(positions);
-        }
+        index.enable_surface_forms();
+        index.add_document("Why RUST".to_string(), "Rust's borrow checker".to_string());
 
-        for (term, positions) in term_positions {
-            let posting_list = self
-                .index
-                .entry(term.clone())
-                .or_insert_with(|| PostingList::new(term));
-            posting_list.add_posting(doc_id, positions);
-            self.total_terms += 1;
-        }
+        assert_eq!(
+            index.surface_forms("rust"),
+            vec!["RUST", "Rust", "Rust's", "rust"]
+        );
+        assert_eq!(index.surface_forms("RUST"), index.surface_forms("rust"));
+        assert_eq!(index.surface_forms("borrow"), vec!["borrow"]);
+        assert!(index.surface_forms("missing").is_empty());
 
-        doc_id
+        index.reindex();
+        assert_eq!(index.surface_forms("rust").len(), 4);
     }
 
-    fn extract_terms(&self, text: &str, field: FieldType) -> HashMap<String, Vec<TermPosition>> {
-        let mut terms = HashMap::new();
-        let tokens = self.tokenizer.tokenize(text);
+    #[test]
+    fn test_non_indexed_field_is_only_stored() {
+        let mut index = InvertedIndex::new();
+        let doc_id = index
+            .add()
+            .title("Release Notes")
+            .field_with_options(
+                "html",
+                "<div class=\"banner\">markup</div>",
+                FieldOptions { indexed: false },
+            )
+            .field("plaintext", "compiler improvements")
+            .insert();
+
+        assert!(index.search("banner").is_empty());
+        assert!(index.search("markup").is_empty());
+        assert_eq!(index.search("compiler"), vec![doc_id]);
+        assert_eq!(
+            index
+                .get_document(doc_id)
+                .unwrap()
+                .metadata
+                .get("html")
+                .unwrap(),
+            "<div class=\"banner\">markup</div>"
+        );
 
-        for token in tokens {
-            let term_position = TermPosition {
-                position: token.position,
-                field: field.clone(),
-            };
-            terms
-                .entry(token.text)
-                .or_insert_with(Vec::new)
-                .push(term_position);
-        }
+        // Another document indexing the same key keeps it searchable there
+        // only, through reindexing too
+        let later = index
+            .add()
+            .title("Changelog")
+            .field("html", "<p>banner again</p>")
+            .insert();
+        assert_eq!(index.search("banner"), vec![later]);
+        index.reindex();
+        assert_eq!(index.search("banner"), vec![later]);
+        assert!(index.search("markup").is_empty());
+    }
 
-        terms
+    #[test]
+    fn test_stored_only_field_leaves_other_documents_searchable() {
+        let mut index = InvertedIndex::new();
+        let first = index.add().title("First").field("notes", "alpha").insert();
+        index
+            .add()
+            .title("Second")
+            .field_with_options("notes", "beta", FieldOptions { indexed: false })
+            .insert();
+        let third = index.add().title("Third").field("notes", "gamma").insert();
+
+        assert_eq!(index.search("alpha"), vec![first]);
+        assert!(index.search("beta").is_empty());
+        assert_eq!(index.search("gamma"), vec![third]);
+        assert!(index.is_field_searchable(&FieldType::Metadata("notes".to_string())));
     }
 
-    pub fn search(&self, query: &str) -> Vec<DocumentId> {
-        let query_term = query.to_lowercase();
+    #[test]
+    fn test_add_document_dedup_flags_near_duplicates() {
+        let mut index = InvertedIndex::new();
+        index.add_document(
+            "Unrelated".to_string(),
+            "gardening tips for growing tomatoes in small spaces".to_string(),
+        );
+        let article = "the rust compiler team announced faster incremental builds \
+                       and improved diagnostics for async code in the latest release";
 
-        if let Some(posting_list) = self.index.get(&query_term) {
-            posting_list.postings.iter().map(|p| p.doc_id).collect()
-        } else {
-            Vec::new()
+        let first = index.add_document_dedup("Original".to_string(), article.to_string(), 0.8);
+        let DedupResult::Indexed(first_id) = first else {
+            panic!("expected the first copy to be indexed, got {:?}", first);
+        };
+
+        let copy = article.replace("latest release", "latest release today");
+        match index.add_document_dedup("Copy".to_string(), copy, 0.8) {
+            DedupResult::Duplicate {
+                existing,
+                similarity,
+            } => {
+                assert_eq!(existing, first_id);
+                assert!(similarity >= 0.8);
+            }
+            other => panic!("expected a duplicate, got {:?}", other),
         }
-    }
+        assert_eq!(index.total_documents(), 2);
 
-    pub fn get_document(&self, id: DocumentId) -> Option<&Document> {
-        self.document_store.get_document(id)
+        let different = index.add_document_dedup(
+            "Different".to_string(),
+            "python data science libraries compared for beginners".to_string(),
+            0.8,
+        );
+        assert!(matches!(different, DedupResult::Indexed(_)));
     }
 
-    pub fn total_documents(&self) -> usize {
-        self.document_store.total_documents()
-    }
+    #[test]
+    fn test_collect_into_index() {
+        let docs = vec![
+            ("Rust".to_string(), "systems programming".to_string()),
+            (
+                "Python".to_string(),
+                "scripting and programming".to_string(),
+            ),
+        ];
 
-    pub fn total_unique_terms(&self) -> usize {
-        self.index.len()
-    }
+        let mut index: InvertedIndex = docs.into_iter().collect();
 
-    pub fn get_posting_list(&self, term: &str) -> Option<&PostingList> {
-        self.index.get(&term.to_lowercase())
+        assert_eq!(index.total_documents(), 2);
+        assert_eq!(index.search("programming"), vec![0, 1]);
+        assert_eq!(index.search("scripting"), vec![1]);
+
+        index.extend([("Go".to_string(), "concurrent programming".to_string())]);
+        assert_eq!(index.search("programming"), vec![0, 1, 2]);
     }
 
-    pub fn get_term_frequency(&self, term: &str, doc_id: DocumentId) -> usize {
-        if let Some(posting_list) = self.get_posting_list(term) {
-            posting_list
-                .postings
-                .iter()
-                .find(|p| p.doc_id == doc_id)
-                .map(|p| p.term_frequency)
-                .unwrap_or(0)
-        } else {
-            0
+    #[test]
+    fn test_ordered_postings_ascending() {
+        let mut index = InvertedIndex::new();
+        for i in 0..5 {
+            let metadata = HashMap::from([("tag".to_string(), "shared".to_string())]);
+            index.insert_document(format!("Doc {}", i), "content".to_string(), metadata);
         }
-    }
+        // Retroactive metadata indexing walks the store in hash order.
+        index.index_metadata_key("tag");
 
-    pub fn get_document_frequency(&self, term: &str) -> usize {
-        self.get_posting_list(term)
-            .map(|p| p.document_frequency)
-            .unwrap_or(0)
-    }
-}
+        let ordered = index.ordered_postings("shared");
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert_eq!(ordered, vec![0, 1, 2, 3, 4]);
+        let mut searched = index.search("shared");
+        searched.sort();
+        assert_eq!(ordered, searched);
+        assert!(index.ordered_postings("missing").is_empty());
+    }
 
     #[test]
-    fn test_term_position_creation() {
-        let pos = TermPosition {
-            position: 5,
-            field: FieldType::Title,
-        };
-
-        assert_eq!(pos.position, 5);
-        assert_eq!(pos.field, FieldType::Title);
+    fn test_postings_for_matches_posting_list() {
+        let mut index = InvertedIndex::new();
+        index.add_document("Rust".to_string(), "rust is fast, rust is safe".to_string());
+        index.add_document("Go".to_string(), "go is simple".to_string());
+        index.add_document("Both".to_string(), "rust and go".to_string());
+
+        let list = index.get_posting_list("Rust").unwrap();
+        let views: Vec<PostingView> = index.postings_for("Rust").collect();
+
+        assert_eq!(views.len(), list.postings.len());
+        for (view, entry) in views.iter().zip(&list.postings) {
+            assert_eq!(view.doc_id(), entry.doc_id);
+            assert_eq!(view.term_frequency(), entry.term_frequency);
+            let positions: Vec<(usize, FieldType)> = view
+                .positions()
+                .map(|p| (p.position, p.field.clone()))
+                .collect();
+            let expected: Vec<(usize, FieldType)> = entry
+                .positions
+                .iter()
+                .map(|p| (p.position, p.field.clone()))
+                .collect();
+            assert_eq!(positions, expected);
+        }
+        assert_eq!(index.postings_for("missing").count(), 0);
     }
 
     #[test]
@@ -197,10 +1848,16 @@ mod tests {
             TermPosition {
                 position: 0,
                 field: FieldType::Title,
+                sentence: 0,
+                original: None,
+                exact: None,
             },
             TermPosition {
                 position: 3,
                 field: FieldType::Content,
+                sentence: 0,
+                original: None,
+                exact: None,
             },
         ];
 
@@ -260,6 +1917,232 @@ mod tests {
         assert!(index.get_posting_list("Test").is_some()); // Normalized to "test"
     }
 
+    #[test]
+    fn test_single_cjk_character_is_indexed() {
+        let mut index = InvertedIndex::new();
+        let doc = index.add_document("猫".to_string(), "x 猫 sat".to_string());
+
+        assert_eq!(index.search("猫"), vec![doc]);
+        assert!(index.search("x").is_empty());
+        assert!(!index.vocabulary().contains("x"));
+    }
+
+    #[test]
+    fn test_analyze_matches_indexed_terms() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_stem_algorithm(StemAlgorithm::Porter);
+        tokenizer.set_folding(true);
+        let mut index = InvertedIndex::with_tokenizer(tokenizer);
+        let text = "The Cafés were Running, and running again";
+        let doc = index.add_document(String::new(), text.to_string());
+
+        let tokens = index.analyze(text);
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["cafe", "were", "run", "run", "again"]);
+
+        let analyzed: BTreeSet<String> = tokens.iter().map(|t| t.text.clone()).collect();
+        assert_eq!(analyzed, index.vocabulary());
+        for token in &tokens {
+            let posting = &index.index[&token.text].postings[0];
+            assert_eq!(posting.doc_id, doc);
+            assert!(
+                posting
+                    .positions
+                    .iter()
+                    .any(|p| p.position == token.position)
+            );
+        }
+    }
+
+    #[test]
+    fn test_documents_without_term_and_field() {
+        let mut index = InvertedIndex::new();
+        let rust = index.add_document("Rust".to_string(), "rust compiler".to_string());
+        let python = index.add_document("Python".to_string(), "python".to_string());
+        let empty = index.add_document("Rust notes".to_string(), "the of".to_string());
+
+        assert_eq!(index.documents_without_term("rust"), vec![python]);
+        assert_eq!(
+            index.documents_without_term("Compiler"),
+            vec![python, empty]
+        );
+        assert_eq!(
+            index.documents_without_term("haskell"),
+            vec![rust, python, empty]
+        );
+        assert_eq!(
+            index.documents_without_field(&FieldType::Content),
+            vec![empty]
+        );
+        assert!(index.documents_without_field(&FieldType::Title).is_empty());
+    }
+
+    #[test]
+    fn test_unsearchable_field_is_stored_but_not_matched() {
+        let mut index = InvertedIndex::new();
+        let notes = FieldType::Metadata("notes".to_string());
+        index.set_field_searchable(notes.clone(), false);
+        let doc = index
+            .add()
+            .title("Release plan")
+            .content("ship the parser")
+            .field("notes", "confidential budget")
+            .insert();
+
+        assert!(index.search("confidential").is_empty());
+        assert!(!index.vocabulary().contains("budget"));
+        assert_eq!(index.search("parser"), vec![doc]);
+        assert_eq!(
+            index.get_document(doc).unwrap().metadata["notes"],
+            "confidential budget"
+        );
+        assert_eq!(index.validate(), Ok(()));
+
+        // Built-in fields can be excluded too, and turning a field back on
+        // indexes what was stored
+        index.set_field_searchable(FieldType::Title, false);
+        assert!(index.search("release").is_empty());
+        index.set_field_searchable(notes, true);
+        assert_eq!(index.search("confidential"), vec![doc]);
+        assert_eq!(index.search("parser"), vec![doc]);
+    }
+
+    #[test]
+    fn test_term_at_reconstructs_indexed_order() {
+        let mut index = InvertedIndex::new();
+        let doc_id = index.add_document(
+            "Guide".to_string(),
+            "Ownership makes memory safety possible".to_string(),
+        );
+
+        let content: Vec<Option<&str>> = (0..5)
+            .map(|position| index.term_at(doc_id, position, &FieldType::Content))
+            .collect();
+        let expected = index.analyze("Ownership makes memory safety possible");
+        assert_eq!(
+            content,
+            expected
+                .iter()
+                .map(|t| Some(t.text.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(index.term_at(doc_id, 0, &FieldType::Title), Some("guide"));
+        assert_eq!(index.term_at(doc_id, 5, &FieldType::Content), None);
+        assert_eq!(index.term_at(doc_id + 1, 0, &FieldType::Content), None);
+    }
+
+    #[test]
+    fn test_auto_stop_words() {
+        let mut index = InvertedIndex::new();
+        index.add_document("Visit".to_string(), "patient reported headache".to_string());
+        index.add_document("Visit".to_string(), "patient reported fever".to_string());
+        index.add_document("Visit".to_string(), "patient stable".to_string());
+
+        assert_eq!(index.auto_stop_words(0.9), vec!["patient", "visit"]);
+        assert_eq!(
+            index.auto_stop_words(0.5),
+            vec!["patient", "reported", "visit"]
+        );
+        assert!(index.auto_stop_words(1.0).is_empty());
+        assert!(InvertedIndex::new().auto_stop_words(0.0).is_empty());
+    }
+
+    #[test]
+    fn test_posting_lookups_analyzed_or_exact() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_stem_algorithm(StemAlgorithm::Porter);
+        let mut index = InvertedIndex::with_tokenizer(tokenizer);
+        let doc_id = index.add_document("Happiness".to_string(), "running daily".to_string());
+
+        // Query words are analyzed to their indexed stems...
+        assert!(index.get_analyzed_posting_list("Running").is_some());
+        assert_eq!(index.search("running"), vec![doc_id]);
+        assert!(index.get_posting_list("Running").is_none());
+        assert!(index.get_indexed_posting_list("running").is_none());
+        // ...while vocabulary entries are looked up as they are
+        let stem = PorterStemmer::stem("happiness");
+        assert!(index.vocabulary().contains(&stem));
+        assert!(index.get_posting_list(&stem).is_some());
+        assert!(index.get_indexed_posting_list(&stem).is_some());
+        // Stop words never reach the postings
+        assert!(index.get_analyzed_posting_list("the").is_none());
+    }
+
+    #[test]
+    fn test_rarest_terms() {
+        let mut index = InvertedIndex::new();
+        index.add_document("Rust".to_string(), "memory safety matters".to_string());
+        index.add_document("Rust".to_string(), "memory safty matters".to_string());
+        index.add_document("Rust".to_string(), "memory mattres".to_string());
+
+        assert_eq!(
+            index.rarest_terms(3),
+            vec![
+                ("mattres".to_string(), 1),
+                ("safety".to_string(), 1),
+                ("safty".to_string(), 1),
+            ]
+        );
+        let all = index.rarest_terms(usize::MAX);
+        assert_eq!(all.len(), 6);
+        assert_eq!(all.last().unwrap().1, 3);
+        assert!(index.rarest_terms(0).is_empty());
+    }
+
+    #[test]
+    fn test_vocabulary_diff_after_stemming() {
+        let build = |algorithm: StemAlgorithm| {
+            let mut tokenizer = Tokenizer::new();
+            tokenizer.set_stem_algorithm(algorithm);
+            let mut index = InvertedIndex::with_tokenizer(tokenizer);
+            index.add_document("Runner".to_string(), "running runs fast".to_string());
+            index.add_document("Notes".to_string(), "connected connect".to_string());
+            index
+        };
+        let plain = build(StemAlgorithm::None).vocabulary();
+        let stemmed = build(StemAlgorithm::Porter).vocabulary();
+
+        assert_eq!(
+            plain.iter().map(String::as_str).collect::<Vec<_>>(),
+            vec![
+                "connect",
+                "connected",
+                "fast",
+                "notes",
+                "runner",
+                "running",
+                "runs"
+            ]
+        );
+        let (added, removed) = vocabulary_diff(&plain, &stemmed);
+        let set = |terms: &[&str]| terms.iter().map(|t| t.to_string()).collect::<BTreeSet<_>>();
+        assert_eq!(added, set(&["note", "run"]));
+        assert_eq!(removed, set(&["connected", "notes", "running", "runs"]));
+        assert_eq!(
+            vocabulary_diff(&plain, &plain),
+            (BTreeSet::new(), BTreeSet::new())
+        );
+    }
+
+    #[test]
+    fn test_term_positions_keep_original() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_keep_original(true);
+        let mut index = InvertedIndex::with_tokenizer(tokenizer);
+        let doc = index.add_document("Intro".to_string(), "Rust and RUST".to_string());
+
+        let posting = &index.get_posting_list("rust").unwrap().postings[0];
+        assert_eq!(posting.doc_id, doc);
+        let originals: Vec<Option<&str>> = posting.positions.iter().map(|p| p.original()).collect();
+        assert_eq!(originals, vec![Some("Rust"), Some("RUST")]);
+
+        // Positions of an index without originals serialize as before
+        let mut plain = InvertedIndex::new();
+        plain.add_document("Intro".to_string(), "rust".to_string());
+        let json = serde_json::to_string(&plain).unwrap();
+        assert!(!json.contains("\"original\""));
+    }
+
     #[test]
     fn test_inverted_index_term_positions() {
         let mut index = InvertedIndex::new();
@@ -352,6 +2235,23 @@ mod tests {
         assert!(results.is_empty());
     }
 
+    #[test]
+    fn test_inverted_index_document_length() {
+        let mut index = InvertedIndex::new();
+
+        let short = index.add_document("Short".to_string(), "search".to_string());
+        let long = index.add_document(
+            "Longer Document".to_string(),
+            "search search engines rank results".to_string(),
+        );
+
+        assert_eq!(index.document_length(short), 2);
+        assert_eq!(index.document_length(long), 7);
+        assert_eq!(index.document_length(999), 0);
+        assert_eq!(index.average_document_length(), 4.5);
+        assert_eq!(InvertedIndex::new().average_document_length(), 0.0);
+    }
+
     #[test]
     fn test_inverted_index_term_frequency() {
         let mut index = InvertedIndex::new();
@@ -395,6 +2295,259 @@ mod tests {
         assert_eq!(index.get_document_frequency("nonexistent"), 0);
     }
 
+    #[test]
+    fn test_inverted_index_document_term_vector() {
+        let mut index = InvertedIndex::new();
+
+        let doc_id = index.add_document(
+            "Search Engines".to_string(),
+            "The search engine ranks search results".to_string(),
+        );
+        index.add_document("Other".to_string(), "unrelated engine text".to_string());
+
+        let vector = index.document_term_vector(doc_id);
+        let expected: HashMap<String, usize> = [
+            ("search", 3),
+            ("engines", 1),
+            ("engine", 1),
+            ("ranks", 1),
+            ("results", 1),
+        ]
+        .into_iter()
+        .map(|(term, tf)| (term.to_string(), tf))
+        .collect();
+        assert_eq!(vector, expected);
+
+        // Unknown documents have an empty vector
+        assert!(index.document_term_vector(999).is_empty());
+    }
+
+    #[test]
+    fn test_inverted_index_documents_without() {
+        let mut index = InvertedIndex::new();
+
+        let doc1 = index.add_document("Doc 1".to_string(), "machine learning".to_string());
+        let doc2 = index.add_document("Doc 2".to_string(), "machine intelligence".to_string());
+        let doc3 = index.add_document("Doc 3".to_string(), "deep learning".to_string());
+
+        // Only doc 3 lacks "machine"
+        let without = index.documents_without("machine");
+        assert_eq!(without, HashSet::from([doc3]));
+
+        // Only doc 2 lacks "learning"
+        let without = index.documents_without("Learning");
+        assert_eq!(without, HashSet::from([doc2]));
+
+        // Non-existent term: every document lacks it
+        let without = index.documents_without("nonexistent");
+        assert_eq!(without, HashSet::from([doc1, doc2, doc3]));
+    }
+
+    #[test]
+    fn test_inverted_index_serialization_keeps_tokenizer_config() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.add_stop_word("engine");
+        tokenizer.set_stem_algorithm(StemAlgorithm::Simple);
+        tokenizer.set_min_token_length(3);
+
+        let mut index = InvertedIndex::with_tokenizer(tokenizer);
+        index.add_document(
+            "Search Engines".to_string(),
+            "The engine is searching indexed documents".to_string(),
+        );
+        index.add_document("Ranking".to_string(), "ranked search results".to_string());
+
+        let json = serde_json::to_string(&index).unwrap();
+        let restored: InvertedIndex = serde_json::from_str(&json).unwrap();
+
+        for query in ["Searching", "engine", "documents", "go"] {
+            assert_eq!(restored.normalize_term(query), index.normalize_term(query));
+        }
+        assert_eq!(restored.normalize_term("Searching"), "search");
+
+        assert_eq!(restored.total_documents(), index.total_documents());
+        assert_eq!(restored.total_unique_terms(), index.total_unique_terms());
+        assert!(restored.get_posting_list("engine").is_none());
+
+        let mut original_hits = index.search("searches");
+        let mut restored_hits = restored.search("searches");
+        original_hits.sort();
+        restored_hits.sort();
+        assert_eq!(original_hits.len(), 2);
+        assert_eq!(restored_hits, original_hits);
+    }
+
+    #[test]
+    fn test_inverted_index_metadata_key_indexing() {
+        let mut index = InvertedIndex::new();
+        let tags = |value: &str| HashMap::from([("tags".to_string(), value.to_string())]);
+
+        let tagged = index.insert_document(
+            "Release Notes".to_string(),
+            "Version two is out".to_string(),
+            tags("rust, search"),
+        );
+        index.insert_document(
+            "Cooking".to_string(),
+            "A pasta recipe".to_string(),
+            tags("food"),
+        );
+
+        // Metadata isn't searchable until its key is indexed
+        assert!(index.search("rust").is_empty());
+
+        index.index_metadata_key("tags");
+        assert_eq!(index.search("rust"), vec![tagged]);
+
+        // Documents added afterwards are indexed on insert
+        let later = index.insert_document(
+            "Search Tips".to_string(),
+            "Ranking basics".to_string(),
+            tags("search"),
+        );
+        let mut hits = index.search("search");
+        hits.sort();
+        assert_eq!(hits, vec![tagged, later]);
+
+        // "search" in the later document comes from both title and tags
+        let posting_list = index.get_posting_list("search").unwrap();
+        let posting = posting_list
+            .postings
+            .iter()
+            .find(|p| p.doc_id == later)
+            .unwrap();
+        assert_eq!(posting.term_frequency, 2);
+        assert!(
+            posting
+                .positions
+                .iter()
+                .any(|p| p.field == FieldType::Metadata("tags".to_string()))
+        );
+
+        let results = index.search_tfidf("rust");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].doc_id, tagged);
+    }
+
+    #[test]
+    fn test_inverted_index_content_digest() {
+        let build = |second: &str| {
+            let mut index = InvertedIndex::new();
+            index.add_document(
+                "Search Engines".to_string(),
+                "inverted indexes map terms to documents".to_string(),
+            );
+            index.add_document("Ranking".to_string(), second.to_string());
+            index.add_document(
+                "Tokenizers".to_string(),
+                "split text into searchable terms".to_string(),
+            );
+            index
+        };
+
+        let a = build("tf-idf ranks matching documents");
+        let b = build("tf-idf ranks matching documents");
+        assert_eq!(a.content_digest(), b.content_digest());
+        assert_eq!(a.content_digest(), a.content_digest());
+
+        let changed = build("tf-idf ranks relevant documents");
+        assert_ne!(a.content_digest(), changed.content_digest());
+
+        assert_ne!(a.content_digest(), InvertedIndex::new().content_digest());
+    }
+
+    #[test]
+    fn test_content_digest_is_pinned() {
+        // Guards the byte encoding: this value must only change deliberately.
+        let mut index = InvertedIndex::new();
+        index.add_document("Rust".to_string(), "memory safety".to_string());
+        assert_eq!(index.content_digest(), 0xbbd1_da11_7804_7153);
+    }
+
+    #[test]
+    fn test_inverted_index_validate_clean() {
+        let mut index = InvertedIndex::new();
+        assert_eq!(index.validate(), Ok(()));
+
+        index.add_document("Doc 1".to_string(), "machine learning".to_string());
+        index.add_document("Doc 2".to_string(), "machine intelligence".to_string());
+        index.insert_document(
+            "Doc 3".to_string(),
+            "deep learning".to_string(),
+            HashMap::from([("tags".to_string(), "machine".to_string())]),
+        );
+        index.index_metadata_key("tags");
+
+        assert_eq!(index.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_inverted_index_validate_reports_corruption() {
+        let mut index = InvertedIndex::new();
+        index.add_document("Doc 1".to_string(), "machine learning".to_string());
+        index.add_document("Doc 2".to_string(), "machine intelligence".to_string());
+
+        index.index.get_mut("machine").unwrap().document_frequency = 5;
+        index
+            .index
+            .get_mut("learning")
+            .unwrap()
+            .add_posting(42, Vec::new());
+        index
+            .index
+            .insert("ghost".to_string(), PostingList::new("ghost".to_string()));
+
+        let errors = index.validate().unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                ConsistencyError::EmptyPostingList {
+                    term: "ghost".to_string()
+                },
+                ConsistencyError::OrphanedPosting {
+                    term: "learning".to_string(),
+                    doc_id: 42
+                },
+                ConsistencyError::DocumentFrequencyMismatch {
+                    term: "machine".to_string(),
+                    recorded: 5,
+                    actual: 2
+                },
+                ConsistencyError::TotalTermsMismatch {
+                    recorded: 6,
+                    actual: 7
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inverted_index_validate_after_pruning_and_round_trip() {
+        let mut index = InvertedIndex::new();
+        for i in 0..4 {
+            index.add_document(
+                format!("Doc {}", i),
+                ["machine learning"].repeat(i + 1).join(" "),
+            );
+        }
+        index.set_max_postings_per_term(Some(2));
+        index.add_document("Doc 4".to_string(), "machine vision".to_string());
+        assert_eq!(index.validate(), Ok(()));
+
+        let json = serde_json::to_string(&index).unwrap();
+        let mut restored: InvertedIndex = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.validate(), Ok(()));
+
+        restored.total_terms += 1;
+        assert_eq!(
+            restored.validate(),
+            Err(vec![ConsistencyError::TotalTermsMismatch {
+                recorded: index.total_terms + 1,
+                actual: index.total_terms
+            }])
+        );
+    }
+
     #[test]
     fn test_inverted_index_punctuation_handling() {
         let mut index = InvertedIndex::new();
@@ -439,6 +2592,154 @@ mod tests {
         assert_eq!(index.total_unique_terms(), 0);
     }
 
+    #[test]
+    fn test_warm_up_populates_idf_cache() {
+        use crate::search::Searcher;
+
+        let mut index = InvertedIndex::new();
+        index.add_document("Rust".to_string(), "rust ownership borrowing".to_string());
+        index.add_document("Go".to_string(), "go channels and rust".to_string());
+
+        let cold = Searcher::new(&index);
+        let cold_score = cold.search_response("rust ownership").results[0].score;
+        assert_eq!(cold.idf_computations(), 2);
+
+        index.warm_up();
+        index.warm_up();
+        let cache = index.idf_cache.as_ref().unwrap();
+        assert_eq!(cache.entries.len(), index.index.len());
+        assert!(
+            index
+                .index
+                .keys()
+                .all(|term| index.cached_idf(term).is_some())
+        );
+        assert!(index.suffix_index.is_some());
+
+        let warm = Searcher::new(&index);
+        let results = warm.search_response("rust ownership").results;
+        assert_eq!(warm.idf_computations(), 0);
+        assert_eq!(results[0].score, cold_score);
+
+        index.add_document("C".to_string(), "pointers".to_string());
+        assert!(index.cached_idf("rust").is_none());
+    }
+
+    #[test]
+    fn test_indexing_streams_tokens() {
+        let mut index = InvertedIndex::new();
+        let content = "lorem ipsum dolor sit amet ".repeat(10_000);
+
+        let doc_id = index.add_document("Large".to_string(), content.clone());
+
+        assert_eq!(index.get_term_frequency("amet", doc_id), 10_000);
+        assert_eq!(index.document_length(doc_id), 50_001);
+        // Streamed positions agree with a collected tokenization
+        let last = index.tokenizer().tokenize(&content).pop().unwrap();
+        let posting = index.get_posting_list("amet").unwrap();
+        let positions = &posting.postings[0].positions;
+        assert_eq!(positions.last().unwrap().position, last.position);
+    }
+
+    #[test]
+    fn test_max_postings_per_term_keeps_highest_tf() {
+        use crate::search::Searcher;
+
+        let mut index = InvertedIndex::new();
+        index.set_max_postings_per_term(Some(2));
+        let once = index.add_document("One".to_string(), "rust".to_string());
+        let thrice = index.add_document("Three".to_string(), "rust rust rust".to_string());
+        let twice = index.add_document("Two".to_string(), "rust rust".to_string());
+        let tie = index.add_document("Tie".to_string(), "rust rust".to_string());
+
+        let posting_list = index.get_posting_list("rust").unwrap();
+        assert_eq!(posting_list.postings.len(), 2);
+        assert_eq!(posting_list.document_frequency, 2);
+        let mut retained = index.search("rust");
+        retained.sort();
+        assert_eq!(retained, vec![thrice, twice]);
+        assert!(!retained.contains(&once) && !retained.contains(&tie));
+        assert!(index.validate().is_ok());
+
+        let results = Searcher::new(&index).search("rust");
+        assert_eq!(results[0].doc_id, thrice);
+
+        index.set_max_postings_per_term(Some(1));
+        assert_eq!(index.search("rust"), vec![thrice]);
+        assert!(index.validate().is_ok());
+    }
+
+    #[test]
+    fn test_field_lengths() {
+        let mut index = InvertedIndex::new();
+        let a = index.add_document("Rust".to_string(), "safe fast systems code".to_string());
+        let b = index.add_document("Go Concurrency".to_string(), "channels".to_string());
+
+        assert_eq!(index.field_length(a, &FieldType::Title), 1);
+        assert_eq!(index.field_length(a, &FieldType::Content), 4);
+        assert_eq!(index.field_length(b, &FieldType::Title), 2);
+        assert_eq!(index.field_length(99, &FieldType::Title), 0);
+        assert_eq!(index.average_field_length(&FieldType::Title), 1.5);
+        assert_eq!(index.average_field_length(&FieldType::Content), 2.5);
+        let total =
+            index.field_length(a, &FieldType::Title) + index.field_length(a, &FieldType::Content);
+        assert_eq!(total, index.document_length(a));
+
+        index.reindex();
+        assert_eq!(index.field_length(a, &FieldType::Content), 4);
+    }
+
+    #[test]
+    fn test_document_contains_all() {
+        let mut index = InvertedIndex::new();
+        let doc = index.add_document(
+            "Access Policy".to_string(),
+            "confidential finance report".to_string(),
+        );
+        let other = index.add_document("Memo".to_string(), "finance lunch".to_string());
+
+        assert!(index.document_contains_all(doc, &["finance", "confidential", "policy"]));
+        assert!(index.document_contains_all(doc, &["FINANCE", "Confidential"]));
+        assert!(!index.document_contains_all(doc, &["finance", "lunch"]));
+        assert!(!index.document_contains_all(other, &["finance", "confidential"]));
+        assert!(!index.document_contains_all(doc, &["the"]));
+        assert!(index.document_contains_all(doc, &[]));
+    }
+
+    #[test]
+    fn test_document_similarity() {
+        let mut index = InvertedIndex::new();
+        let a = index.add_document("Rust".to_string(), "safe fast systems".to_string());
+        let same = index.add_document("Rust".to_string(), "systems fast safe".to_string());
+        let partial = index.add_document("Rust".to_string(), "safe slow scripts".to_string());
+        let disjoint = index.add_document("Go".to_string(), "goroutines channels".to_string());
+
+        assert_eq!(index.document_similarity(a, a), 1.0);
+        assert_eq!(index.document_similarity(a, same), 1.0);
+        // Shared: rust, safe. Union: rust, safe, fast, systems, slow, scripts.
+        assert_eq!(index.document_similarity(a, partial), 2.0 / 6.0);
+        assert_eq!(
+            index.document_similarity(a, partial),
+            index.document_similarity(partial, a)
+        );
+        assert_eq!(index.document_similarity(a, disjoint), 0.0);
+        assert!(index.document_similarity(a, 999).is_nan());
+    }
+
+    #[test]
+    fn test_case_sensitive_index_queries() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_lowercase(false);
+        tokenizer.set_folding(true);
+        let mut index = InvertedIndex::with_tokenizer(tokenizer);
+        let proper = index.add_document("Name".to_string(), "Rust Café".to_string());
+        index.add_document("Noun".to_string(), "rust on iron".to_string());
+
+        assert_eq!(index.search("Rust"), vec![proper]);
+        assert_eq!(index.search("Cafe").len(), 1);
+        assert!(index.search("cafe").is_empty());
+    }
+
     #[test]
     fn test_tokenizer_integration_stop_words() {
         let mut index = InvertedIndex::new();
@@ -1,9 +1,15 @@
+#[cfg(feature = "async")]
+pub mod async_index;
+pub mod cache;
+pub mod dedup;
 pub mod document;
 pub mod index;
+pub mod parser;
 pub mod search;
 pub mod tokenizer;
 
-pub use document::{Document, DocumentId};
+pub use document::{Document, DocumentId, MetadataFilter};
 pub use index::InvertedIndex;
-pub use search::SearchResult;
+pub use parser::{ParseError, parse_query, parse_query_with_operator};
+pub use search::{QueryError, SearchResponse, SearchResult};
 pub use tokenizer::Tokenizer;
@@ -0,0 +1,326 @@
+use crate::index::FieldType;
+use crate::search::{BooleanOperator, Query};
+use std::fmt;
+
+/// Field names accepted in `field:term` clauses, besides `meta.<key>`.
+const FIELDS: [(&str, FieldType); 2] =
+    [("title", FieldType::Title), ("content", FieldType::Content)];
+
+/// Prefix naming an indexed metadata key, as in `meta.author:knuth`.
+const METADATA_PREFIX: &str = "meta.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// A `"` at byte `position` was never closed.
+    UnterminatedQuote { position: usize },
+    /// A `field:term` clause named a field that cannot be searched.
+    UnknownField { field: String },
+    /// The text after `^` is not a non-negative number.
+    InvalidBoost { value: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnterminatedQuote { position } => {
+                write!(f, "unterminated quote starting at byte {}", position)
+            }
+            ParseError::UnknownField { field } => {
+                let mut valid: Vec<&str> = FIELDS.iter().map(|(name, _)| *name).collect();
+                valid.push("meta.<key>");
+                write!(
+                    f,
+                    "unknown field '{}'; valid fields are: {}",
+                    field,
+                    valid.join(", ")
+                )
+            }
+            ParseError::InvalidBoost { value } => write!(f, "invalid boost '{}'", value),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses a user query string. Bare words become [`Query::Term`]s, double
+/// quoted text becomes a [`Query::Phrase`], and multiple clauses are joined
+/// with an implicit AND. A word may be scoped with `field:` (e.g.
+/// `title:rust` or `meta.author:knuth`) and any clause may carry a `^n`
+/// boost.
+pub fn parse_query(input: &str) -> Result<Query, ParseError> {
+    parse_query_with_operator(input, BooleanOperator::And)
+}
+
+/// Like [`parse_query`], but joins multiple clauses with `operator`.
+pub fn parse_query_with_operator(
+    input: &str,
+    operator: BooleanOperator,
+) -> Result<Query, ParseError> {
+    let mut clauses = Vec::new();
+    let mut rest = input;
+
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let position = input.len() - rest.len();
+            let end = quoted
+                .find('"')
+                .ok_or(ParseError::UnterminatedQuote { position })?;
+            let terms: Vec<String> = quoted[..end]
+                .split_whitespace()
+                .map(|s| s.to_string())
+                .collect();
+            rest = &quoted[end + 1..];
+            let boost_end = rest
+                .find(|c: char| c.is_whitespace() || c == '"')
+                .unwrap_or(rest.len());
+            let boost = match rest[..boost_end].strip_prefix('^') {
+                Some(value) => {
+                    rest = &rest[boost_end..];
+                    Some(parse_boost(value)?)
+                }
+                None => None,
+            };
+            if !terms.is_empty() {
+                clauses.push(with_boost(Query::Phrase(terms), boost));
+            }
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '"')
+                .unwrap_or(rest.len());
+            clauses.push(parse_word(&rest[..end])?);
+            rest = &rest[end..];
+        }
+    }
+
+    if clauses.len() == 1 {
+        Ok(clauses.remove(0))
+    } else {
+        Ok(Query::Boolean {
+            operator,
+            queries: clauses,
+        })
+    }
+}
+
+/// Parses a bare word with an optional `field:` prefix and `^n` suffix.
+fn parse_word(word: &str) -> Result<Query, ParseError> {
+    let (word, boost) = match word.rsplit_once('^') {
+        Some((word, value)) => (word, Some(parse_boost(value)?)),
+        None => (word, None),
+    };
+
+    let query = match word.split_once(':') {
+        Some((field, term)) if looks_like_field(field, term) => Query::FieldTerm {
+            field: parse_field(field)?,
+            term: term.to_string(),
+        },
+        _ => Query::Term(word.to_string()),
+    };
+
+    Ok(with_boost(query, boost))
+}
+
+/// Whether `field:term` is meant as a field prefix: always for a known
+/// field, and for any other name of two or more letters followed by a word,
+/// which is then reported as unknown. Anything else, such as `c:\path` or
+/// `12:30`, is a plain term.
+fn looks_like_field(field: &str, term: &str) -> bool {
+    let known = field.starts_with(METADATA_PREFIX)
+        || FIELDS
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case(field));
+    let named = field.len() >= 2
+        && field.chars().all(|c| c.is_ascii_alphabetic())
+        && term.starts_with(char::is_alphanumeric);
+    known || named
+}
+
+fn parse_field(field: &str) -> Result<FieldType, ParseError> {
+    if let Some(key) = field.strip_prefix(METADATA_PREFIX)
+        && !key.is_empty()
+    {
+        return Ok(FieldType::Metadata(key.to_string()));
+    }
+    FIELDS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(field))
+        .map(|(_, field_type)| field_type.clone())
+        .ok_or_else(|| ParseError::UnknownField {
+            field: field.to_string(),
+        })
+}
+
+fn parse_boost(value: &str) -> Result<f64, ParseError> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|boost| boost.is_finite() && *boost >= 0.0)
+        .ok_or_else(|| ParseError::InvalidBoost {
+            value: value.to_string(),
+        })
+}
+
+fn with_boost(query: Query, boost: Option<f64>) -> Query {
+    match boost {
+        Some(boost) => Query::Boost {
+            query: Box::new(query),
+            boost,
+        },
+        None => query,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_single_term() {
+        let query = parse_query("  search  ").unwrap();
+        assert!(matches!(query, Query::Term(t) if t == "search"));
+    }
+
+    #[test]
+    fn test_parse_implicit_and() {
+        let query = parse_query("machine learning").unwrap();
+
+        match query {
+            Query::Boolean { operator, queries } => {
+                assert!(matches!(operator, BooleanOperator::And));
+                assert_eq!(queries.len(), 2);
+                assert!(matches!(&queries[0], Query::Term(t) if t == "machine"));
+                assert!(matches!(&queries[1], Query::Term(t) if t == "learning"));
+            }
+            _ => panic!("Expected Boolean query"),
+        }
+    }
+
+    #[test]
+    fn test_parse_phrase() {
+        let query = parse_query("rust \"neural network\"").unwrap();
+
+        match query {
+            Query::Boolean { queries, .. } => {
+                assert!(matches!(&queries[0], Query::Term(t) if t == "rust"));
+                match &queries[1] {
+                    Query::Phrase(terms) => assert_eq!(terms, &vec!["neural", "network"]),
+                    _ => panic!("Expected Phrase query"),
+                }
+            }
+            _ => panic!("Expected Boolean query"),
+        }
+    }
+
+    #[test]
+    fn test_parse_empty_input() {
+        let query = parse_query("   ").unwrap();
+
+        match query {
+            Query::Boolean { queries, .. } => assert!(queries.is_empty()),
+            _ => panic!("Expected Boolean query"),
+        }
+        assert!(matches!(
+            parse_query("\"\"").unwrap(),
+            Query::Boolean { .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_unterminated_quote() {
+        let err = parse_query("rust \"neural network").unwrap_err();
+
+        assert_eq!(err, ParseError::UnterminatedQuote { position: 5 });
+        assert_eq!(err.to_string(), "unterminated quote starting at byte 5");
+    }
+
+    #[test]
+    fn test_parse_field_term() {
+        let query = parse_query("title:rust").unwrap();
+
+        assert!(matches!(
+            query,
+            Query::FieldTerm { field: FieldType::Title, term } if term == "rust"
+        ));
+    }
+
+    #[test]
+    fn test_parse_boosted_term() {
+        let query = parse_query("body^2").unwrap();
+
+        match query {
+            Query::Boost { query, boost } => {
+                assert_eq!(boost, 2.0);
+                assert!(matches!(*query, Query::Term(t) if t == "body"));
+            }
+            _ => panic!("Expected Boost query"),
+        }
+
+        let query = parse_query("title:machine^3 content:learning").unwrap();
+        match query {
+            Query::Boolean { queries, .. } => {
+                assert!(matches!(
+                    &queries[0],
+                    Query::Boost { query, boost } if *boost == 3.0
+                        && matches!(**query, Query::FieldTerm { field: FieldType::Title, .. })
+                ));
+                assert!(matches!(
+                    &queries[1],
+                    Query::FieldTerm { field: FieldType::Content, term } if term == "learning"
+                ));
+            }
+            _ => panic!("Expected Boolean query"),
+        }
+
+        assert_eq!(
+            parse_query("rust^x").unwrap_err(),
+            ParseError::InvalidBoost {
+                value: "x".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_field() {
+        let err = parse_query("author:knuth").unwrap_err();
+
+        assert_eq!(
+            err,
+            ParseError::UnknownField {
+                field: "author".to_string()
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "unknown field 'author'; valid fields are: title, content, meta.<key>"
+        );
+        assert_eq!(
+            parse_query("meta.:knuth").unwrap_err(),
+            ParseError::UnknownField {
+                field: "meta.".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_metadata_field_term() {
+        let query = parse_query("meta.author:knuth").unwrap();
+
+        assert!(matches!(
+            query,
+            Query::FieldTerm { field: FieldType::Metadata(key), term }
+                if key == "author" && term == "knuth"
+        ));
+    }
+
+    #[test]
+    fn test_parse_colon_in_plain_term() {
+        for input in ["c:\\path", "12:30", "http://example.com"] {
+            assert!(matches!(parse_query(input).unwrap(), Query::Term(t) if t == input));
+        }
+    }
+}
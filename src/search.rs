@@ -1,6 +1,15 @@
-use crate::document::DocumentId;
-use crate::index::InvertedIndex;
+use crate::cache::{CacheStats, ResultCache};
+use crate::document::{Document, DocumentId, MetadataFilter};
+use crate::index::{FieldType, InvertedIndex, PostingEntry};
+use crate::parser::{ParseError, parse_query_with_operator};
+use crate::tokenizer::{FIELD_SEPARATOR, StemAlgorithm, Tokenizer};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::{Cell, OnceCell};
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone)]
 pub struct SearchResult {
@@ -10,511 +19,5131 @@ pub struct SearchResult {
     pub snippet: String,
 }
 
+/// Results of a free-text search plus the query words the tokenizer discarded.
 #[derive(Debug, Clone)]
+pub struct SearchResponse {
+    pub results: Vec<SearchResult>,
+    /// Query words removed by analysis (stop words, too short or too long),
+    /// in query order.
+    pub dropped_terms: Vec<String>,
+    /// AND clauses removed because their term is not in the vocabulary. Only
+    /// populated when [`Searcher::with_absent_terms_dropped`] is set.
+    pub absent_terms: Vec<String>,
+}
+
+/// Why a query could not be run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryError {
+    Parse(ParseError),
+    /// A phrase has more terms than [`InvertedIndex::max_phrase_terms`].
+    PhraseTooLong {
+        terms: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::Parse(err) => err.fmt(f),
+            QueryError::PhraseTooLong { terms, max } => {
+                write!(f, "phrase has {} terms; at most {} are allowed", terms, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for QueryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            QueryError::Parse(err) => Some(err),
+            QueryError::PhraseTooLong { .. } => None,
+        }
+    }
+}
+
+impl From<ParseError> for QueryError {
+    fn from(err: ParseError) -> Self {
+        QueryError::Parse(err)
+    }
+}
+
+/// Summary of the scores in one result list, for spotting ambiguous queries.
+/// All values are 0.0 when there are no results.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ScoreDistribution {
+    pub max: f64,
+    pub min: f64,
+    pub mean: f64,
+    /// Top score divided by the second-best: near 1.0 when the leaders tie,
+    /// large when one result stands out. `None` with fewer than two results
+    /// or a second-best score of zero.
+    pub top_ratio: Option<f64>,
+}
+
+impl ScoreDistribution {
+    fn of(results: &[SearchResult]) -> Self {
+        let (Some(first), Some(last)) = (results.first(), results.last()) else {
+            return Self::default();
+        };
+        let mean = results.iter().map(|r| r.score).sum::<f64>() / results.len() as f64;
+        let top_ratio = results
+            .get(1)
+            .filter(|second| second.score > 0.0)
+            .map(|second| first.score / second.score);
+        Self {
+            max: first.score,
+            min: last.score,
+            mean,
+            top_ratio,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub enum BooleanOperator {
+    #[default]
     And,
     Or,
     Not,
 }
 
-#[derive(Debug, Clone)]
+/// Restrictions on how a [`Query::PhraseWith`] matches.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PhraseOptions {
+    /// Matches must also agree in case with the stored text, so "Apple"
+    /// skips documents that only say "apple". Words are compared before
+    /// stemming and plural stripping.
+    pub case_sensitive: bool,
+    /// Matches must fall within one sentence, so "state art" skips "... the
+    /// state. Art ...".
+    pub same_sentence: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Query {
     Term(String),
+    /// Clause scores combine recursively, in clause order:
+    ///
+    /// - `And` matches documents matching every clause and sums their scores.
+    /// - `Or` matches documents matching any clause and sums the scores of
+    ///   the clauses they match, times the coverage factor (1.0 unless
+    ///   [`ScoringOptions::coverage_weight`] is set).
+    /// - A binary `Not` matches its first clause minus its second and keeps
+    ///   the first clause's score. A unary `Not` matches every document its
+    ///   clause doesn't, with a score of zero, so inside an `And` it only
+    ///   filters. `Not` with more clauses matches nothing.
     Boolean {
         operator: BooleanOperator,
         queries: Vec<Query>,
     },
     Phrase(Vec<String>),
+    /// A [`Query::Phrase`] matched according to `options`. With default
+    /// options it matches exactly like `Phrase`.
+    PhraseWith {
+        terms: Vec<String>,
+        options: PhraseOptions,
+    },
     Wildcard(String),
+    /// Matches when every clause matches within one field and the gaps between
+    /// them total at most `slop` positions. With `in_order`, clauses must
+    /// appear in the given sequence. Clauses may be terms, phrases, or nested
+    /// span queries. With `same_sentence`, the whole match must also fall
+    /// within one sentence.
+    SpanNear {
+        clauses: Vec<Query>,
+        slop: usize,
+        in_order: bool,
+        same_sentence: bool,
+    },
+    /// A term that is already analyzed: it must equal a vocabulary entry
+    /// exactly, so lowercasing, stemming and the like are up to the caller.
+    IndexedTerm(String),
+    /// A term that only matches occurrences within `field`.
+    FieldTerm {
+        field: FieldType,
+        term: String,
+    },
+    /// Matches documents satisfying every `(field, query)` pair, each query
+    /// only counting matches inside its field. Scores are summed.
+    FieldScoped(Vec<(FieldType, Query)>),
+    /// Multiplies the scores of `query` by `boost`.
+    Boost {
+        query: Box<Query>,
+        boost: f64,
+    },
+    /// Matches documents containing every term, ranked in tiers: the exact
+    /// phrase first, then the terms in any order with at most `window`
+    /// positions between them (as a [`Query::SpanNear`] slop), then mere
+    /// co-occurrence. Within a tier, documents are ordered by the AND score
+    /// of the terms.
+    Proximity {
+        terms: Vec<String>,
+        window: usize,
+    },
+    /// Matches every document with a score of 1.0.
+    MatchAll,
+    /// Matches no documents.
+    MatchNone,
 }
 
-pub struct Searcher<'a> {
-    index: &'a InvertedIndex,
+impl Query {
+    /// Rewrites the query into an equivalent, simpler form: `And` and `Or`
+    /// clauses nested under the same operator are flattened into their
+    /// parent, repeated clauses are dropped, and single-clause `And`/`Or`
+    /// are unwrapped, recursively. Matches are unchanged, and so are scores,
+    /// except that a repeated clause no longer counts twice and an `Or`'s
+    /// coverage factor counts the flattened clauses. Span clauses are left
+    /// alone, as their order and repetition are positional.
+    pub fn optimize(self) -> Query {
+        match self {
+            Query::Boolean {
+                operator: BooleanOperator::Not,
+                queries,
+            } => Query::Boolean {
+                operator: BooleanOperator::Not,
+                queries: queries.into_iter().map(Query::optimize).collect(),
+            },
+            Query::Boolean { operator, queries } => {
+                let mut flattened: Vec<Query> = Vec::new();
+                let mut push = |query: Query| {
+                    if !flattened.contains(&query) {
+                        flattened.push(query);
+                    }
+                };
+                for query in queries.into_iter().map(Query::optimize) {
+                    match query {
+                        // An empty clause matches nothing, which flattening
+                        // it away would change.
+                        Query::Boolean {
+                            operator: inner,
+                            queries: inner_queries,
+                        } if inner == operator && !inner_queries.is_empty() => {
+                            inner_queries.into_iter().for_each(&mut push)
+                        }
+                        other => push(other),
+                    }
+                }
+                if flattened.len() == 1 {
+                    flattened.remove(0)
+                } else {
+                    Query::Boolean {
+                        operator,
+                        queries: flattened,
+                    }
+                }
+            }
+            Query::Boost { query, boost } => Query::Boost {
+                query: Box::new(query.optimize()),
+                boost,
+            },
+            Query::FieldScoped(clauses) => Query::FieldScoped(
+                clauses
+                    .into_iter()
+                    .map(|(field, query)| (field, query.optimize()))
+                    .collect(),
+            ),
+            other => other,
+        }
+    }
 }
 
-impl<'a> Searcher<'a> {
-    pub fn new(index: &'a InvertedIndex) -> Self {
-        Self { index }
+/// Positions `start..=end` of one field covered by a span query match, plus
+/// the number of unmatched positions (`slop`) inside that range. `sentence`
+/// is `None` when the range crosses a sentence boundary.
+#[derive(Debug, Clone)]
+struct Span {
+    field: FieldType,
+    start: usize,
+    end: usize,
+    slop: usize,
+    sentence: Option<usize>,
+}
+
+impl Span {
+    fn len(&self) -> usize {
+        self.end - self.start + 1
     }
 
-    pub fn search(&self, query: &str) -> Vec<SearchResult> {
-        let query = Query::Term(query.to_string());
-        self.execute_query(&query)
+    fn overlaps(&self, other: &Span) -> bool {
+        self.field == other.field && self.start <= other.end && other.start <= self.end
     }
+}
 
-    pub fn search_with_query(&self, query: &Query) -> Vec<SearchResult> {
-        self.execute_query(query)
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ScoringModel {
+    #[default]
+    TfIdf,
+    /// Okapi BM25: `k1` controls term-frequency saturation and `b` how
+    /// strongly scores are normalized by document length.
+    Bm25 { k1: f64, b: f64 },
+}
+
+impl ScoringModel {
+    /// BM25 with the customary `k1 = 1.2`, `b = 0.75`.
+    pub fn bm25() -> Self {
+        ScoringModel::Bm25 { k1: 1.2, b: 0.75 }
     }
+}
 
-    fn execute_query(&self, query: &Query) -> Vec<SearchResult> {
-        match query {
-            Query::Term(term) => self.search_term(term),
-            Query::Boolean { operator, queries } => self.search_boolean(operator, queries),
-            Query::Phrase(terms) => self.search_phrase(terms),
-            Query::Wildcard(pattern) => self.search_wildcard(pattern),
+/// Knobs that shape how matches are turned into relevance scores.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScoringOptions {
+    pub model: ScoringModel,
+    /// Occurrences of a term beyond this count no longer raise a document's
+    /// score, blunting keyword stuffing.
+    pub max_effective_tf: Option<usize>,
+    /// Scales OR scores by `(matched clauses / clauses) ^ coverage_weight`,
+    /// so documents matching more of the query rank above those matching a
+    /// single rare term. 0.0, the default, disables it; 1.0 is the classic
+    /// coordination factor.
+    #[serde(default)]
+    pub coverage_weight: f64,
+    /// Multiplies a term's score by the boost of each field it occurs in,
+    /// scoring every field's occurrences separately. Unlisted fields have a
+    /// boost of 1.0.
+    #[serde(default)]
+    pub field_boosts: Vec<(FieldType, f64)>,
+    /// Lists results from the lowest score up, the exact reverse of the
+    /// default order, ties included.
+    #[serde(default)]
+    pub ascending: bool,
+    /// Unix timestamp to rank at: documents whose [`Self::expiration_key`]
+    /// metadata holds an earlier timestamp are left out of results.
+    #[serde(default)]
+    pub as_of: Option<u64>,
+    /// Metadata key holding a document's expiry as a unix timestamp.
+    /// Documents without it, or with a value that isn't a timestamp, never
+    /// expire.
+    #[serde(default)]
+    pub expiration_key: Option<String>,
+    /// Divides each boolean clause's scores by that clause's best score
+    /// before combining, so every clause contributes on the same `0..=1`
+    /// scale whatever its query type. Off by default.
+    #[serde(default)]
+    pub normalize_clauses: bool,
+    /// Treats terms found in more than this share of documents as stop
+    /// words: they match nothing alone and are dropped from AND and OR
+    /// clauses. See [`InvertedIndex::auto_stop_words`].
+    #[serde(default)]
+    pub auto_stop_word_ratio: Option<f64>,
+}
+
+/// How snippets mark up matched terms.
+#[derive(Debug, Clone)]
+pub struct SnippetConfig {
+    /// Inserted before each highlight. A `{term}` placeholder is replaced by
+    /// the index of the matched query term, e.g. `<mark data-term="{term}">`.
+    pub pre_tag: String,
+    pub post_tag: String,
+    /// HTML-escape the content around and inside highlights, so the snippet
+    /// can be embedded in a page. The tags themselves are left untouched.
+    pub escape_html: bool,
+}
+
+impl Default for SnippetConfig {
+    fn default() -> Self {
+        Self {
+            pre_tag: "<b>".to_string(),
+            post_tag: "</b>".to_string(),
+            escape_html: false,
         }
     }
+}
 
-    fn search_term(&self, term: &str) -> Vec<SearchResult> {
-        let mut results = Vec::new();
-        let normalized_term = term.to_lowercase();
+/// The kind of query clause a share of a score came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContributionSource {
+    /// A single term, field-scoped term, or wildcard expansion.
+    Term,
+    Phrase,
+    /// A [`Query::SpanNear`] match.
+    Proximity,
+    MatchAll,
+}
 
-        if let Some(posting_list) = self.index.get_posting_list(&normalized_term) {
-            for posting in &posting_list.postings {
-                let score = self.calculate_tfidf(
-                    posting.term_frequency,
-                    posting_list.document_frequency,
-                    self.index.total_documents(),
-                );
+/// One leaf clause's share of a document's score, after boosts.
+#[derive(Debug, Clone)]
+pub struct ScoreContribution {
+    pub source: ContributionSource,
+    pub query: Query,
+    pub score: f64,
+}
 
-                if let Some(doc) = self.index.get_document(posting.doc_id) {
-                    let snippet = self.generate_snippet(&doc.content, &normalized_term);
-                    results.push(SearchResult {
-                        doc_id: posting.doc_id,
-                        score,
-                        title: doc.title.clone(),
-                        snippet,
-                    });
-                }
-            }
-        }
+/// Breaks a document's score for a query down into the clauses that
+/// produced it. The contributions sum to `score`.
+#[derive(Debug, Clone)]
+pub struct ScoreExplanation {
+    pub doc_id: DocumentId,
+    pub score: f64,
+    pub contributions: Vec<ScoreContribution>,
+}
 
-        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
-        results
+impl ScoreExplanation {
+    /// Sum of the contributions coming from `source` clauses.
+    pub fn score_from(&self, source: ContributionSource) -> f64 {
+        self.contributions
+            .iter()
+            .filter(|c| c.source == source)
+            .map(|c| c.score)
+            .sum()
     }
+}
 
-    fn search_boolean(&self, operator: &BooleanOperator, queries: &[Query]) -> Vec<SearchResult> {
-        if queries.is_empty() {
-            return Vec::new();
+/// A term's inverse document frequency under each scoring model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TermIdf {
+    pub(crate) tfidf: f64,
+    pub(crate) bm25: f64,
+}
+
+impl TermIdf {
+    /// Both idfs are clamped at zero so drifted accounting (df > total)
+    /// can't produce negative scores.
+    pub(crate) fn new(document_frequency: usize, total_docs: usize) -> Self {
+        let df = document_frequency as f64;
+        let total = total_docs as f64;
+        Self {
+            tfidf: (total / df).log10().max(0.0),
+            bm25: (1.0 + (total - df + 0.5) / (df + 0.5)).ln().max(0.0),
         }
+    }
+}
 
-        let mut result_sets: Vec<HashSet<DocumentId>> = Vec::new();
-        let mut all_results: HashMap<DocumentId, SearchResult> = HashMap::new();
+/// How snippet lengths are measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SnippetLengthUnit {
+    /// UTF-8 bytes; text with many multi-byte characters shows fewer of them.
+    #[default]
+    Bytes,
+    /// Unicode scalar values, roughly what a reader sees as characters.
+    Chars,
+}
 
-        for query in queries {
-            let results = self.execute_query(query);
-            let doc_ids: HashSet<DocumentId> = results.iter().map(|r| r.doc_id).collect();
+/// A value results can be ordered by.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortKey {
+    /// The result score.
+    Relevance,
+    /// A metadata value, compared numerically when both values parse as
+    /// numbers and as text otherwise. Documents without it sort last.
+    Metadata(String),
+}
 
-            for result in results {
-                all_results.insert(result.doc_id, result);
-            }
+/// One key of a result ordering, see [`Searcher::search_multi_sorted`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortSpec {
+    pub key: SortKey,
+    pub descending: bool,
+}
 
-            result_sets.push(doc_ids);
+impl SortSpec {
+    pub fn ascending(key: SortKey) -> Self {
+        Self {
+            key,
+            descending: false,
         }
+    }
 
-        let final_doc_ids = match operator {
-            BooleanOperator::And => result_sets
-                .into_iter()
-                .reduce(|acc, set| acc.intersection(&set).cloned().collect())
-                .unwrap_or_default(),
-            BooleanOperator::Or => result_sets
-                .into_iter()
-                .reduce(|acc, set| acc.union(&set).cloned().collect())
-                .unwrap_or_default(),
-            BooleanOperator::Not => {
-                if result_sets.len() != 2 {
-                    return Vec::new();
+    pub fn descending(key: SortKey) -> Self {
+        Self {
+            key,
+            descending: true,
+        }
+    }
+
+    fn compare(&self, a: &SearchResult, b: &SearchResult, index: &InvertedIndex) -> Ordering {
+        let ordering = match &self.key {
+            SortKey::Relevance => a.score.partial_cmp(&b.score).unwrap_or(Ordering::Equal),
+            SortKey::Metadata(key) => {
+                let value = |result: &SearchResult| {
+                    index
+                        .get_document(result.doc_id)
+                        .and_then(|doc| doc.metadata.get(key))
+                };
+                match (value(a), value(b)) {
+                    (Some(x), Some(y)) => {
+                        match (x.trim().parse::<f64>(), y.trim().parse::<f64>()) {
+                            (Ok(x), Ok(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+                            _ => x.cmp(y),
+                        }
+                    }
+                    // Missing values go last whatever the direction.
+                    (Some(_), None) => return Ordering::Less,
+                    (None, Some(_)) => return Ordering::Greater,
+                    (None, None) => Ordering::Equal,
                 }
-                let base = &result_sets[0];
-                let exclude = &result_sets[1];
-                base.difference(exclude).cloned().collect()
             }
         };
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
 
-        let mut results: Vec<SearchResult> = final_doc_ids
-            .into_iter()
-            .filter_map(|doc_id| all_results.get(&doc_id).cloned())
-            .collect();
+/// How the excerpt around a match is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SnippetStrategy {
+    /// A window of the configured snippet length centered on the match.
+    #[default]
+    Window,
+    /// The whole sentences containing the match, followed (then preceded)
+    /// by neighbouring sentences up to `max_sentences` in total. A sentence
+    /// ends at `.`, `!` or `?` followed by whitespace and a capital letter.
+    /// The snippet length doesn't apply and no ellipses are added.
+    Sentence { max_sentences: usize },
+}
 
-        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
-        results
-    }
+/// Which document text snippets are drawn from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SnippetSource {
+    #[default]
+    Content,
+    Title,
+    /// The title and content joined by the searcher's full-text separator,
+    /// `" | "` unless changed with [`Searcher::with_full_text_separator`].
+    FullText,
+    /// The content when it matches the query, otherwise the full text
+    /// around the title match.
+    Auto,
+}
 
-    fn search_phrase(&self, terms: &[String]) -> Vec<SearchResult> {
-        if terms.is_empty() {
-            return Vec::new();
-        }
+/// Highlights query terms in text that isn't read from the index, such as
+/// content fetched from external storage at display time. Matching uses the
+/// same analysis as indexing, so it respects word boundaries, case folding
+/// and stemming.
+#[derive(Debug, Clone)]
+pub struct Highlighter {
+    tokenizer: Tokenizer,
+    terms: Vec<HighlightUnit>,
+    config: SnippetConfig,
+}
 
-        let first_term = &terms[0].to_lowercase();
-        let mut candidates = HashSet::new();
+/// Analyzed terms highlighted together, each with its position relative to
+/// the first; a single term for plain words.
+type HighlightUnit = Vec<(String, usize)>;
 
-        if let Some(posting_list) = self.index.get_posting_list(first_term) {
-            for posting in &posting_list.postings {
-                candidates.insert(posting.doc_id);
-            }
+impl Highlighter {
+    /// Analyzes each of `terms` with `tokenizer`; one that yields several
+    /// words is highlighted as a phrase. A term's index in `terms` fills
+    /// `{term}` placeholders.
+    pub fn new(tokenizer: Tokenizer, terms: &[&str], config: SnippetConfig) -> Self {
+        let terms = terms
+            .iter()
+            .map(|term| {
+                let tokens = tokenizer.tokenize(term);
+                let first = tokens.first().map_or(0, |token| token.position);
+                tokens
+                    .into_iter()
+                    .map(|token| (token.text, token.position - first))
+                    .collect()
+            })
+            .collect();
+        Self {
+            tokenizer,
+            terms,
+            config,
         }
+    }
 
-        for term in &terms[1..] {
-            let term = term.to_lowercase();
-            let mut new_candidates = HashSet::new();
+    pub fn highlight(&self, text: &str) -> String {
+        highlight_units(&self.tokenizer, text, &self.terms, &self.config)
+    }
+}
 
-            if let Some(posting_list) = self.index.get_posting_list(&term) {
-                for posting in &posting_list.postings {
-                    if candidates.contains(&posting.doc_id) {
-                        new_candidates.insert(posting.doc_id);
-                    }
-                }
-            }
+/// Corpus-wide statistics read once per searcher rather than per posting.
+#[derive(Debug, Clone, Copy)]
+struct CorpusStats {
+    total_documents: usize,
+    average_document_length: f64,
+}
 
-            candidates = new_candidates;
+#[derive(Clone)]
+pub struct Searcher<'a> {
+    index: &'a InvertedIndex,
+    options: ScoringOptions,
+    stats: CorpusStats,
+    /// Shared by clones of this searcher.
+    cache: Option<Arc<Mutex<ResultCache>>>,
+    drop_absent_terms: bool,
+    snippet_config: Option<SnippetConfig>,
+    /// `None` keeps the default window; see [`Self::with_snippet_length`].
+    snippet_length: Option<usize>,
+    snippet_length_unit: SnippetLengthUnit,
+    snippet_source: SnippetSource,
+    full_text_separator: String,
+    snippet_strategy: SnippetStrategy,
+    idf_computations: Cell<usize>,
+    /// Shared by every case-sensitive phrase this searcher matches.
+    case_preserving_tokenizer: OnceCell<Tokenizer>,
+}
+
+/// Ranked results whose titles and snippets are built lazily on `next()`.
+pub struct ResultStream<'a> {
+    searcher: Searcher<'a>,
+    query: Query,
+    hits: std::vec::IntoIter<(DocumentId, f64)>,
+    #[cfg(test)]
+    snippets_generated: usize,
+}
+
+impl ResultStream<'_> {
+    /// Number of results materialized so far.
+    #[cfg(test)]
+    fn snippets_generated(&self) -> usize {
+        self.snippets_generated
+    }
+
+    fn materialize(&mut self, doc_id: DocumentId, score: f64) -> Option<SearchResult> {
+        let result = self.searcher.build_result(&self.query, doc_id, score)?;
+        #[cfg(test)]
+        {
+            self.snippets_generated += 1;
         }
+        Some(result)
+    }
+}
 
-        let mut results = Vec::new();
-        for doc_id in candidates {
-            if let Some(doc) = self.index.get_document(doc_id) {
-                if self.contains_phrase(&doc.full_text(), terms) {
-                    let score = 1.0;
-                    let snippet = self.generate_snippet(&doc.content, &terms.join(" "));
-                    results.push(SearchResult {
-                        doc_id,
-                        score,
-                        title: doc.title.clone(),
-                        snippet,
-                    });
-                }
+impl Iterator for ResultStream<'_> {
+    type Item = SearchResult;
+
+    fn next(&mut self) -> Option<SearchResult> {
+        loop {
+            let (doc_id, score) = self.hits.next()?;
+            if let Some(result) = self.materialize(doc_id, score) {
+                return Some(result);
             }
         }
+    }
 
-        results
+    fn nth(&mut self, n: usize) -> Option<SearchResult> {
+        // Skipped hits never pay for snippet generation.
+        let (doc_id, score) = self.hits.nth(n)?;
+        self.materialize(doc_id, score).or_else(|| self.next())
     }
 
-    fn search_wildcard(&self, pattern: &str) -> Vec<SearchResult> {
-        let mut results = Vec::new();
-        let pattern_lower = pattern.to_lowercase();
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.hits.size_hint().1)
+    }
+}
 
-        let prefix = pattern_lower.trim_end_matches('*');
-        let suffix = pattern_lower.trim_start_matches('*');
-        let is_prefix = pattern_lower.ends_with('*') && !pattern_lower.starts_with('*');
-        let is_suffix = pattern_lower.starts_with('*') && !pattern_lower.ends_with('*');
-
-        for term in self.index.index.keys() {
-            let matches = if is_prefix {
-                term.starts_with(prefix)
-            } else if is_suffix {
-                term.ends_with(suffix)
-            } else {
-                term.contains(&pattern_lower.replace('*', ""))
-            };
+impl<'a> Searcher<'a> {
+    pub fn new(index: &'a InvertedIndex) -> Self {
+        Self::with_options(index, index.scoring_options().clone())
+    }
 
-            if matches {
-                results.extend(self.search_term(term));
-            }
+    /// Creates a searcher that scores with `options` instead of the index's
+    /// configured scoring options.
+    pub fn with_options(index: &'a InvertedIndex, options: ScoringOptions) -> Self {
+        let stats = CorpusStats {
+            total_documents: index.total_documents(),
+            average_document_length: index.average_document_length(),
+        };
+        Self {
+            index,
+            options,
+            stats,
+            cache: None,
+            drop_absent_terms: false,
+            snippet_config: None,
+            snippet_length: None,
+            snippet_length_unit: SnippetLengthUnit::Bytes,
+            snippet_source: SnippetSource::Content,
+            full_text_separator: " | ".to_string(),
+            snippet_strategy: SnippetStrategy::Window,
+            idf_computations: Cell::new(0),
+            case_preserving_tokenizer: OnceCell::new(),
         }
+    }
 
-        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
-        results.dedup_by_key(|r| r.doc_id);
-        results
+    /// Highlights matched terms in snippets according to `config`. Without
+    /// this, snippets are plain excerpts of the content.
+    pub fn with_snippet_config(mut self, config: SnippetConfig) -> Self {
+        self.snippet_config = Some(config);
+        self
     }
 
-    fn calculate_tfidf(
-        &self,
-        term_frequency: usize,
-        document_frequency: usize,
-        total_docs: usize,
-    ) -> f64 {
-        let tf = (term_frequency as f64).log10() + 1.0;
-        let idf = ((total_docs as f64) / (document_frequency as f64)).log10();
-        tf * idf
+    /// Limits snippets to about `length` units of content, the match
+    /// included and ellipses excluded. By default snippets take 50 bytes
+    /// either side of the match, widened to character boundaries, or the
+    /// first 100 characters of content when nothing matched.
+    pub fn with_snippet_length(mut self, length: usize, unit: SnippetLengthUnit) -> Self {
+        self.snippet_length = Some(length);
+        self.snippet_length_unit = unit;
+        self
     }
 
-    fn generate_snippet(&self, content: &str, query: &str) -> String {
-        let lower_content = content.to_lowercase();
-        let lower_query = query.to_lowercase();
+    /// Chooses which document text snippets are drawn from. Defaults to
+    /// [`SnippetSource::Content`].
+    pub fn with_snippet_source(mut self, source: SnippetSource) -> Self {
+        self.snippet_source = source;
+        self
+    }
 
-        if let Some(pos) = lower_content.find(&lower_query) {
-            let start = pos.saturating_sub(50);
-            let end = (pos + query.len() + 50).min(content.len());
+    /// Chooses how the excerpt around a match is cut. Defaults to
+    /// [`SnippetStrategy::Window`].
+    pub fn with_snippet_strategy(mut self, strategy: SnippetStrategy) -> Self {
+        self.snippet_strategy = strategy;
+        self
+    }
 
-            let mut snippet = String::new();
-            if start > 0 {
-                snippet.push_str("...");
-            }
-            snippet.push_str(&content[start..end]);
-            if end < content.len() {
-                snippet.push_str("...");
-            }
-            snippet
-        } else {
-            content.chars().take(100).collect::<String>() + "..."
-        }
+    /// Shows `separator` between title and content in
+    /// [`SnippetSource::FullText`] snippets; the default is `" | "`. Matches
+    /// and highlighted phrases never span the two fields, whatever the
+    /// separator.
+    pub fn with_full_text_separator(mut self, separator: impl Into<String>) -> Self {
+        self.full_text_separator = separator.into();
+        self
     }
 
-    fn contains_phrase(&self, text: &str, terms: &[String]) -> bool {
-        let text_lower = text.to_lowercase();
-        let phrase_lower = terms.join(" ").to_lowercase();
-        text_lower.contains(&phrase_lower)
+    /// Makes [`Self::search_response`] drop AND clauses whose term is missing
+    /// from the vocabulary instead of returning nothing, reporting them in
+    /// [`SearchResponse::absent_terms`].
+    pub fn with_absent_terms_dropped(mut self) -> Self {
+        self.drop_absent_terms = true;
+        self
     }
-}
 
-impl InvertedIndex {
-    pub fn search_tfidf(&self, query: &str) -> Vec<SearchResult> {
-        let searcher = Searcher::new(self);
-        searcher.search(query)
+    /// Caches up to `capacity` ranked result lists, evicting the least
+    /// recently used. Entries are discarded once the index changes.
+    pub fn with_result_cache(mut self, capacity: usize) -> Self {
+        self.cache = Some(Arc::new(Mutex::new(ResultCache::new(
+            capacity,
+            self.index.generation(),
+        ))));
+        self
     }
 
-    pub fn boolean_search(
-        &self,
-        operator: BooleanOperator,
-        queries: Vec<&str>,
-    ) -> Vec<SearchResult> {
-        let query = Query::Boolean {
-            operator,
-            queries: queries
-                .into_iter()
-                .map(|q| Query::Term(q.to_string()))
-                .collect(),
-        };
-        let searcher = Searcher::new(self);
-        searcher.search_with_query(&query)
+    /// Rebinds this searcher, options and result cache included, to `index`
+    /// (typically the same index after it was modified). Cached results are
+    /// dropped if the index generation has moved on.
+    pub fn reopen(self, index: &InvertedIndex) -> Searcher<'_> {
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().sync_generation(index.generation());
+        }
+        Searcher {
+            cache: self.cache,
+            drop_absent_terms: self.drop_absent_terms,
+            snippet_config: self.snippet_config,
+            snippet_length: self.snippet_length,
+            snippet_length_unit: self.snippet_length_unit,
+            snippet_source: self.snippet_source,
+            full_text_separator: self.full_text_separator,
+            snippet_strategy: self.snippet_strategy,
+            ..Searcher::with_options(index, self.options)
+        }
     }
 
-    pub fn phrase_search(&self, phrase: &str) -> Vec<SearchResult> {
-        let terms: Vec<String> = phrase.split_whitespace().map(|s| s.to_string()).collect();
-        let query = Query::Phrase(terms);
-        let searcher = Searcher::new(self);
-        searcher.search_with_query(&query)
+    /// Result cache counters, or `None` if caching is not enabled.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache
+            .as_ref()
+            .map(|cache| cache.lock().unwrap().stats())
     }
 
-    pub fn wildcard_search(&self, pattern: &str) -> Vec<SearchResult> {
-        let query = Query::Wildcard(pattern.to_string());
-        let searcher = Searcher::new(self);
-        searcher.search_with_query(&query)
+    /// Caps the raw term frequency counted per document, whatever the
+    /// scoring model; shorthand for [`ScoringOptions::max_effective_tf`].
+    pub fn set_tf_cap(&mut self, cap: Option<usize>) {
+        self.options.max_effective_tf = cap;
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use core::panic;
+    /// Builder form of [`Self::set_tf_cap`].
+    pub fn with_tf_cap(mut self, cap: Option<usize>) -> Self {
+        self.set_tf_cap(cap);
+        self
+    }
 
-    use super::*;
+    /// Number of term idfs this searcher computed itself because the index's
+    /// idf cache was cold. Zero after [`InvertedIndex::warm_up`].
+    pub fn idf_computations(&self) -> usize {
+        self.idf_computations.get()
+    }
 
-    fn create_test_index() -> InvertedIndex {
-        let mut index = InvertedIndex::new();
+    pub fn search(&self, query: &str) -> Vec<SearchResult> {
+        let query = Query::Term(query.to_string());
+        self.execute_query(&query)
+    }
 
-        index.add_document(
-            "AI Research".to_string(),
-            "artificial intelligence research methods".to_string(),
-        );
-        index.add_document(
-            "Machine Learning".to_string(),
-            "machine learning algorithms and techniques".to_string(),
-        );
-        index.add_document(
-            "Deep Learning".to_string(),
-            "deep learning neural networks".to_string(),
-        );
-        index.add_document(
-            "Data Science".to_string(),
-            "data science and machine learning applications".to_string(),
-        );
-        index.add_document(
-            "Search Engines".to_string(),
-            "search engine algorithms and information retrieval".to_string(),
-        );
+    /// Searches for `word` as written (after case folding and the like, but
+    /// before stemming), falling back to stemmed matching when no document
+    /// has the exact form. Needs a tokenizer with
+    /// [`Tokenizer::set_dual_index`] enabled to tell forms apart; otherwise
+    /// this is the same as [`Self::search`]. `word` must analyze to a single
+    /// token: input of several words matches nothing.
+    pub fn search_exact(&self, word: &str) -> Vec<SearchResult> {
+        let query = Query::Term(word.to_string());
+        let mut tokens = self.index.tokenizer().tokenize_iter(word);
+        let (Some(token), None) = (tokens.next(), tokens.next()) else {
+            return Vec::new();
+        };
+        let exact = token.exact().unwrap_or(&token.text);
+        let frequencies: Vec<(DocumentId, usize)> = self
+            .index
+            .index
+            .get(&token.text)
+            .into_iter()
+            .flat_map(|posting_list| &posting_list.postings)
+            .filter_map(|posting| {
+                let tf = posting
+                    .positions
+                    .iter()
+                    .filter(|p| p.exact().unwrap_or(&token.text) == exact)
+                    .count();
+                (tf > 0).then_some((posting.doc_id, tf))
+            })
+            .collect();
+        if frequencies.is_empty() {
+            return self.execute_query(&query);
+        }
 
-        index
+        let idf = self.idf(frequencies.len());
+        let scores = frequencies
+            .into_iter()
+            .map(|(doc_id, tf)| (doc_id, self.score_with_idf(tf, idf, doc_id)))
+            .collect();
+        self.order_hits(scores)
+            .into_iter()
+            .filter_map(|(doc_id, score)| self.build_result(&query, doc_id, score))
+            .collect()
     }
 
-    #[test]
-    fn test_search_result_creation() {
-        let result = SearchResult {
-            doc_id: 1,
-            score: 0.85,
-            title: "Test Document".to_string(),
-            snippet: "This is a test snippet".to_string(),
+    /// Searches for documents containing every analyzed word of `query` and
+    /// reports the words that analysis dropped.
+    pub fn search_response(&self, query: &str) -> SearchResponse {
+        let tokens = self.index.tokenizer().tokenize(query);
+        let surface = |start: usize, end: usize| {
+            query[char_to_byte_offset(query, start)..char_to_byte_offset(query, end)].to_string()
         };
 
-        assert_eq!(result.doc_id, 1);
+        let mut clauses: Vec<Query> = tokens
+            .iter()
+            .map(|token| Query::Term(surface(token.start_offset, token.end_offset)))
+            .collect();
+        let mut response = match clauses.len() {
+            0 => SearchResponse {
+                results: Vec::new(),
+                dropped_terms: Vec::new(),
+                absent_terms: Vec::new(),
+            },
+            1 => self.search_response_with_query(&clauses.remove(0)),
+            _ => self.search_response_with_query(&Query::Boolean {
+                operator: self.index.default_operator().clone(),
+                queries: clauses,
+            }),
+        };
+        // A word is dropped only when analysis keeps none of it, so
+        // "borrow-checker" counts as kept even though it yields two tokens.
+        response.dropped_terms = whitespace_words(query)
+            .into_iter()
+            .filter(|(start, end)| {
+                !tokens
+                    .iter()
+                    .any(|t| (*start..*end).contains(&t.start_offset))
+            })
+            .map(|(start, end)| surface(start, end))
+            .collect();
+        response
+    }
+
+    /// Runs `query`, first rewriting away absent AND terms if
+    /// [`Self::with_absent_terms_dropped`] is set.
+    pub fn search_response_with_query(&self, query: &Query) -> SearchResponse {
+        let mut absent_terms = Vec::new();
+        let results = if self.drop_absent_terms {
+            self.execute_query(&self.drop_absent_and_terms(query, &mut absent_terms))
+        } else {
+            self.execute_query(query)
+        };
+
+        SearchResponse {
+            results,
+            dropped_terms: Vec::new(),
+            absent_terms,
+        }
+    }
+
+    /// Removes term clauses missing from the vocabulary from every AND in
+    /// `query`, recording them in `absent`. An AND whose terms are all absent
+    /// is left alone, since dropping everything would change its meaning.
+    fn drop_absent_and_terms(&self, query: &Query, absent: &mut Vec<String>) -> Query {
+        match query {
+            Query::Boolean { operator, queries } => {
+                let mut queries: Vec<Query> = queries
+                    .iter()
+                    .map(|q| self.drop_absent_and_terms(q, absent))
+                    .collect();
+                if matches!(operator, BooleanOperator::And) {
+                    let is_absent = |q: &Query| {
+                        matches!(q, Query::Term(term)
+                            if !self.index.index.contains_key(&self.index.normalize_term(term)))
+                    };
+                    if !queries.iter().all(is_absent) {
+                        queries.retain(|q| match q {
+                            Query::Term(term) if is_absent(q) => {
+                                absent.push(term.clone());
+                                false
+                            }
+                            _ => true,
+                        });
+                    }
+                }
+                Query::Boolean {
+                    operator: operator.clone(),
+                    queries,
+                }
+            }
+            other => other.clone(),
+        }
+    }
+
+    /// Parses `input` with [`parse_query`] and joins its top-level terms and
+    /// phrases with `operator` instead of the implicit AND. Each clause,
+    /// phrases included, contributes its own score.
+    pub fn search_boolean(
+        &self,
+        operator: BooleanOperator,
+        input: &str,
+    ) -> Result<Vec<SearchResult>, QueryError> {
+        self.try_search_with_query(&parse_query_with_operator(input, operator)?)
+    }
+
+    pub fn search_with_query(&self, query: &Query) -> Vec<SearchResult> {
+        self.execute_query(query)
+    }
+
+    /// Like [`Self::search_with_query`], but first rejects queries that
+    /// exceed the index's limits.
+    pub fn try_search_with_query(&self, query: &Query) -> Result<Vec<SearchResult>, QueryError> {
+        self.check_limits(query)?;
+        Ok(self.execute_query(query))
+    }
+
+    fn check_limits(&self, query: &Query) -> Result<(), QueryError> {
+        match query {
+            Query::Phrase(terms) | Query::PhraseWith { terms, .. } => {
+                match self.index.max_phrase_terms() {
+                    Some(max) if terms.len() > max => Err(QueryError::PhraseTooLong {
+                        terms: terms.len(),
+                        max,
+                    }),
+                    _ => Ok(()),
+                }
+            }
+            Query::Boolean { queries, .. } => queries.iter().try_for_each(|q| self.check_limits(q)),
+            Query::SpanNear { clauses, .. } => {
+                clauses.iter().try_for_each(|q| self.check_limits(q))
+            }
+            Query::FieldScoped(clauses) => {
+                clauses.iter().try_for_each(|(_, q)| self.check_limits(q))
+            }
+            Query::Boost { query, .. } => self.check_limits(query),
+            Query::Proximity { terms, window } => {
+                let [phrase, ..] = proximity_tiers(terms, *window);
+                self.check_limits(&phrase)
+            }
+            Query::Term(_)
+            | Query::IndexedTerm(_)
+            | Query::Wildcard(_)
+            | Query::FieldTerm { .. }
+            | Query::MatchAll
+            | Query::MatchNone => Ok(()),
+        }
+    }
+
+    /// Like [`Self::search_with_query`], also summarizing the result scores.
+    pub fn search_with_distribution(
+        &self,
+        query: &Query,
+    ) -> (Vec<SearchResult>, ScoreDistribution) {
+        let results = self.execute_query(query);
+        let distribution = ScoreDistribution::of(&results);
+        (results, distribution)
+    }
+
+    /// Runs `query`, then replaces each hit's score with what `rerank`
+    /// returns for it and its document, and re-sorts the hits. Ties are
+    /// broken by ascending document id, as in every other ranking, and NaN
+    /// scores are ordered by [`f64::total_cmp`] instead of panicking.
+    pub fn search_reranked(
+        &self,
+        query: &Query,
+        rerank: impl Fn(&SearchResult, &Document) -> f64,
+    ) -> Vec<SearchResult> {
+        let mut results = self.execute_query(query);
+
+        for result in &mut results {
+            if let Some(doc) = self.index.get_document(result.doc_id) {
+                result.score = rerank(result, doc);
+            }
+        }
+
+        results.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.doc_id.cmp(&b.doc_id)));
+        if self.options.ascending {
+            results.reverse();
+        }
+        results
+    }
+
+    /// Ranks documents containing any analyzed word of `query` with BM25F:
+    /// each field's term frequency is normalized by that field's length
+    /// against its average and scaled by its weight before saturation, so a
+    /// match in a short title can outweigh one buried in a long body. Fields
+    /// missing from `field_weights` are ignored. `k1` and `b` come from the
+    /// searcher's BM25 options, or the customary defaults otherwise.
+    pub fn search_bm25f(
+        &self,
+        query: &str,
+        field_weights: &[(FieldType, f64)],
+    ) -> Vec<SearchResult> {
+        let (k1, b) = match self.options.model {
+            ScoringModel::Bm25 { k1, b } => (k1, b),
+            ScoringModel::TfIdf => (1.2, 0.75),
+        };
+        let average_lengths: Vec<f64> = field_weights
+            .iter()
+            .map(|(field, _)| self.index.average_field_length(field))
+            .collect();
+
+        let terms: Vec<String> = self
+            .index
+            .tokenizer()
+            .tokenize_iter(query)
+            .map(|token| token.text)
+            .collect();
+        let mut scores: HashMap<DocumentId, f64> = HashMap::new();
+        for term in &terms {
+            let Some(posting_list) = self.index.index.get(term) else {
+                continue;
+            };
+            let idf = self.idf(posting_list.document_frequency).bm25;
+            for posting in &posting_list.postings {
+                let weighted_tf: f64 = field_weights
+                    .iter()
+                    .zip(&average_lengths)
+                    .map(|((field, weight), &average)| {
+                        let tf = posting
+                            .positions
+                            .iter()
+                            .filter(|p| &p.field == field)
+                            .count();
+                        if tf == 0 {
+                            return 0.0;
+                        }
+                        let length = self.index.field_length(posting.doc_id, field) as f64;
+                        weight * tf as f64 / (1.0 - b + b * length / average)
+                    })
+                    .sum();
+                if weighted_tf > 0.0 {
+                    *scores.entry(posting.doc_id).or_insert(0.0) +=
+                        idf * weighted_tf / (k1 + weighted_tf);
+                }
+            }
+        }
+
+        let snippet_query = Query::Boolean {
+            operator: BooleanOperator::Or,
+            queries: terms.into_iter().map(Query::Term).collect(),
+        };
+        self.order_hits(scores)
+            .into_iter()
+            .filter_map(|(doc_id, score)| self.build_result(&snippet_query, doc_id, score))
+            .collect()
+    }
+
+    /// Searches for terms that are already analyzed, skipping query
+    /// analysis: each term must match a vocabulary entry exactly, so the
+    /// caller is responsible for lowercasing, stemming and the like. Terms
+    /// combine like the clauses of a [`Query::Boolean`]; with `Not`, the
+    /// first term's documents are kept unless they contain any other term.
+    pub fn search_terms(&self, terms: &[String], operator: BooleanOperator) -> Vec<SearchResult> {
+        let mut clauses: Vec<Query> = terms.iter().cloned().map(Query::IndexedTerm).collect();
+        let query = match operator {
+            BooleanOperator::Not if clauses.len() <= 1 => clauses.pop().unwrap_or(Query::MatchNone),
+            BooleanOperator::Not => {
+                let excluded = clauses.split_off(1);
+                Query::Boolean {
+                    operator,
+                    queries: vec![
+                        clauses.remove(0),
+                        Query::Boolean {
+                            operator: BooleanOperator::Or,
+                            queries: excluded,
+                        },
+                    ],
+                }
+            }
+            operator => Query::Boolean {
+                operator,
+                queries: clauses,
+            },
+        };
+        self.execute_query(&query)
+    }
+
+    /// Ranks other documents by cosine similarity of their TF-IDF vectors to
+    /// the vector of `doc_id`. The source document is never returned.
+    pub fn more_like_this(&self, doc_id: DocumentId, limit: usize) -> Vec<SearchResult> {
+        let source = self.tfidf_vector(doc_id);
+        let source_norm = source.values().map(|w| w * w).sum::<f64>().sqrt();
+        if source_norm == 0.0 {
+            return Vec::new();
+        }
+
+        let mut dot_products: HashMap<DocumentId, f64> = HashMap::new();
+        for (term, weight) in &source {
+            if let Some(posting_list) = self.index.index.get(term) {
+                for posting in &posting_list.postings {
+                    if posting.doc_id == doc_id {
+                        continue;
+                    }
+                    let other_weight = self.calculate_tfidf(
+                        posting.term_frequency,
+                        posting_list.document_frequency,
+                        self.stats.total_documents,
+                    );
+                    *dot_products.entry(posting.doc_id).or_insert(0.0) += weight * other_weight;
+                }
+            }
+        }
+
+        let norms = self.tfidf_norms(dot_products.keys().copied().collect());
+        let mut hits: Vec<(DocumentId, f64)> = dot_products
+            .into_iter()
+            .filter_map(|(other_id, dot)| {
+                let other_norm = norms[&other_id];
+                (other_norm > 0.0 && dot > 0.0)
+                    .then(|| (other_id, dot / (source_norm * other_norm)))
+            })
+            .collect();
+        hits.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+        hits.truncate(limit);
+
+        // Anchor snippets on the source document's most distinctive terms.
+        let mut source_terms: Vec<(&String, &f64)> = source.iter().collect();
+        source_terms.sort_by(|a, b| b.1.partial_cmp(a.1).unwrap().then(a.0.cmp(b.0)));
+        let snippet_query = Query::Boolean {
+            operator: BooleanOperator::Or,
+            queries: source_terms
+                .into_iter()
+                .map(|(term, _)| Query::Term(term.clone()))
+                .collect(),
+        };
+
+        hits.into_iter()
+            .filter_map(|(other_id, score)| self.build_result(&snippet_query, other_id, score))
+            .collect()
+    }
+
+    fn tfidf_vector(&self, doc_id: DocumentId) -> HashMap<String, f64> {
+        self.index
+            .document_term_vector(doc_id)
+            .into_iter()
+            .map(|(term, tf)| {
+                let df = self.index.index[&term].document_frequency;
+                let weight = self.tfidf_weight(tf, self.term_idf(&term, df).tfidf);
+                (term, weight)
+            })
+            .collect()
+    }
+
+    /// The length of each document's [`Self::tfidf_vector`], gathered in a
+    /// single pass over the postings.
+    fn tfidf_norms(&self, doc_ids: HashSet<DocumentId>) -> HashMap<DocumentId, f64> {
+        let mut squares: HashMap<DocumentId, f64> = HashMap::new();
+        for (term, posting_list) in &self.index.index {
+            let mut idf = None;
+            for posting in &posting_list.postings {
+                if !doc_ids.contains(&posting.doc_id) {
+                    continue;
+                }
+                let idf = *idf.get_or_insert_with(|| {
+                    self.term_idf(term, posting_list.document_frequency).tfidf
+                });
+                let weight = self.tfidf_weight(posting.term_frequency, idf);
+                *squares.entry(posting.doc_id).or_insert(0.0) += weight * weight;
+            }
+        }
+        doc_ids
+            .into_iter()
+            .map(|doc_id| (doc_id, squares.get(&doc_id).copied().unwrap_or(0.0).sqrt()))
+            .collect()
+    }
+
+    /// The `n` terms of `doc_id` with the highest TF-IDF weight, highest
+    /// first, ties broken alphabetically.
+    pub fn top_terms_for_document(&self, doc_id: DocumentId, n: usize) -> Vec<(String, f64)> {
+        let mut terms: Vec<(String, f64)> = self.tfidf_vector(doc_id).into_iter().collect();
+        terms.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+        terms.truncate(n);
+        terms
+    }
+
+    /// Tallies the terms `queries` analyze to, as the index would search for
+    /// them; handy for mining query logs for popular terms. Each query is
+    /// parsed like user input, so field names, boosts and quotes are not
+    /// counted, and queries that fail to parse are skipped. Stop words and
+    /// other discarded words are not counted either.
+    pub fn term_frequencies_over(
+        &self,
+        queries: impl IntoIterator<Item = String>,
+    ) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        let operator = self.index.default_operator();
+        for query in queries {
+            if let Ok(query) = parse_query_with_operator(&query, operator.clone()) {
+                self.tally_terms(&query, &mut counts);
+            }
+        }
+        counts
+    }
+
+    /// Adds the analyzed words of every term and phrase in `query`.
+    fn tally_terms(&self, query: &Query, counts: &mut HashMap<String, usize>) {
+        let words: &[String] = match query {
+            Query::Term(term) | Query::FieldTerm { term, .. } => std::slice::from_ref(term),
+            Query::Phrase(terms) | Query::PhraseWith { terms, .. } => terms,
+            Query::Boost { query, .. } => return self.tally_terms(query, counts),
+            Query::Boolean { queries, .. } => {
+                queries.iter().for_each(|q| self.tally_terms(q, counts));
+                return;
+            }
+            Query::FieldScoped(clauses) => {
+                clauses
+                    .iter()
+                    .for_each(|(_, q)| self.tally_terms(q, counts));
+                return;
+            }
+            _ => return,
+        };
+        for word in words {
+            for token in self.index.tokenizer().tokenize_iter(word) {
+                *counts.entry(token.text).or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// The score `doc_id` gets for `query` in this searcher's results,
+    /// whether or not it would rank near the top; `None` if it doesn't match.
+    pub fn score(&self, query: &Query, doc_id: DocumentId) -> Option<f64> {
+        self.score_query(query).get(&doc_id).copied()
+    }
+
+    /// Explains how `doc_id` scored for `query`, or `None` if it doesn't match.
+    pub fn explain(&self, query: &Query, doc_id: DocumentId) -> Option<ScoreExplanation> {
+        let score = *self.score_query(query).get(&doc_id)?;
+        let mut contributions = Vec::new();
+        self.collect_contributions(query, doc_id, None, 1.0, &mut contributions);
+        Some(ScoreExplanation {
+            doc_id,
+            score,
+            contributions,
+        })
+    }
+
+    /// Records the boosted score of every leaf clause of `query` that matched
+    /// `doc_id`, descending only into clauses the document satisfied so that
+    /// the contributions add up to the score actually assigned.
+    fn collect_contributions(
+        &self,
+        query: &Query,
+        doc_id: DocumentId,
+        field: Option<&FieldType>,
+        weight: f64,
+        contributions: &mut Vec<ScoreContribution>,
+    ) {
+        let source = match query {
+            Query::Term(_)
+            | Query::IndexedTerm(_)
+            | Query::FieldTerm { .. }
+            | Query::Wildcard(_) => ContributionSource::Term,
+            Query::Phrase(_) | Query::PhraseWith { .. } => ContributionSource::Phrase,
+            Query::SpanNear { .. } | Query::Proximity { .. } => ContributionSource::Proximity,
+            Query::MatchAll => ContributionSource::MatchAll,
+            Query::MatchNone => return,
+            Query::Boost { query, boost } => {
+                return self.collect_contributions(
+                    query,
+                    doc_id,
+                    field,
+                    weight * boost,
+                    contributions,
+                );
+            }
+            Query::Boolean { operator, queries } => {
+                let queries = self.active_clauses(operator, queries);
+                let matched: Vec<(&Query, f64)> = scoring_clauses(operator, &queries)
+                    .iter()
+                    .filter_map(|q| {
+                        let scores = self.score_query_in(q, field);
+                        scores
+                            .contains_key(&doc_id)
+                            .then(|| (q, self.clause_scale(&scores)))
+                    })
+                    .collect();
+                let weight = weight * self.coverage_factor(operator, matched.len(), queries.len());
+                for (q, scale) in matched {
+                    self.collect_contributions(q, doc_id, field, weight * scale, contributions);
+                }
+                return;
+            }
+            Query::FieldScoped(clauses) => {
+                for (clause_field, clause) in clauses {
+                    if field.is_none_or(|field| field == clause_field) {
+                        self.collect_contributions(
+                            clause,
+                            doc_id,
+                            Some(clause_field),
+                            weight,
+                            contributions,
+                        );
+                    }
+                }
+                return;
+            }
+        };
+
+        if let Some(score) = self.score_query_in(query, field).get(&doc_id) {
+            contributions.push(ScoreContribution {
+                source,
+                query: query.clone(),
+                score: score * weight,
+            });
+        }
+    }
+
+    /// [`Query::optimize`]s `query`, then orders every `And`'s clauses by
+    /// ascending [`Self::estimate_hits`] so the rarest is evaluated first
+    /// and an empty one ends the evaluation early.
+    pub fn optimize(&self, query: Query) -> Query {
+        self.order_rarest_first(query.optimize())
+    }
+
+    fn order_rarest_first(&self, query: Query) -> Query {
+        match query {
+            Query::Boolean { operator, queries } => {
+                let mut queries: Vec<Query> = queries
+                    .into_iter()
+                    .map(|q| self.order_rarest_first(q))
+                    .collect();
+                if operator == BooleanOperator::And {
+                    queries.sort_by_cached_key(|q| self.estimate_hits(q));
+                }
+                Query::Boolean { operator, queries }
+            }
+            Query::Boost { query, boost } => Query::Boost {
+                query: Box::new(self.order_rarest_first(*query)),
+                boost,
+            },
+            Query::FieldScoped(clauses) => Query::FieldScoped(
+                clauses
+                    .into_iter()
+                    .map(|(field, q)| (field, self.order_rarest_first(q)))
+                    .collect(),
+            ),
+            other => other,
+        }
+    }
+
+    /// Upper bound on the number of documents `query` can match, computed from
+    /// posting counts without scoring. AND takes the smallest clause count
+    /// and OR the sum, so OR over-counts documents matching several clauses.
+    pub fn estimate_hits(&self, query: &Query) -> usize {
+        let posting_count = |term: &str| {
+            self.index
+                .index
+                .get(term)
+                .map_or(0, |list| list.postings.len())
+        };
+
+        let estimate = match query {
+            Query::Term(term) | Query::FieldTerm { term, .. } => {
+                posting_count(&self.index.normalize_term(term))
+            }
+            Query::IndexedTerm(term) => posting_count(term),
+            Query::Phrase(terms) | Query::PhraseWith { terms, .. } => self
+                .analyze_terms(terms)
+                .iter()
+                .map(|(term, _)| posting_count(term))
+                .min()
+                .unwrap_or(0),
+            Query::Wildcard(pattern) => self
+                .wildcard_terms(&pattern.to_lowercase())
+                .iter()
+                .map(|term| posting_count(term))
+                .sum(),
+            Query::Boolean { operator, queries } => match operator {
+                BooleanOperator::And => self.estimate_min(queries),
+                BooleanOperator::Or => queries.iter().map(|q| self.estimate_hits(q)).sum(),
+                BooleanOperator::Not if queries.len() == 1 => self.stats.total_documents,
+                BooleanOperator::Not => queries.first().map_or(0, |q| self.estimate_hits(q)),
+            },
+            Query::SpanNear { clauses, .. } => self.estimate_min(clauses),
+            Query::FieldScoped(clauses) => self.estimate_min(clauses.iter().map(|(_, q)| q)),
+            Query::Boost { query, .. } => self.estimate_hits(query),
+            Query::Proximity { terms, window } => {
+                let [.., all] = proximity_tiers(terms, *window);
+                self.estimate_hits(&all)
+            }
+            Query::MatchAll => self.stats.total_documents,
+            Query::MatchNone => 0,
+        };
+        estimate.min(self.stats.total_documents)
+    }
+
+    fn estimate_min<'q>(&self, queries: impl IntoIterator<Item = &'q Query>) -> usize {
+        queries
+            .into_iter()
+            .map(|q| self.estimate_hits(q))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Results of `query` ordered by `sorts`: each key breaks the ties of the
+    /// ones before it, and remaining ties go to the lower document id.
+    pub fn search_multi_sorted(&self, query: &Query, sorts: &[SortSpec]) -> Vec<SearchResult> {
+        let mut results = self.execute_query(query);
+        results.sort_by(|a, b| {
+            sorts
+                .iter()
+                .map(|sort| sort.compare(a, b, self.index))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| a.doc_id.cmp(&b.doc_id))
+        });
+        results
+    }
+
+    /// Runs each query, scales its scores by the paired weight and ranks
+    /// documents by the weighted sum across all of them. Snippets highlight
+    /// matches of any of the queries.
+    pub fn combine(&self, weighted: &[(Query, f64)]) -> Vec<SearchResult> {
+        let mut combined: HashMap<DocumentId, f64> = HashMap::new();
+        for (query, weight) in weighted {
+            for (doc_id, score) in self.score_query(query) {
+                *combined.entry(doc_id).or_insert(0.0) += score * weight;
+            }
+        }
+        let snippet_query = Query::Boolean {
+            operator: BooleanOperator::Or,
+            queries: weighted.iter().map(|(query, _)| query.clone()).collect(),
+        };
+        self.order_hits(combined)
+            .into_iter()
+            .filter_map(|(doc_id, score)| self.build_result(&snippet_query, doc_id, score))
+            .collect()
+    }
+
+    /// Up to `limit` ranked results starting at `offset`, plus the total
+    /// number of matches. Only the returned page pays for snippets.
+    pub fn search_page(
+        &self,
+        query: &Query,
+        offset: usize,
+        limit: usize,
+    ) -> (Vec<SearchResult>, usize) {
+        let hits = self.rank(query);
+        let total = hits.len();
+        let page = hits
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .filter_map(|(doc_id, score)| self.build_result(query, doc_id, score))
+            .collect();
+        (page, total)
+    }
+
+    /// Runs `query` and returns a stream that materializes titles and
+    /// snippets only as results are consumed.
+    pub fn result_stream(&self, query: &Query) -> ResultStream<'a> {
+        ResultStream {
+            searcher: self.clone(),
+            query: query.clone(),
+            hits: self.rank(query).into_iter(),
+            #[cfg(test)]
+            snippets_generated: 0,
+        }
+    }
+
+    fn execute_query(&self, query: &Query) -> Vec<SearchResult> {
+        let Some(cache) = &self.cache else {
+            return self.build_results(query);
+        };
+
+        let key = self.cache_key(query);
+        if let Some(results) = cache.lock().unwrap().get(&key) {
+            return results;
+        }
+        let results = self.build_results(query);
+        cache.lock().unwrap().insert(key, results.clone());
+        results
+    }
+
+    fn build_results(&self, query: &Query) -> Vec<SearchResult> {
+        self.rank(query)
+            .into_iter()
+            .filter_map(|(doc_id, score)| self.build_result(query, doc_id, score))
+            .collect()
+    }
+
+    /// Identifies a query together with the scoring options it is ranked
+    /// under and the snippet settings its results are rendered with, since
+    /// clones share the cache. Plain terms are keyed by their analyzed form
+    /// so that e.g. "Rust" and "rust" share an entry.
+    fn cache_key(&self, query: &Query) -> String {
+        let query = match query {
+            Query::Term(term) => format!("Term({:?})", self.index.normalize_term(term)),
+            other => format!("{:?}", other),
+        };
+        format!(
+            "{}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}",
+            query,
+            self.options,
+            self.snippet_config,
+            self.snippet_length,
+            self.snippet_length_unit,
+            self.snippet_source,
+            self.snippet_strategy,
+            self.full_text_separator,
+        )
+    }
+
+    /// Scores `query` and orders the hits as [`Self::order_hits`] does.
+    fn rank(&self, query: &Query) -> Vec<(DocumentId, f64)> {
+        self.order_hits(self.score_query(query))
+    }
+
+    /// Orders hits by descending score, breaking ties by ascending document
+    /// id so rankings are deterministic, or the exact reverse when
+    /// [`ScoringOptions::ascending`] is set.
+    ///
+    /// Documents expired as of [`ScoringOptions::as_of`] are dropped first,
+    /// for callers whose scores don't come from [`Self::score_query`].
+    fn order_hits(&self, scores: HashMap<DocumentId, f64>) -> Vec<(DocumentId, f64)> {
+        let mut hits: Vec<(DocumentId, f64)> = scores
+            .into_iter()
+            .filter(|(doc_id, _)| !self.is_expired(*doc_id))
+            .collect();
+        hits.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+        if self.options.ascending {
+            hits.reverse();
+        }
+        hits
+    }
+
+    fn is_expired(&self, doc_id: DocumentId) -> bool {
+        let (Some(as_of), Some(key)) = (self.options.as_of, &self.options.expiration_key) else {
+            return false;
+        };
+        self.index
+            .get_document(doc_id)
+            .and_then(|doc| doc.metadata.get(key))
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .is_some_and(|expires_at| expires_at < as_of)
+    }
+
+    /// Scores `query` over the whole document, leaving out expired documents.
+    fn score_query(&self, query: &Query) -> HashMap<DocumentId, f64> {
+        let mut scores = self.score_query_in(query, None);
+        if self.options.as_of.is_some() {
+            scores.retain(|doc_id, _| !self.is_expired(*doc_id));
+        }
+        scores
+    }
+
+    /// Scores `query`, counting only matches inside `field` when given.
+    fn score_query_in(&self, query: &Query, field: Option<&FieldType>) -> HashMap<DocumentId, f64> {
+        match query {
+            Query::Term(term) => {
+                self.score_query_in(&Query::IndexedTerm(self.index.normalize_term(term)), field)
+            }
+            Query::IndexedTerm(term) => {
+                if self.is_auto_stop_word(term) {
+                    return HashMap::new();
+                }
+                self.score_indexed_term_in(term, field)
+            }
+            Query::Boolean { operator, queries } => {
+                self.score_boolean(operator, &self.active_clauses(operator, queries), field)
+            }
+            Query::Phrase(terms) => self.score_phrase(terms, field, PhraseOptions::default()),
+            Query::PhraseWith { terms, options } => self.score_phrase(terms, field, *options),
+            Query::Wildcard(pattern) => self.score_wildcard(pattern, field),
+            Query::SpanNear { clauses, .. } => self.score_span_near(query, clauses, field),
+            Query::Proximity { terms, window } => self.score_proximity(terms, *window, field),
+            Query::FieldTerm {
+                field: term_field,
+                term,
+            } => self.score_field_scoped(field, term_field, &Query::Term(term.clone())),
+            Query::FieldScoped(clauses) => {
+                let scores: Vec<HashMap<DocumentId, f64>> = clauses
+                    .iter()
+                    .map(|(clause_field, clause)| {
+                        self.score_field_scoped(field, clause_field, clause)
+                    })
+                    .collect();
+                intersect_scores(scores)
+            }
+            Query::Boost { query, boost } => {
+                let mut scores = self.score_query_in(query, field);
+                scores.values_mut().for_each(|score| *score *= boost);
+                scores
+            }
+            Query::MatchAll => self.index.document_ids().map(|id| (id, 1.0)).collect(),
+            Query::MatchNone => HashMap::new(),
+        }
+    }
+
+    /// Scores `query` within `inner`, which can match nothing if an enclosing
+    /// scope already restricts matching to a different field.
+    fn score_field_scoped(
+        &self,
+        outer: Option<&FieldType>,
+        inner: &FieldType,
+        query: &Query,
+    ) -> HashMap<DocumentId, f64> {
+        match outer {
+            Some(outer) if outer != inner => HashMap::new(),
+            _ => self.score_query_in(query, Some(inner)),
+        }
+    }
+
+    fn build_result(&self, query: &Query, doc_id: DocumentId, score: f64) -> Option<SearchResult> {
+        let doc = self.index.get_document(doc_id)?;
+        let snippet = self.snippet_for(query, doc);
+        let mut snippet = match &self.snippet_config {
+            Some(config) => self.highlight(&snippet, &self.highlight_terms(query), config),
+            None => snippet,
+        };
+        if matches!(
+            self.snippet_source,
+            SnippetSource::FullText | SnippetSource::Auto
+        ) {
+            snippet = snippet.replace(FIELD_SEPARATOR, &self.full_text_separator);
+        }
+
+        Some(SearchResult {
+            doc_id,
+            score,
+            title: doc.title.clone(),
+            snippet,
+        })
+    }
+
+    /// Scores a term exactly as it appears in the vocabulary, skipping query
+    /// analysis. When `field` is given only occurrences in that field count
+    /// towards tf and df.
+    fn score_indexed_term_in(
+        &self,
+        term: &str,
+        field: Option<&FieldType>,
+    ) -> HashMap<DocumentId, f64> {
+        let Some(posting_list) = self.index.index.get(term) else {
+            return HashMap::new();
+        };
+
+        let frequencies: Vec<(&PostingEntry, usize)> = posting_list
+            .postings
+            .iter()
+            .filter_map(|posting| {
+                let tf = match field {
+                    Some(field) => posting
+                        .positions
+                        .iter()
+                        .filter(|p| &p.field == field)
+                        .count(),
+                    None => posting.term_frequency,
+                };
+                (tf > 0).then_some((posting, tf))
+            })
+            .collect();
+        // Field-scoped counts have their own df, so only whole-term scoring
+        // can use the index's precomputed idf.
+        let idf = match field {
+            Some(_) => self.idf(frequencies.len()),
+            None => self.term_idf(term, posting_list.document_frequency),
+        };
+
+        frequencies
+            .into_iter()
+            .map(|(posting, tf)| {
+                let doc_id = posting.doc_id;
+                let score = match field {
+                    Some(field) => self.field_boost(field) * self.score_with_idf(tf, idf, doc_id),
+                    None if !self.options.field_boosts.is_empty() => {
+                        self.field_boosted_score(posting, idf)
+                    }
+                    None => self.score_with_idf(tf, idf, doc_id),
+                };
+                (doc_id, score)
+            })
+            .collect()
+    }
+
+    fn field_boost(&self, field: &FieldType) -> f64 {
+        self.options
+            .field_boosts
+            .iter()
+            .find(|(f, _)| f == field)
+            .map_or(1.0, |(_, boost)| *boost)
+    }
+
+    /// Scores each field's occurrences separately and sums them, weighted by
+    /// the configured field boosts.
+    fn field_boosted_score(&self, posting: &PostingEntry, idf: TermIdf) -> f64 {
+        let mut field_tfs: Vec<(&FieldType, usize)> = Vec::new();
+        for position in &posting.positions {
+            match field_tfs.iter_mut().find(|(f, _)| *f == &position.field) {
+                Some((_, tf)) => *tf += 1,
+                None => field_tfs.push((&position.field, 1)),
+            }
+        }
+        field_tfs
+            .into_iter()
+            .map(|(field, tf)| {
+                self.field_boost(field) * self.score_with_idf(tf, idf, posting.doc_id)
+            })
+            .sum()
+    }
+
+    fn idf(&self, document_frequency: usize) -> TermIdf {
+        debug_assert!(
+            document_frequency <= self.stats.total_documents,
+            "document frequency {} exceeds total documents {}",
+            document_frequency,
+            self.stats.total_documents
+        );
+        TermIdf::new(document_frequency, self.stats.total_documents)
+    }
+
+    /// Idf of a whole term, from the index's cache when it is warm.
+    fn term_idf(&self, term: &str, document_frequency: usize) -> TermIdf {
+        self.index.cached_idf(term).unwrap_or_else(|| {
+            self.idf_computations.set(self.idf_computations.get() + 1);
+            self.idf(document_frequency)
+        })
+    }
+
+    fn score_tf(
+        &self,
+        term_frequency: usize,
+        document_frequency: usize,
+        doc_id: DocumentId,
+    ) -> f64 {
+        self.score_with_idf(term_frequency, self.idf(document_frequency), doc_id)
+    }
+
+    fn score_with_idf(&self, term_frequency: usize, idf: TermIdf, doc_id: DocumentId) -> f64 {
+        match self.options.model {
+            ScoringModel::TfIdf => self.tfidf_weight(term_frequency, idf.tfidf),
+            ScoringModel::Bm25 { k1, b } => self.bm25_weight(
+                term_frequency,
+                idf.bm25,
+                self.index.document_length(doc_id) as f64 / self.stats.average_document_length,
+                k1,
+                b,
+            ),
+        }
+    }
+
+    /// Whether the analyzed `term` is too common to score under
+    /// [`ScoringOptions::auto_stop_word_ratio`].
+    fn is_auto_stop_word(&self, term: &str) -> bool {
+        self.options
+            .auto_stop_word_ratio
+            .is_some_and(|ratio| self.index.exceeds_document_ratio(term, ratio))
+    }
+
+    /// Whether `query` is a bare term that is an automatic stop word.
+    fn is_stop_clause(&self, query: &Query) -> bool {
+        match query {
+            Query::Term(term) => self.is_auto_stop_word(&self.index.normalize_term(term)),
+            Query::IndexedTerm(term) => self.is_auto_stop_word(term),
+            _ => false,
+        }
+    }
+
+    /// The clauses of an AND or OR left after dropping automatic stop words,
+    /// which match nothing anyway. NOT clauses are kept as they are.
+    fn active_clauses<'q>(
+        &self,
+        operator: &BooleanOperator,
+        queries: &'q [Query],
+    ) -> Cow<'q, [Query]> {
+        if self.options.auto_stop_word_ratio.is_none()
+            || *operator == BooleanOperator::Not
+            || !queries.iter().any(|q| self.is_stop_clause(q))
+        {
+            return Cow::Borrowed(queries);
+        }
+        Cow::Owned(
+            queries
+                .iter()
+                .filter(|q| !self.is_stop_clause(q))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Factor applied to one boolean clause's scores: the inverse of its
+    /// best score under [`ScoringOptions::normalize_clauses`], else 1.
+    fn clause_scale(&self, scores: &HashMap<DocumentId, f64>) -> f64 {
+        if !self.options.normalize_clauses {
+            return 1.0;
+        }
+        let best = scores.values().copied().fold(0.0, f64::max);
+        if best > 0.0 { 1.0 / best } else { 1.0 }
+    }
+
+    /// Implements the combination rules documented on [`Query::Boolean`].
+    fn score_boolean(
+        &self,
+        operator: &BooleanOperator,
+        queries: &[Query],
+        field: Option<&FieldType>,
+    ) -> HashMap<DocumentId, f64> {
+        if queries.is_empty() {
+            return HashMap::new();
+        }
+
+        if let (BooleanOperator::Not, [excluded]) = (operator, queries) {
+            let excluded = self.score_query_in(excluded, field);
+            return self
+                .index
+                .document_ids()
+                .filter(|doc_id| !excluded.contains_key(doc_id))
+                .map(|doc_id| (doc_id, 0.0))
+                .collect();
+        }
+
+        let mut result_sets: Vec<HashSet<DocumentId>> = Vec::new();
+        let mut all_scores: HashMap<DocumentId, f64> = HashMap::new();
+        let mut matched_clauses: HashMap<DocumentId, usize> = HashMap::new();
+
+        for (i, query) in queries.iter().enumerate() {
+            let scores = self.score_query_in(query, field);
+            if scores.is_empty() && matches!(operator, BooleanOperator::And) {
+                return HashMap::new();
+            }
+            result_sets.push(scores.keys().cloned().collect());
+            for doc_id in scores.keys() {
+                *matched_clauses.entry(*doc_id).or_insert(0) += 1;
+            }
+
+            // The excluded side of a NOT never adds to a document's score.
+            if matches!(operator, BooleanOperator::Not) && i > 0 {
+                continue;
+            }
+            let scale = self.clause_scale(&scores);
+            for (doc_id, score) in scores {
+                *all_scores.entry(doc_id).or_insert(0.0) += score * scale;
+            }
+        }
+
+        let final_doc_ids: HashSet<DocumentId> = match operator {
+            BooleanOperator::And => result_sets
+                .into_iter()
+                .reduce(|acc, set| acc.intersection(&set).cloned().collect())
+                .unwrap_or_default(),
+            BooleanOperator::Or => result_sets
+                .into_iter()
+                .reduce(|acc, set| acc.union(&set).cloned().collect())
+                .unwrap_or_default(),
+            BooleanOperator::Not => {
+                if result_sets.len() != 2 {
+                    return HashMap::new();
+                }
+                let base = &result_sets[0];
+                let exclude = &result_sets[1];
+                base.difference(exclude).cloned().collect()
+            }
+        };
+
+        final_doc_ids
+            .into_iter()
+            .filter_map(|doc_id| {
+                let coverage =
+                    self.coverage_factor(operator, matched_clauses[&doc_id], queries.len());
+                Some((doc_id, all_scores.get(&doc_id)? * coverage))
+            })
+            .collect()
+    }
+
+    /// The [`ScoringOptions::coverage_weight`] multiplier for a document
+    /// matching `matched` of `clauses` clauses; always 1.0 outside OR.
+    fn coverage_factor(&self, operator: &BooleanOperator, matched: usize, clauses: usize) -> f64 {
+        let weight = self.options.coverage_weight;
+        if !matches!(operator, BooleanOperator::Or) || weight == 0.0 {
+            return 1.0;
+        }
+        (matched as f64 / clauses as f64).powf(weight)
+    }
+
+    fn score_phrase(
+        &self,
+        words: &[String],
+        field: Option<&FieldType>,
+        options: PhraseOptions,
+    ) -> HashMap<DocumentId, f64> {
+        let terms = self.analyze_terms(words);
+        if terms.is_empty() {
+            return HashMap::new();
+        }
+        let mut candidates = HashSet::new();
+
+        if let Some(posting_list) = self.index.index.get(&terms[0].0) {
+            for posting in &posting_list.postings {
+                candidates.insert(posting.doc_id);
+            }
+        }
+
+        for (term, _) in &terms[1..] {
+            let mut new_candidates = HashSet::new();
+
+            if let Some(posting_list) = self.index.index.get(term) {
+                for posting in &posting_list.postings {
+                    if candidates.contains(&posting.doc_id) {
+                        new_candidates.insert(posting.doc_id);
+                    }
+                }
+            }
+
+            candidates = new_candidates;
+        }
+
+        // Score the phrase as a pseudo-term: its occurrences per document are
+        // the tf, and the documents containing it give the df.
+        let frequencies: Vec<(DocumentId, usize)> = candidates
+            .into_iter()
+            .filter_map(|doc_id| {
+                let mut spans = self.phrase_spans(doc_id, &terms);
+                self.retain_phrase_options(doc_id, &mut spans, words, options);
+                let occurrences = spans
+                    .iter()
+                    .filter(|span| field.is_none_or(|field| &span.field == field))
+                    .count();
+                (occurrences > 0).then_some((doc_id, occurrences))
+            })
+            .collect();
+        let document_frequency = frequencies.len();
+
+        frequencies
+            .into_iter()
+            .map(|(doc_id, tf)| (doc_id, self.score_tf(tf, document_frequency, doc_id)))
+            .collect()
+    }
+
+    /// Occurrences of the phrase `words` in `doc_id` that satisfy `options`.
+    fn phrase_matches(
+        &self,
+        doc_id: DocumentId,
+        words: &[String],
+        options: PhraseOptions,
+    ) -> Vec<Span> {
+        let mut spans = self.phrase_spans(doc_id, &self.analyze_terms(words));
+        self.retain_phrase_options(doc_id, &mut spans, words, options);
+        spans
+    }
+
+    /// Drops the spans of phrase `words` that `options` rule out.
+    fn retain_phrase_options(
+        &self,
+        doc_id: DocumentId,
+        spans: &mut Vec<Span>,
+        words: &[String],
+        options: PhraseOptions,
+    ) {
+        if options.same_sentence {
+            spans.retain(|span| span.sentence.is_some());
+        }
+        if options.case_sensitive {
+            let cased = self.case_preserving_tokenizer();
+            self.retain_case_matches(doc_id, spans, words, cased);
+        }
+    }
+
+    /// The index's tokenizer without case folding, stemming or plural
+    /// stripping, built on first use. Its token positions agree with the
+    /// index's, since those steps never drop a token.
+    fn case_preserving_tokenizer(&self) -> &Tokenizer {
+        self.case_preserving_tokenizer.get_or_init(|| {
+            let mut tokenizer = self.index.tokenizer().clone();
+            tokenizer.set_lowercase(false);
+            tokenizer.set_stem_algorithm(StemAlgorithm::None);
+            tokenizer.set_strip_plurals(false);
+            tokenizer
+        })
+    }
+
+    /// Keeps the phrase spans whose words in the stored text equal `words`
+    /// once both are run through the case-preserving `cased` tokenizer.
+    fn retain_case_matches(
+        &self,
+        doc_id: DocumentId,
+        spans: &mut Vec<Span>,
+        words: &[String],
+        cased: &Tokenizer,
+    ) {
+        let Some(doc) = self.index.get_document(doc_id) else {
+            spans.clear();
+            return;
+        };
+        let expected: Vec<String> = words
+            .iter()
+            .flat_map(|word| cased.tokenize_iter(word).map(|token| token.text))
+            .collect();
+        spans.retain(|span| {
+            let text = match &span.field {
+                FieldType::Title => &doc.title,
+                FieldType::Content => &doc.content,
+                FieldType::Metadata(key) => match doc.metadata.get(key) {
+                    Some(value) => value,
+                    None => return false,
+                },
+            };
+            cased
+                .tokenize_iter(text)
+                .skip_while(|token| token.position < span.start)
+                .take_while(|token| token.position <= span.end)
+                .map(|token| token.text)
+                .eq(expected.iter().cloned())
+        });
+    }
+
+    /// Normalizes phrase words, each paired with its position relative to
+    /// the first. Words the tokenizer discards (stop words and the like) were
+    /// never indexed, so they are dropped; with position gaps preserved they
+    /// still advance the position, so the phrase requires the gap they leave.
+    fn analyze_terms(&self, words: &[String]) -> Vec<(String, usize)> {
+        let tokenizer = self.index.tokenizer();
+        let mut terms = Vec::new();
+        let mut offset = 0;
+        for word in words {
+            if tokenizer.tokenize_iter(word).next().is_some() {
+                terms.push((self.index.normalize_term(word), offset));
+                offset += 1;
+            } else if tokenizer.preserve_position_gaps() && !terms.is_empty() {
+                offset += 1;
+            }
+        }
+        terms
+    }
+
+    /// Returns every occurrence of the analyzed `terms` at their relative
+    /// positions within a single field of `doc_id`.
+    fn phrase_spans(&self, doc_id: DocumentId, terms: &[(String, usize)]) -> Vec<Span> {
+        let Some(&(_, last_offset)) = terms.last() else {
+            return Vec::new();
+        };
+        let mut term_positions = Vec::new();
+        for (term, _) in terms {
+            let posting = self
+                .index
+                .index
+                .get(term)
+                .and_then(|list| list.postings.iter().find(|p| p.doc_id == doc_id));
+            match posting {
+                Some(posting) => term_positions.push(&posting.positions),
+                None => return Vec::new(),
+            }
+        }
+
+        term_positions[0]
+            .iter()
+            .filter_map(|start| {
+                let mut sentence = Some(start.sentence);
+                for ((_, offset), positions) in terms.iter().zip(&term_positions).skip(1) {
+                    let next = positions.iter().find(|p| {
+                        p.field == start.field && p.position == start.position + offset
+                    })?;
+                    if sentence != Some(next.sentence) {
+                        sentence = None;
+                    }
+                }
+                Some(Span {
+                    field: start.field.clone(),
+                    start: start.position,
+                    end: start.position + last_offset,
+                    slop: 0,
+                    sentence,
+                })
+            })
+            .collect()
+    }
+
+    /// Scores span matches by their tightest fit: `1 / (1 + slop)`, so an
+    /// exact adjacent match scores 1.0.
+    fn score_span_near(
+        &self,
+        query: &Query,
+        clauses: &[Query],
+        field: Option<&FieldType>,
+    ) -> HashMap<DocumentId, f64> {
+        let candidates = clauses
+            .iter()
+            .map(|clause| {
+                self.score_query_in(clause, field)
+                    .into_keys()
+                    .collect::<HashSet<_>>()
+            })
+            .reduce(|acc, set| acc.intersection(&set).cloned().collect())
+            .unwrap_or_default();
+
+        candidates
+            .into_iter()
+            .filter_map(|doc_id| {
+                let tightest = self
+                    .spans(query, doc_id)
+                    .iter()
+                    .filter(|span| field.is_none_or(|field| &span.field == field))
+                    .map(|s| s.slop)
+                    .min()?;
+                Some((doc_id, 1.0 / (1.0 + tightest as f64)))
+            })
+            .collect()
+    }
+
+    /// Every span of `doc_id` matched by a span-capable query. Queries that
+    /// have no positional meaning (boolean, wildcard) produce no spans.
+    fn spans(&self, query: &Query, doc_id: DocumentId) -> Vec<Span> {
+        match query {
+            Query::Term(term) => {
+                self.spans(&Query::IndexedTerm(self.index.normalize_term(term)), doc_id)
+            }
+            Query::IndexedTerm(term) => self
+                .index
+                .index
+                .get(term)
+                .and_then(|list| list.postings.iter().find(|p| p.doc_id == doc_id))
+                .map(|posting| {
+                    posting
+                        .positions
+                        .iter()
+                        .map(|p| Span {
+                            field: p.field.clone(),
+                            start: p.position,
+                            end: p.position,
+                            slop: 0,
+                            sentence: Some(p.sentence),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Query::FieldTerm { field, term } => self
+                .spans(&Query::Term(term.clone()), doc_id)
+                .into_iter()
+                .filter(|span| &span.field == field)
+                .collect(),
+            Query::Phrase(terms) => self.phrase_matches(doc_id, terms, PhraseOptions::default()),
+            Query::PhraseWith { terms, options } => self.phrase_matches(doc_id, terms, *options),
+            Query::SpanNear {
+                clauses,
+                slop,
+                in_order,
+                same_sentence,
+            } if !clauses.is_empty() => {
+                let clause_spans: Vec<Vec<Span>> = clauses
+                    .iter()
+                    .map(|c| {
+                        let mut spans = self.spans(c, doc_id);
+                        spans.sort_by_key(|span| span.start);
+                        spans
+                    })
+                    .collect();
+                let mut matches = Vec::new();
+                // The most positions each suffix of the clauses can cover.
+                let mut fill = vec![0; clause_spans.len() + 1];
+                for (i, spans) in clause_spans.iter().enumerate().rev() {
+                    fill[i] = fill[i + 1] + spans.iter().map(Span::len).max().unwrap_or(0);
+                }
+                collect_near_spans(
+                    &clause_spans,
+                    &fill,
+                    *slop,
+                    *in_order,
+                    &mut Vec::new(),
+                    &mut matches,
+                );
+                if *same_sentence {
+                    matches.retain(|span| span.sentence.is_some());
+                }
+                matches
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Scores a [`Query::Proximity`] as its tier (2 for the exact phrase, 1
+    /// for a near match, 0 otherwise) plus the AND score of its terms
+    /// squashed into `[0, 1)`, so no document outranks a better tier.
+    fn score_proximity(
+        &self,
+        terms: &[String],
+        window: usize,
+        field: Option<&FieldType>,
+    ) -> HashMap<DocumentId, f64> {
+        let [phrase, near, all] = proximity_tiers(terms, window);
+        let phrase = self.score_query_in(&phrase, field);
+        let near = self.score_query_in(&near, field);
+        self.score_query_in(&all, field)
+            .into_iter()
+            .map(|(doc_id, score)| {
+                let tier = if phrase.contains_key(&doc_id) {
+                    2.0
+                } else if near.contains_key(&doc_id) {
+                    1.0
+                } else {
+                    0.0
+                };
+                (doc_id, tier + score / (1.0 + score))
+            })
+            .collect()
+    }
+
+    fn score_wildcard(&self, pattern: &str, field: Option<&FieldType>) -> HashMap<DocumentId, f64> {
+        let mut scores: HashMap<DocumentId, f64> = HashMap::new();
+        let pattern_lower = pattern.to_lowercase();
+
+        for term in self.wildcard_terms(&pattern_lower) {
+            for (doc_id, score) in self.score_indexed_term_in(&term, field) {
+                let best = scores.entry(doc_id).or_insert(score);
+                *best = best.max(score);
+            }
+        }
+
+        scores
+    }
+
+    /// Vocabulary terms matching `pattern`, answered from the suffix index for
+    /// plain `*suffix` patterns when it is enabled.
+    fn wildcard_terms(&self, pattern: &str) -> Vec<String> {
+        if let Some(suffix) = pattern.strip_prefix('*')
+            && !suffix.is_empty()
+            && !suffix.contains('*')
+            && let Some(terms) = self.index.terms_with_suffix(suffix)
+        {
+            return terms;
+        }
+
+        self.index
+            .index
+            .keys()
+            .filter(|term| wildcard_matches(pattern, term))
+            .cloned()
+            .collect()
+    }
+
+    fn calculate_tfidf(
+        &self,
+        term_frequency: usize,
+        document_frequency: usize,
+        total_docs: usize,
+    ) -> f64 {
+        self.tfidf_weight(
+            term_frequency,
+            TermIdf::new(document_frequency, total_docs).tfidf,
+        )
+    }
+
+    fn tfidf_weight(&self, term_frequency: usize, idf: f64) -> f64 {
+        ((self.effective_tf(term_frequency) as f64).log10() + 1.0) * idf
+    }
+
+    /// BM25 for one term; `relative_length` is the document's length divided
+    /// by the average document length.
+    fn bm25_weight(
+        &self,
+        term_frequency: usize,
+        idf: f64,
+        relative_length: f64,
+        k1: f64,
+        b: f64,
+    ) -> f64 {
+        let tf = self.effective_tf(term_frequency) as f64;
+        idf * tf * (k1 + 1.0) / (tf + k1 * (1.0 - b + b * relative_length))
+    }
+
+    fn effective_tf(&self, term_frequency: usize) -> usize {
+        match self.options.max_effective_tf {
+            Some(cap) => term_frequency.min(cap),
+            None => term_frequency,
+        }
+    }
+
+    /// Excerpts the text selected by the snippet source around the first
+    /// match of `query`, falling back to the start of the content.
+    fn snippet_for(&self, query: &Query, doc: &Document) -> String {
+        let title_match = || self.match_range(query, doc, &FieldType::Title);
+        let content_match = || self.match_range(query, doc, &FieldType::Content);
+        let full_text_match = || {
+            title_match().or_else(|| {
+                let shift = doc.title.len() + FIELD_SEPARATOR.len_utf8();
+                content_match().map(|(start, end)| (start + shift, end + shift))
+            })
+        };
+        // Joined by the invisible FIELD_SEPARATOR so highlights can't bridge
+        // the fields; build_result shows the configured separator instead.
+        let full_text_excerpt = |(start, end)| {
+            let text = doc.full_text_with_separator(&FIELD_SEPARATOR.to_string());
+            self.excerpt(&text, start, end)
+        };
+
+        let snippet = match self.snippet_source {
+            SnippetSource::Content => {
+                content_match().map(|(start, end)| self.excerpt(&doc.content, start, end))
+            }
+            SnippetSource::Title => {
+                title_match().map(|(start, end)| self.excerpt(&doc.title, start, end))
+            }
+            SnippetSource::FullText => full_text_match().map(full_text_excerpt),
+            SnippetSource::Auto => content_match()
+                .map(|(start, end)| self.excerpt(&doc.content, start, end))
+                .or_else(|| title_match().map(full_text_excerpt)),
+        };
+        snippet.unwrap_or_else(|| self.leading_snippet(&doc.content))
+    }
+
+    /// The snippet shown when no match is visible: the start of `content`.
+    fn leading_snippet(&self, content: &str) -> String {
+        match (self.snippet_length, self.snippet_strategy) {
+            (None, SnippetStrategy::Window) => {
+                let end = char_to_byte_offset(content, 100);
+                with_ellipses(content, 0, end)
+            }
+            _ => self.excerpt(content, 0, 0),
+        }
+    }
+
+    /// Cuts the snippet for the match at bytes `match_start..match_end`
+    /// according to the snippet strategy.
+    fn excerpt(&self, text: &str, match_start: usize, match_end: usize) -> String {
+        match self.snippet_strategy {
+            SnippetStrategy::Window => self.snippet_around(text, match_start, match_end),
+            SnippetStrategy::Sentence { max_sentences } => {
+                sentence_snippet(text, match_start, match_end, max_sentences)
+            }
+        }
+    }
+
+    /// Byte range, within the text of `field`, of the first match of `query`
+    /// there. Only title and content are searched.
+    fn match_range(
+        &self,
+        query: &Query,
+        doc: &Document,
+        field: &FieldType,
+    ) -> Option<(usize, usize)> {
+        let text = match field {
+            FieldType::Title => &doc.title,
+            FieldType::Content => &doc.content,
+            FieldType::Metadata(_) => return None,
+        };
+        match query {
+            Query::Term(term) => {
+                let term = self.index.normalize_term(term);
+                self.token_range(text, |token| token == term)
+            }
+            Query::IndexedTerm(term) => self.token_range(text, |token| token == *term),
+            Query::Boolean { operator, queries } => {
+                let positive = scoring_clauses(operator, queries);
+                positive
+                    .iter()
+                    .find_map(|q| self.match_range(q, doc, field))
+            }
+            Query::Phrase(terms) => self
+                .phrase_matches(doc.id, terms, PhraseOptions::default())
+                .iter()
+                .find(|span| &span.field == field)
+                .and_then(|span| self.phrase_range(text, span.start, span.len())),
+            Query::PhraseWith { terms, options } => self
+                .phrase_matches(doc.id, terms, *options)
+                .iter()
+                .find(|span| &span.field == field)
+                .and_then(|span| self.phrase_range(text, span.start, span.len())),
+            Query::FieldTerm { field: scope, term } => (scope == field)
+                .then(|| self.match_range(&Query::Term(term.clone()), doc, field))
+                .flatten(),
+            Query::Boost { query, .. } => self.match_range(query, doc, field),
+            Query::FieldScoped(clauses) => clauses
+                .iter()
+                .filter(|(scope, _)| scope == field)
+                .find_map(|(_, clause)| self.match_range(clause, doc, field)),
+            Query::Proximity { terms, window } => proximity_tiers(terms, *window)
+                .iter()
+                .find_map(|q| self.match_range(q, doc, field)),
+            Query::MatchAll | Query::MatchNone => None,
+            Query::SpanNear { .. } => self
+                .spans(query, doc.id)
+                .iter()
+                .filter(|span| &span.field == field)
+                .min_by_key(|span| (span.slop, span.start))
+                .and_then(|span| self.phrase_range(text, span.start, span.len())),
+            Query::Wildcard(pattern) => {
+                let pattern_lower = pattern.to_lowercase();
+                self.token_range(text, |token| wildcard_matches(&pattern_lower, token))
+            }
+        }
+    }
+
+    /// Byte range of the first token of `text` whose analyzed form satisfies
+    /// `matches`, so terms altered by stemming or folding are still found at
+    /// their original offsets.
+    fn token_range(&self, text: &str, matches: impl Fn(&str) -> bool) -> Option<(usize, usize)> {
+        self.index
+            .tokenizer()
+            .tokenize_iter(text)
+            .find(|token| matches(&token.text))
+            .map(|token| {
+                (
+                    char_to_byte_offset(text, token.start_offset),
+                    char_to_byte_offset(text, token.end_offset),
+                )
+            })
+    }
+
+    /// Byte range of the phrase occupying `length` token positions starting
+    /// at `position`, as reported by positional verification.
+    fn phrase_range(&self, text: &str, position: usize, length: usize) -> Option<(usize, usize)> {
+        let tokens = self.index.tokenizer().tokenize(text);
+        let first = tokens.iter().find(|t| t.position == position)?;
+        let last = tokens
+            .iter()
+            .find(|t| t.position == position + length - 1)?;
+        Some((
+            char_to_byte_offset(text, first.start_offset),
+            char_to_byte_offset(text, last.end_offset),
+        ))
+    }
+
+    /// Analyzed terms and phrases whose occurrences should be highlighted
+    /// for `query`, in order of first appearance; a unit's index identifies
+    /// it in `{term}` tag placeholders. Excluded clauses of a NOT contribute
+    /// nothing.
+    fn highlight_terms(&self, query: &Query) -> Vec<HighlightUnit> {
+        let mut terms = Vec::new();
+        self.collect_highlight_terms(query, &mut terms);
+        terms
+    }
+
+    fn collect_highlight_terms(&self, query: &Query, terms: &mut Vec<HighlightUnit>) {
+        let mut add = |unit: HighlightUnit| {
+            if !unit.is_empty() && !terms.contains(&unit) {
+                terms.push(unit);
+            }
+        };
+        match query {
+            Query::Term(term) | Query::FieldTerm { term, .. } => {
+                add(vec![(self.index.normalize_term(term), 0)])
+            }
+            Query::IndexedTerm(term) => add(vec![(term.clone(), 0)]),
+            Query::Phrase(phrase) | Query::PhraseWith { terms: phrase, .. } => {
+                add(self.analyze_terms(phrase))
+            }
+            Query::Wildcard(pattern) => {
+                let mut matches = self.wildcard_terms(&pattern.to_lowercase());
+                matches.sort();
+                matches.into_iter().for_each(|term| add(vec![(term, 0)]));
+            }
+            Query::Boolean { operator, queries } => {
+                let positive = scoring_clauses(operator, queries);
+                for q in positive {
+                    self.collect_highlight_terms(q, terms);
+                }
+            }
+            Query::SpanNear { clauses, .. } => {
+                for q in clauses {
+                    self.collect_highlight_terms(q, terms);
+                }
+            }
+            Query::Boost { query, .. } => self.collect_highlight_terms(query, terms),
+            Query::Proximity {
+                terms: words,
+                window,
+            } => {
+                // The phrase unit outranks single terms where it matches.
+                let [phrase, _, all] = proximity_tiers(words, *window);
+                self.collect_highlight_terms(&phrase, terms);
+                self.collect_highlight_terms(&all, terms);
+            }
+            Query::FieldScoped(clauses) => {
+                for (_, q) in clauses {
+                    self.collect_highlight_terms(q, terms);
+                }
+            }
+            Query::MatchAll | Query::MatchNone => {}
+        }
+    }
+
+    fn highlight(&self, snippet: &str, terms: &[HighlightUnit], config: &SnippetConfig) -> String {
+        highlight_units(self.index.tokenizer(), snippet, terms, config)
+    }
+
+    /// Excerpts `content` around the match at bytes `match_start..match_end`.
+    /// With a configured snippet length the match is centered in it where
+    /// the content allows, never cutting the match itself, and byte limits
+    /// shrink to character boundaries.
+    fn snippet_around(&self, content: &str, match_start: usize, match_end: usize) -> String {
+        let Some(length) = self.snippet_length else {
+            let start = content.floor_char_boundary(match_start.saturating_sub(50));
+            let end = content.ceil_char_boundary((match_end + 50).min(content.len()));
+            return with_ellipses(content, start, end);
+        };
+        let (match_start_unit, match_end_unit, total) = match self.snippet_length_unit {
+            SnippetLengthUnit::Bytes => (match_start, match_end, content.len()),
+            SnippetLengthUnit::Chars => (
+                content[..match_start].chars().count(),
+                content[..match_end].chars().count(),
+                content.chars().count(),
+            ),
+        };
+        let window = length.max(match_end_unit - match_start_unit);
+        let before = (window - (match_end_unit - match_start_unit)) / 2;
+        let start_unit = match_start_unit.saturating_sub(before);
+        let end_unit = (start_unit + window).min(total);
+        let start_unit = end_unit.saturating_sub(window).min(start_unit);
+
+        let (start, end) = match self.snippet_length_unit {
+            SnippetLengthUnit::Bytes => (
+                content.ceil_char_boundary(start_unit),
+                content.floor_char_boundary(end_unit),
+            ),
+            SnippetLengthUnit::Chars => (
+                char_to_byte_offset(content, start_unit),
+                char_to_byte_offset(content, end_unit),
+            ),
+        };
+        with_ellipses(content, start, end)
+    }
+}
+
+/// `content[start..end]`, with ellipses marking omitted text on either side;
+/// none where only whitespace was cut.
+fn with_ellipses(content: &str, start: usize, end: usize) -> String {
+    let mut snippet = String::new();
+    if !content[..start].trim().is_empty() {
+        snippet.push_str("...");
+    }
+    snippet.push_str(&content[start..end]);
+    if !content[end..].trim().is_empty() {
+        snippet.push_str("...");
+    }
+    snippet
+}
+
+/// Byte ranges of the sentences of `text`, without surrounding whitespace.
+fn sentence_bounds(text: &str) -> Vec<(usize, usize)> {
+    let mut bounds = Vec::new();
+    let mut start = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if start.is_none() && !c.is_whitespace() {
+            start = Some(i);
+        }
+        if !matches!(c, '.' | '!' | '?') || !chars.peek().is_some_and(|(_, n)| n.is_whitespace()) {
+            continue;
+        }
+        let next_word = text[i + 1..].trim_start().chars().next();
+        if let Some(sentence_start) = start
+            && next_word.is_none_or(char::is_uppercase)
+        {
+            bounds.push((sentence_start, i + 1));
+            start = None;
+        }
+    }
+    if let Some(sentence_start) = start {
+        bounds.push((sentence_start, text.trim_end().len()));
+    }
+    bounds
+}
+
+/// The sentences of `text` covering bytes `match_start..match_end`, grown
+/// by following and then preceding sentences to at most `max_sentences`.
+fn sentence_snippet(
+    text: &str,
+    match_start: usize,
+    match_end: usize,
+    max_sentences: usize,
+) -> String {
+    let bounds = sentence_bounds(text);
+    if bounds.is_empty() {
+        return String::new();
+    }
+    let containing = |offset: usize| {
+        bounds
+            .iter()
+            .rposition(|&(start, _)| start <= offset)
+            .unwrap_or(0)
+    };
+    let mut first = containing(match_start);
+    let mut last = containing(match_end.saturating_sub(1).max(match_start)).max(first);
+    while last - first + 1 < max_sentences && last + 1 < bounds.len() {
+        last += 1;
+    }
+    while last - first + 1 < max_sentences && first > 0 {
+        first -= 1;
+    }
+    text[bounds[first].0..bounds[last].1].to_string()
+}
+
+/// Wraps each occurrence of one of `terms` in `snippet` in the configured
+/// tags, escaping the text first if requested. A phrase matches only at
+/// consecutive token positions and is wrapped once, from the start of its
+/// first token to the end of its last.
+fn highlight_units(
+    tokenizer: &Tokenizer,
+    snippet: &str,
+    terms: &[HighlightUnit],
+    config: &SnippetConfig,
+) -> String {
+    let escape = |text: &str| {
+        if config.escape_html {
+            escape_html(text)
+        } else {
+            text.to_string()
+        }
+    };
+
+    let tokens = tokenizer.tokenize(snippet);
+    let mut highlighted = String::new();
+    let mut copied = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        let matched = terms
+            .iter()
+            .enumerate()
+            .filter(|(_, unit)| {
+                !unit.is_empty()
+                    && tokens[i..].len() >= unit.len()
+                    && unit.iter().zip(&tokens[i..]).all(|((t, offset), token)| {
+                        *t == token.text && token.position == tokens[i].position + offset
+                    })
+            })
+            .max_by_key(|(index, unit)| (unit.len(), std::cmp::Reverse(*index)));
+        let Some((term_index, unit)) = matched else {
+            i += 1;
+            continue;
+        };
+        let start = char_to_byte_offset(snippet, tokens[i].start_offset);
+        let end = char_to_byte_offset(snippet, tokens[i + unit.len() - 1].end_offset);
+        highlighted.push_str(&escape(&snippet[copied..start]));
+        highlighted.push_str(&config.pre_tag.replace("{term}", &term_index.to_string()));
+        highlighted.push_str(&escape(&snippet[start..end]));
+        highlighted.push_str(&config.post_tag);
+        copied = end;
+        i += unit.len();
+    }
+    highlighted.push_str(&escape(&snippet[copied..]));
+    highlighted
+}
+
+/// Combines plain terms with `operator`.
+fn term_boolean(operator: BooleanOperator, terms: Vec<&str>) -> Query {
+    Query::Boolean {
+        operator,
+        queries: terms
+            .into_iter()
+            .map(|term| Query::Term(term.to_string()))
+            .collect(),
+    }
+}
+
+/// The exact phrase, near and co-occurrence queries whose matches rank the
+/// tiers of a [`Query::Proximity`].
+fn proximity_tiers(terms: &[String], window: usize) -> [Query; 3] {
+    let clauses: Vec<Query> = terms.iter().cloned().map(Query::Term).collect();
+    [
+        Query::Phrase(terms.to_vec()),
+        Query::SpanNear {
+            clauses: clauses.clone(),
+            slop: window,
+            in_order: false,
+            same_sentence: false,
+        },
+        Query::Boolean {
+            operator: BooleanOperator::And,
+            queries: clauses,
+        },
+    ]
+}
+
+/// The clauses of a boolean query that contribute score and snippets: all of
+/// them for AND and OR, only the base of a binary NOT, and none of a unary
+/// NOT, which just filters.
+fn scoring_clauses<'q>(operator: &BooleanOperator, queries: &'q [Query]) -> &'q [Query] {
+    match operator {
+        BooleanOperator::Not if queries.len() == 2 => &queries[..1],
+        BooleanOperator::Not => &[],
+        _ => queries,
+    }
+}
+
+/// Keeps documents present in every score map, summing their scores.
+fn intersect_scores(scores: Vec<HashMap<DocumentId, f64>>) -> HashMap<DocumentId, f64> {
+    scores
+        .into_iter()
+        .reduce(|acc, next| {
+            acc.into_iter()
+                .filter_map(|(doc_id, score)| next.get(&doc_id).map(|s| (doc_id, score + s)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Extends `chosen` with one span per remaining clause, recording every
+/// combination that satisfies the slop and ordering constraints. Each
+/// clause's spans are sorted by start. `fill[i]` bounds how many positions
+/// clauses `i..` can cover, so only spans near enough for the rest to close
+/// the gap are tried.
+fn collect_near_spans(
+    clause_spans: &[Vec<Span>],
+    fill: &[usize],
+    slop: usize,
+    in_order: bool,
+    chosen: &mut Vec<Span>,
+    matches: &mut Vec<Span>,
+) {
+    let Some(candidates) = clause_spans.get(chosen.len()) else {
+        let start = chosen.iter().map(|s| s.start).min().unwrap();
+        let end = chosen.iter().map(|s| s.end).max().unwrap();
+        let covered: usize = chosen.iter().map(|s| s.len()).sum();
+        let inner_slop: usize = chosen.iter().map(|s| s.slop).sum();
+        let gap = end - start + 1 - covered;
+        if gap <= slop {
+            let sentence = chosen[0].sentence;
+            matches.push(Span {
+                field: chosen[0].field.clone(),
+                start,
+                end,
+                slop: gap + inner_slop,
+                sentence: sentence.filter(|_| chosen.iter().all(|s| s.sentence == sentence)),
+            });
+        }
+        return;
+    };
+
+    let start = chosen.iter().map(|s| s.start).min().unwrap_or(usize::MAX);
+    let end = chosen.iter().map(|s| s.end).max().unwrap_or(0);
+    let covered: usize = chosen.iter().map(|s| s.len()).sum();
+    let candidates = if chosen.is_empty() {
+        &candidates[..]
+    } else {
+        // The widest extent the finished match may have.
+        let extent = slop + covered + fill[chosen.len()];
+        let from = candidates.partition_point(|s| s.start + extent <= end);
+        let to = candidates.partition_point(|s| s.start < start + extent);
+        &candidates[from..to]
+    };
+    for span in candidates {
+        let fits = match chosen.last() {
+            None => true,
+            Some(previous) if in_order => {
+                span.field == previous.field
+                    && span.start > previous.end
+                    && span.start - previous.end - 1 <= slop
+            }
+            Some(previous) => {
+                span.field == previous.field && chosen.iter().all(|c| !c.overlaps(span))
+            }
+        };
+        if !fits {
+            continue;
+        }
+        let extent = end.max(span.end) - start.min(span.start) + 1;
+        if extent.saturating_sub(covered + span.len() + fill[chosen.len() + 1]) > slop {
+            continue;
+        }
+        chosen.push(span.clone());
+        collect_near_spans(clause_spans, fill, slop, in_order, chosen, matches);
+        chosen.pop();
+    }
+}
+
+/// Matches a lowercase wildcard `pattern` (`prefix*`, `*suffix`, or `*infix*`)
+/// against an indexed term.
+fn wildcard_matches(pattern: &str, term: &str) -> bool {
+    let is_prefix = pattern.ends_with('*') && !pattern.starts_with('*');
+    let is_suffix = pattern.starts_with('*') && !pattern.ends_with('*');
+
+    if is_prefix {
+        term.starts_with(pattern.trim_end_matches('*'))
+    } else if is_suffix {
+        term.ends_with(pattern.trim_start_matches('*'))
+    } else {
+        term.contains(&pattern.replace('*', ""))
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Char ranges of the whitespace-separated words in `text`.
+fn whitespace_words(text: &str) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, ch) in text.chars().enumerate() {
+        match (ch.is_whitespace(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(word_start)) => {
+                words.push((word_start, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(word_start) = start {
+        words.push((word_start, text.chars().count()));
+    }
+    words
+}
+
+fn char_to_byte_offset(text: &str, char_offset: usize) -> usize {
+    text.char_indices()
+        .nth(char_offset)
+        .map(|(byte, _)| byte)
+        .unwrap_or(text.len())
+}
+
+impl InvertedIndex {
+    /// A [`Highlighter`] for `terms` that analyzes text like this index.
+    pub fn highlighter(&self, terms: &[&str], config: SnippetConfig) -> Highlighter {
+        Highlighter::new(self.tokenizer().clone(), terms, config)
+    }
+
+    pub fn search_tfidf(&self, query: &str) -> Vec<SearchResult> {
+        let searcher = Searcher::new(self);
+        searcher.search(query)
+    }
+
+    pub fn boolean_search(
+        &self,
+        operator: BooleanOperator,
+        queries: Vec<&str>,
+    ) -> Vec<SearchResult> {
+        let searcher = Searcher::new(self);
+        searcher.search_with_query(&term_boolean(operator, queries))
+    }
+
+    /// One page of [`Self::boolean_search`]: up to `limit` results starting
+    /// at `offset`, plus the total number of matches.
+    pub fn boolean_search_paginated(
+        &self,
+        operator: BooleanOperator,
+        terms: Vec<&str>,
+        offset: usize,
+        limit: usize,
+    ) -> (Vec<SearchResult>, usize) {
+        Searcher::new(self).search_page(&term_boolean(operator, terms), offset, limit)
+    }
+
+    /// ORs `terms` together, multiplying each term's score by its weight
+    /// before summing per document.
+    pub fn weighted_or_search(&self, terms: &[(&str, f64)]) -> Vec<SearchResult> {
+        let query = Query::Boolean {
+            operator: BooleanOperator::Or,
+            queries: terms
+                .iter()
+                .map(|&(term, weight)| Query::Boost {
+                    query: Box::new(Query::Term(term.to_string())),
+                    boost: weight,
+                })
+                .collect(),
+        };
+        Searcher::new(self).search_with_query(&query)
+    }
+
+    pub fn phrase_search(&self, phrase: &str) -> Vec<SearchResult> {
+        let terms: Vec<String> = phrase.split_whitespace().map(|s| s.to_string()).collect();
+        let query = Query::Phrase(terms);
+        let searcher = Searcher::new(self);
+        searcher.search_with_query(&query)
+    }
+
+    pub fn wildcard_search(&self, pattern: &str) -> Vec<SearchResult> {
+        let query = Query::Wildcard(pattern.to_string());
+        let searcher = Searcher::new(self);
+        searcher.search_with_query(&query)
+    }
+
+    pub fn more_like_this(&self, doc_id: DocumentId, limit: usize) -> Vec<SearchResult> {
+        let searcher = Searcher::new(self);
+        searcher.more_like_this(doc_id, limit)
+    }
+
+    /// The `n` most distinctive terms of `doc_id` by TF-IDF, highest first.
+    /// Empty for an unknown document.
+    pub fn top_terms_for_document(&self, doc_id: DocumentId, n: usize) -> Vec<(String, f64)> {
+        Searcher::new(self).top_terms_for_document(doc_id, n)
+    }
+
+    /// Batteries-included search: parses `input` (bare words combine with
+    /// [`Self::default_operator`], quoted text is a phrase) and ranks with
+    /// BM25. Malformed input, such as an unterminated quote, or a query over
+    /// the index's limits returns no results; use [`Self::try_query`] to see
+    /// the error.
+    pub fn query(&self, input: &str) -> Vec<SearchResult> {
+        self.try_query(input).unwrap_or_default()
+    }
+
+    /// Runs `query` and keeps only results whose document passes `filter`.
+    pub fn search_filtered(&self, query: &Query, filter: &MetadataFilter) -> Vec<SearchResult> {
+        let mut results = Searcher::new(self).search_with_query(query);
+        results.retain(|result| {
+            self.get_document(result.doc_id)
+                .is_some_and(|doc| filter.matches(doc))
+        });
+        results
+    }
+
+    /// Like [`Self::query`], but reports why `input` could not be run: a
+    /// parse error, or a phrase longer than [`Self::max_phrase_terms`].
+    pub fn try_query(&self, input: &str) -> Result<Vec<SearchResult>, QueryError> {
+        let query = parse_query_with_operator(input, self.default_operator().clone())?;
+        let options = ScoringOptions {
+            model: ScoringModel::bm25(),
+            ..self.scoring_options().clone()
+        };
+        Searcher::with_options(self, options).try_search_with_query(&query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::panic;
+
+    use super::*;
+
+    fn create_test_index() -> InvertedIndex {
+        let mut index = InvertedIndex::new();
+
+        index.add_document(
+            "AI Research".to_string(),
+            "artificial intelligence research methods".to_string(),
+        );
+        index.add_document(
+            "Machine Learning".to_string(),
+            "machine learning algorithms and techniques".to_string(),
+        );
+        index.add_document(
+            "Deep Learning".to_string(),
+            "deep learning neural networks".to_string(),
+        );
+        index.add_document(
+            "Data Science".to_string(),
+            "data science and machine learning applications".to_string(),
+        );
+        index.add_document(
+            "Search Engines".to_string(),
+            "search engine algorithms and information retrieval".to_string(),
+        );
+
+        index
+    }
+
+    #[test]
+    fn test_search_result_creation() {
+        let result = SearchResult {
+            doc_id: 1,
+            score: 0.85,
+            title: "Test Document".to_string(),
+            snippet: "This is a test snippet".to_string(),
+        };
+
+        assert_eq!(result.doc_id, 1);
         assert_eq!(result.score, 0.85);
         assert_eq!(result.title, "Test Document");
         assert_eq!(result.snippet, "This is a test snippet");
     }
 
     #[test]
-    fn test_query_enum() {
-        // Test different query types
-        let term_query = Query::Term("search".to_string());
-        let boolean_query = Query::Boolean {
-            operator: BooleanOperator::And,
+    fn test_query_enum() {
+        // Test different query types
+        let term_query = Query::Term("search".to_string());
+        let boolean_query = Query::Boolean {
+            operator: BooleanOperator::And,
+            queries: vec![
+                Query::Term("search".to_string()),
+                Query::Term("engine".to_string()),
+            ],
+        };
+        let phrase_query = Query::Phrase(vec!["machine".to_string(), "learning".to_string()]);
+        let wildcard_query = Query::Wildcard("learn*".to_string());
+
+        match term_query {
+            Query::Term(term) => assert_eq!(term, "search"),
+            _ => panic!("Expected Term query"),
+        }
+
+        match boolean_query {
+            Query::Boolean { operator, queries } => {
+                assert!(matches!(operator, BooleanOperator::And));
+                assert_eq!(queries.len(), 2);
+            }
+            _ => panic!("Expected Boolean query"),
+        }
+        match phrase_query {
+            Query::Phrase(terms) => {
+                assert_eq!(terms.len(), 2);
+                assert!(terms[0] == "machine" && terms[1] == "learning");
+            }
+            _ => panic!("Expected Phrase query"),
+        }
+
+        match wildcard_query {
+            Query::Wildcard(pattern) => assert_eq!(pattern, "learn*"),
+            _ => panic!("Expected Wildcard query"),
+        }
+    }
+
+    #[test]
+    fn test_searcher_creation() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index);
+
+        assert!(std::ptr::eq(searcher.index, &index));
+    }
+
+    #[test]
+    fn test_simple_term_search() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index);
+
+        let results = searcher.search("machine");
+
+        // Should find 2 documents containing "machine"
+        assert_eq!(results.len(), 2);
+
+        // Results should be sorted by score (highest first)
+        assert!(results[0].score >= results[1].score);
+
+        // All results should contain the search term in title or snippet
+        for result in &results {
+            let text = format!(
+                "{} {}",
+                result.title.to_lowercase(),
+                result.snippet.to_lowercase()
+            );
+            assert!(text.contains("machine"));
+        }
+    }
+
+    #[test]
+    fn test_search_response_reports_dropped_terms() {
+        let mut index = InvertedIndex::new();
+        let doc_id = index.add_document("Foxes".to_string(), "the quick brown fox".to_string());
+        let searcher = Searcher::new(&index);
+
+        let response = searcher.search_response("the quick a");
+
+        assert_eq!(response.dropped_terms, vec!["the", "a"]);
+        assert!(!response.dropped_terms.contains(&"quick".to_string()));
+        assert_eq!(response.results.len(), 1);
+        assert_eq!(response.results[0].doc_id, doc_id);
+
+        let response = searcher.search_response("the a");
+        assert!(response.results.is_empty());
+        assert_eq!(response.dropped_terms, vec!["the", "a"]);
+    }
+
+    #[test]
+    fn test_search_response_keeps_apostrophe_and_hyphenated_words() {
+        let mut index = InvertedIndex::new();
+        let doc_id =
+            index.add_document("Rust".to_string(), "rust has a borrow checker".to_string());
+        let searcher = Searcher::new(&index);
+
+        let response = searcher.search_response("rust's checker");
+        assert!(response.dropped_terms.is_empty());
+        assert_eq!(response.results.len(), 1);
+        assert_eq!(response.results[0].doc_id, doc_id);
+
+        let response = searcher.search_response("the borrow-checker");
+        assert_eq!(response.dropped_terms, vec!["the"]);
+        assert_eq!(response.results.len(), 1);
+    }
+
+    #[test]
+    fn test_result_cache_hits_and_misses() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index).with_result_cache(8);
+
+        let first = searcher.search("search");
+        let second = searcher.search("Search");
+        assert_eq!(
+            searcher.cache_stats(),
+            Some(CacheStats {
+                hits: 1,
+                misses: 1,
+                entries: 1
+            })
+        );
+        assert_eq!(first.len(), second.len());
+
+        searcher.search("learning");
+        let stats = searcher.cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 2));
+
+        let bm25 = Searcher::with_options(
+            &index,
+            ScoringOptions {
+                model: ScoringModel::bm25(),
+                ..Default::default()
+            },
+        );
+        assert!(bm25.cache_stats().is_none());
+        assert_ne!(
+            searcher.cache_key(&Query::Term("search".to_string())),
+            bm25.cache_key(&Query::Term("search".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_result_cache_respects_snippet_settings() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index).with_result_cache(8);
+        let content = searcher.search("learning");
+
+        let titled = searcher.clone().with_snippet_source(SnippetSource::Title);
+        let titles = titled.search("learning");
+        assert_ne!(titles[0].snippet, content[0].snippet);
+        assert_eq!(titles[0].snippet, titles[0].title);
+
+        let short = searcher
+            .clone()
+            .with_snippet_length(10, SnippetLengthUnit::Chars);
+        assert_ne!(short.search("learning")[0].snippet, content[0].snippet);
+        assert_eq!(searcher.cache_stats().unwrap().hits, 0);
+    }
+
+    #[test]
+    fn test_result_cache_evicted_when_index_changes() {
+        let index = create_test_index();
+        let mut updated = create_test_index();
+        updated.add_document("Rust".to_string(), "rust ownership".to_string());
+
+        let searcher = Searcher::new(&index).with_result_cache(8);
+        assert!(searcher.search("rust").is_empty());
+
+        let searcher = searcher.reopen(&index);
+        assert_eq!(searcher.cache_stats().unwrap().entries, 1);
+
+        let searcher = searcher.reopen(&updated);
+        assert_eq!(searcher.cache_stats().unwrap().entries, 0);
+        assert_eq!(searcher.search("rust").len(), 1);
+        assert_eq!(searcher.cache_stats().unwrap().hits, 0);
+    }
+
+    #[test]
+    fn test_result_cache_not_shared_across_indexes() {
+        // Both indexes see the same number of changes
+        let mut first = InvertedIndex::new();
+        first.add_document("Go".to_string(), "goroutines".to_string());
+        let mut second = InvertedIndex::new();
+        second.add_document("Rust".to_string(), "rust ownership".to_string());
+
+        let searcher = Searcher::new(&first).with_result_cache(8);
+        assert!(searcher.search("rust").is_empty());
+        let searcher = searcher.reopen(&second);
+        assert_eq!(searcher.search("rust").len(), 1);
+
+        // A reloaded copy doesn't pass for the index it was saved from
+        let json = serde_json::to_string(&second).unwrap();
+        let mut reloaded: InvertedIndex = serde_json::from_str(&json).unwrap();
+        assert_ne!(reloaded.generation(), second.generation());
+        reloaded.add_document("Rust 2".to_string(), "rust traits".to_string());
+        let searcher = searcher.reopen(&reloaded);
+        assert_eq!(searcher.search("rust").len(), 2);
+    }
+
+    #[test]
+    fn test_result_cache_evicted_when_tokenizer_changes() {
+        let mut index = InvertedIndex::new();
+        index.add_document("Jog".to_string(), "running daily".to_string());
+        let json = serde_json::to_string(&index).unwrap();
+        let mut updated: InvertedIndex = serde_json::from_str(&json).unwrap();
+        let mut tokenizer = crate::tokenizer::Tokenizer::new();
+        tokenizer.set_stem_algorithm(crate::tokenizer::StemAlgorithm::Porter);
+        updated.set_tokenizer(tokenizer);
+        let phrase = Query::Phrase(vec!["running".to_string(), "daily".to_string()]);
+
+        let searcher = Searcher::new(&index).with_result_cache(8);
+        assert_eq!(searcher.search_with_query(&phrase).len(), 1);
+
+        // The query now analyzes to stems the unreindexed postings lack
+        let searcher = searcher.reopen(&updated);
+        assert_eq!(searcher.cache_stats().unwrap().entries, 0);
+        assert!(searcher.search_with_query(&phrase).is_empty());
+    }
+
+    #[test]
+    fn test_absent_terms_dropped_from_and() {
+        let index = create_test_index();
+        let query = Query::Boolean {
+            operator: BooleanOperator::And,
+            queries: vec![
+                Query::Term("machine".to_string()),
+                Query::Term("xylophone".to_string()),
+                Query::Term("learning".to_string()),
+            ],
+        };
+
+        let strict = Searcher::new(&index).search_response_with_query(&query);
+        assert!(strict.results.is_empty());
+        assert!(strict.absent_terms.is_empty());
+
+        let searcher = Searcher::new(&index).with_absent_terms_dropped();
+        let rewritten = searcher.search_response_with_query(&query);
+        let expected = searcher.search_with_query(&Query::Boolean {
+            operator: BooleanOperator::And,
+            queries: vec![
+                Query::Term("machine".to_string()),
+                Query::Term("learning".to_string()),
+            ],
+        });
+
+        assert_eq!(rewritten.absent_terms, vec!["xylophone"]);
+        assert!(!rewritten.results.is_empty());
+        assert_eq!(
+            rewritten
+                .results
+                .iter()
+                .map(|r| r.doc_id)
+                .collect::<Vec<_>>(),
+            expected.iter().map(|r| r.doc_id).collect::<Vec<_>>()
+        );
+
+        let response = searcher.search_response("machine xylophone learning");
+        assert_eq!(response.absent_terms, vec!["xylophone"]);
+        assert_eq!(response.results.len(), expected.len());
+    }
+
+    #[test]
+    fn test_search_reranked() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index);
+        let query = Query::Term("learning".to_string());
+
+        let base = searcher.search_with_query(&query);
+        assert_eq!(base.len(), 3);
+
+        // Rank by shortest title first, ignoring the base score entirely
+        let reranked = searcher.search_reranked(&query, |_, doc| -(doc.title.len() as f64));
+
+        assert_eq!(reranked.len(), base.len());
+        let titles: Vec<&str> = reranked.iter().map(|r| r.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec!["Data Science", "Deep Learning", "Machine Learning"]
+        );
+
+        for result in &reranked {
+            let doc = index.get_document(result.doc_id).unwrap();
+            assert_eq!(result.score, -(doc.title.len() as f64));
+        }
+    }
+
+    #[test]
+    fn test_search_reranked_tolerates_nan_and_breaks_ties_by_id() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index);
+        let query = Query::Term("learning".to_string());
+
+        let tied = searcher.search_reranked(&query, |_, _| 1.0);
+        let ids: Vec<DocumentId> = tied.iter().map(|r| r.doc_id).collect();
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert_eq!(ids, sorted);
+
+        let nan = searcher.search_reranked(&query, |_, _| f64::NAN);
+        assert_eq!(nan.len(), 3);
+    }
+
+    #[test]
+    fn test_result_stream_is_lazy() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index);
+        let query = Query::Boolean {
+            operator: BooleanOperator::Or,
+            queries: vec![
+                Query::Term("learning".to_string()),
+                Query::Term("algorithms".to_string()),
+            ],
+        };
+
+        let eager = searcher.search_with_query(&query);
+        assert!(eager.len() >= 3);
+
+        let mut stream = searcher.result_stream(&query);
+        let third: Vec<SearchResult> = stream.by_ref().skip(2).take(1).collect();
+
+        assert_eq!(third.len(), 1);
+        assert_eq!(third[0].doc_id, eager[2].doc_id);
+        assert_eq!(third[0].score, eager[2].score);
+        assert_eq!(third[0].snippet, eager[2].snippet);
+
+        // Only the consumed result paid for a snippet
+        assert_eq!(stream.snippets_generated(), 1);
+    }
+
+    #[test]
+    fn test_tfidf_scoring() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index);
+
+        // Search for a term that appears in multiple documents with different frequencies
+        let results = searcher.search("learning");
+
+        assert!(!results.is_empty());
+
+        // All scores should be positive
+        for result in &results {
+            assert!(result.score > 0.0);
+        }
+
+        // Results should be sorted by score (descending)
+        for i in 1..results.len() {
+            assert!(results[i - 1].score >= results[i].score);
+        }
+    }
+
+    #[test]
+    fn test_boolean_and_search() {
+        let index = create_test_index();
+        let query = Query::Boolean {
+            operator: BooleanOperator::And,
+            queries: vec![
+                Query::Term("machine".to_string()),
+                Query::Term("learning".to_string()),
+            ],
+        };
+        let searcher = Searcher::new(&index);
+        let results = searcher.search_with_query(&query);
+
+        // Should find documents containing both "machine" AND "learning"
+        assert!(!results.is_empty());
+
+        for result in &results {
+            let text = format!(
+                "{} {}",
+                result.title.to_lowercase(),
+                result.snippet.to_lowercase()
+            );
+            assert!(text.contains("machine") && text.contains("learning"));
+        }
+    }
+
+    #[test]
+    fn test_boolean_or_search() {
+        let index = create_test_index();
+        let query = Query::Boolean {
+            operator: BooleanOperator::Or,
+            queries: vec![
+                Query::Term("artificial".to_string()),
+                Query::Term("neural".to_string()),
+            ],
+        };
+        let searcher = Searcher::new(&index);
+        let results = searcher.search_with_query(&query);
+
+        // Should find documents containing either "artificial" OR "neural"
+        assert!(!results.is_empty());
+
+        for result in &results {
+            let text = format!(
+                "{} {}",
+                result.title.to_lowercase(),
+                result.snippet.to_lowercase()
+            );
+            assert!(text.contains("artificial") || text.contains("neural"));
+        }
+    }
+
+    #[test]
+    fn test_boolean_not_search() {
+        let index = create_test_index();
+        let query = Query::Boolean {
+            operator: BooleanOperator::Not,
+            queries: vec![
+                Query::Term("learning".to_string()),
+                Query::Term("machine".to_string()),
+            ],
+        };
+        let searcher = Searcher::new(&index);
+        let results = searcher.search_with_query(&query);
+
+        // Should find documents containing "learning" but NOT "machine"
+        for result in &results {
+            let text = format!(
+                "{} {}",
+                result.title.to_lowercase(),
+                result.snippet.to_lowercase()
+            );
+            assert!(text.contains("learning") && !text.contains("machine"));
+        }
+    }
+
+    #[test]
+    fn test_nested_not_filters_without_scoring() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index);
+        let machine = Query::Term("machine".to_string());
+        let not_algorithms = Query::Boolean {
+            operator: BooleanOperator::Not,
+            queries: vec![Query::Term("algorithms".to_string())],
+        };
+        let query = Query::Boolean {
+            operator: BooleanOperator::And,
+            queries: vec![machine.clone(), not_algorithms.clone()],
+        };
+
+        let alone: HashMap<DocumentId, f64> = searcher
+            .search_with_query(&machine)
+            .into_iter()
+            .map(|r| (r.doc_id, r.score))
+            .collect();
+        let filtered = searcher.search_with_query(&query);
+
+        assert!(!filtered.is_empty());
+        assert!(filtered.len() < alone.len());
+        for result in &filtered {
+            assert_eq!(result.score, alone[&result.doc_id]);
+            assert_eq!(index.get_term_frequency("algorithms", result.doc_id), 0);
+        }
+        assert!(
+            searcher
+                .explain(&query, filtered[0].doc_id)
+                .unwrap()
+                .contributions
+                .iter()
+                .all(|c| matches!(&c.query, Query::Term(t) if t == "machine"))
+        );
+
+        let everything_else = searcher.search_with_query(&not_algorithms);
+        assert!(everything_else.iter().all(|r| r.score == 0.0));
+    }
+
+    #[test]
+    fn test_match_all_and_match_none() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index);
+
+        let all = searcher.search_with_query(&Query::MatchAll);
+        assert_eq!(all.len(), index.total_documents());
+        assert!(all.iter().all(|r| r.score == 1.0));
+        assert!(searcher.search_with_query(&Query::MatchNone).is_empty());
+
+        let without_learning = searcher.search_with_query(&Query::Boolean {
+            operator: BooleanOperator::Not,
+            queries: vec![Query::MatchAll, Query::Term("learning".to_string())],
+        });
+        let mut ids: Vec<DocumentId> = without_learning.iter().map(|r| r.doc_id).collect();
+        ids.sort();
+        let mut expected: Vec<DocumentId> =
+            index.documents_without("learning").into_iter().collect();
+        expected.sort();
+        assert!(!ids.is_empty());
+        assert_eq!(ids, expected);
+
+        let and_none = searcher.search_with_query(&Query::Boolean {
+            operator: BooleanOperator::And,
+            queries: vec![Query::Term("learning".to_string()), Query::MatchNone],
+        });
+        assert!(and_none.is_empty());
+    }
+
+    #[test]
+    fn test_snippet_highlighting_escapes_html() {
+        let mut index = InvertedIndex::new();
+        index.add_document(
+            "Unsafe".to_string(),
+            "rust & <script>alert('rust')</script> here".to_string(),
+        );
+
+        let plain = Searcher::new(&index)
+            .with_snippet_config(SnippetConfig::default())
+            .search("rust");
+        assert!(plain[0].snippet.starts_with("<b>rust</b> & <script>"));
+
+        let escaped = Searcher::new(&index)
+            .with_snippet_config(SnippetConfig {
+                escape_html: true,
+                ..Default::default()
+            })
+            .search("rust");
+        assert_eq!(
+            escaped[0].snippet,
+            "<b>rust</b> &amp; &lt;script&gt;alert(&#39;<b>rust</b>&#39;)&lt;/script&gt; here"
+        );
+
+        let unhighlighted = Searcher::new(&index).search("rust");
+        assert!(!unhighlighted[0].snippet.contains("<b>"));
+    }
+
+    #[test]
+    fn test_search_boolean_mixes_terms_and_phrases() {
+        let mut index = InvertedIndex::new();
+        let loose = index.add_document(
+            "Loose".to_string(),
+            "machine learning with a network of neural models".to_string(),
+        );
+        let exact = index.add_document(
+            "Exact".to_string(),
+            "machine learning with a neural network".to_string(),
+        );
+        index.add_document("Other".to_string(), "cooking recipes".to_string());
+        let searcher = Searcher::new(&index);
+
+        let results = searcher
+            .search_boolean(BooleanOperator::Or, "machine learning \"neural network\"")
+            .unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].doc_id, exact);
+        assert_eq!(results[1].doc_id, loose);
+        assert!(results[0].score > results[1].score);
+
+        let results = searcher
+            .search_boolean(BooleanOperator::And, "machine \"neural network\"")
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].doc_id, exact);
+    }
+
+    #[test]
+    fn test_snippet_finds_folded_term() {
+        let mut tokenizer = crate::tokenizer::Tokenizer::new();
+        tokenizer.set_folding(true);
+        let mut index = InvertedIndex::with_tokenizer(tokenizer);
+        let content = format!("{} then we met at the café downtown", "filler ".repeat(20));
+        index.add_document("Meeting".to_string(), content);
+
+        let results = Searcher::new(&index)
+            .with_snippet_config(SnippetConfig::default())
+            .search("cafe");
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].snippet.starts_with("..."));
+        assert!(results[0].snippet.contains("<b>café</b> downtown"));
+    }
+
+    #[test]
+    fn test_number_handling_applies_to_queries() {
+        use crate::tokenizer::NumberHandling;
+
+        let build = |handling: NumberHandling| {
+            let mut tokenizer = crate::tokenizer::Tokenizer::new();
+            tokenizer.set_number_handling(handling);
+            let mut index = InvertedIndex::with_tokenizer(tokenizer);
+            index.add_document("Release".to_string(), "shipped in 2023".to_string());
+            index
+        };
+        let hits = |index: &InvertedIndex, query: &str| Searcher::new(index).search(query).len();
+
+        let index = build(NumberHandling::Index);
+        assert_eq!(hits(&index, "2023"), 1);
+        assert_eq!(hits(&index, "1999"), 0);
+
+        let index = build(NumberHandling::Skip);
+        assert_eq!(hits(&index, "2023"), 0);
+        assert_eq!(hits(&index, "shipped 2023"), 1);
+
+        let index = build(NumberHandling::Normalize);
+        assert_eq!(hits(&index, "2023"), 1);
+        assert_eq!(hits(&index, "1999"), 1);
+    }
+
+    #[test]
+    fn test_highlight_marks_each_term_with_its_index() {
+        let mut index = InvertedIndex::new();
+        index.add_document(
+            "Neural".to_string(),
+            "learning with neural models makes neural learning fun".to_string(),
+        );
+        let config = SnippetConfig {
+            pre_tag: "<mark data-term=\"{term}\">".to_string(),
+            post_tag: "</mark>".to_string(),
+            ..Default::default()
+        };
+        let searcher = Searcher::new(&index).with_snippet_config(config);
+
+        let results = searcher
+            .search_boolean(BooleanOperator::And, "neural learning")
+            .unwrap();
+
+        assert_eq!(
+            results[0].snippet,
+            "<mark data-term=\"1\">learning</mark> with <mark data-term=\"0\">neural</mark> \
+             models makes <mark data-term=\"0\">neural</mark> <mark data-term=\"1\">learning</mark> fun"
+        );
+    }
+
+    #[test]
+    fn test_phrase_highlighted_as_one_span() {
+        let mut index = InvertedIndex::new();
+        index.add_document(
+            "ML".to_string(),
+            "machine learning beats learning by machine; machine  learning wins".to_string(),
+        );
+        let searcher = Searcher::new(&index).with_snippet_config(SnippetConfig::default());
+
+        let results = searcher.search_with_query(&Query::Phrase(vec![
+            "machine".to_string(),
+            "learning".to_string(),
+        ]));
+
+        assert_eq!(
+            results[0].snippet,
+            "<b>machine learning</b> beats learning by machine; <b>machine  learning</b> wins"
+        );
+    }
+
+    #[test]
+    fn test_explain_splits_term_and_phrase_contributions() {
+        let mut index = InvertedIndex::new();
+        let doc = index.add_document(
+            "ML".to_string(),
+            "rust for machine learning and more machine learning".to_string(),
+        );
+        index.add_document("Other".to_string(), "rust compilers".to_string());
+        index.add_document("Unrelated".to_string(), "cooking recipes".to_string());
+        let searcher = Searcher::new(&index);
+        let query = crate::parse_query("rust \"machine learning\"^2").unwrap();
+
+        let explanation = searcher.explain(&query, doc).unwrap();
+
+        assert_eq!(explanation.contributions.len(), 2);
+        let term = explanation.score_from(ContributionSource::Term);
+        let phrase = explanation.score_from(ContributionSource::Phrase);
+        assert!(term > 0.0 && phrase > 0.0);
+        assert!((term + phrase - explanation.score).abs() < 1e-9);
+        assert_eq!(
+            searcher.search_with_query(&query)[0].score,
+            explanation.score
+        );
+        assert!(searcher.explain(&query, doc + 1).is_none());
+    }
+
+    #[test]
+    fn test_score_distribution_separates_peaked_and_flat() {
+        let mut index = InvertedIndex::new();
+        index.add_document("Rust".to_string(), "rust rust rust ownership".to_string());
+        for i in 0..4 {
+            index.add_document(format!("Note {}", i), "shared note rust".to_string());
+        }
+        index.add_document("Other".to_string(), "unrelated".to_string());
+        let searcher = Searcher::new(&index);
+
+        let (results, peaked) = searcher.search_with_distribution(&Query::Term("rust".to_string()));
+        assert_eq!(results.len(), 5);
+        assert_eq!(peaked.max, results[0].score);
+        assert_eq!(peaked.min, results[4].score);
+        assert!(peaked.min < peaked.mean && peaked.mean < peaked.max);
+        assert!(peaked.top_ratio.unwrap() > 1.5);
+
+        let (_, flat) = searcher.search_with_distribution(&Query::Term("shared".to_string()));
+        assert_eq!(flat.max, flat.min);
+        assert_eq!(flat.mean, flat.max);
+        assert_eq!(flat.top_ratio, Some(1.0));
+
+        let (none, empty) = searcher.search_with_distribution(&Query::Term("absent".to_string()));
+        assert!(none.is_empty());
+        assert_eq!(empty, ScoreDistribution::default());
+    }
+
+    #[test]
+    fn test_default_snippet_window() {
+        let mut index = InvertedIndex::new();
+        let content = format!("{} needle {}", "a".repeat(80), "b".repeat(80));
+        index.add_document("Doc".to_string(), content.clone());
+        index.add_document("Other".to_string(), "z".repeat(150));
+        let searcher = Searcher::new(&index);
+
+        // 50 bytes either side of the match
+        let results = searcher.search("needle");
+        assert_eq!(
+            results[0].snippet,
+            format!("...{}...", &content[81 - 50..87 + 50])
+        );
+
+        // Nothing visible to center on: the first 100 characters
+        let snippet = searcher
+            .build_result(&Query::MatchAll, 1, 0.0)
+            .unwrap()
+            .snippet;
+        assert_eq!(snippet, format!("{}...", "z".repeat(100)));
+    }
+
+    #[test]
+    fn test_snippet_length_in_chars_or_bytes() {
+        let mut index = InvertedIndex::new();
+        let content = format!("{} needle {}", "語".repeat(200), "語".repeat(200));
+        index.add_document("Wide".to_string(), content);
+        let body = |snippet: &str| snippet.trim_matches('.').to_string();
+
+        let chars = Searcher::new(&index)
+            .with_snippet_length(60, SnippetLengthUnit::Chars)
+            .search("needle");
+        assert_eq!(body(&chars[0].snippet).chars().count(), 60);
+        assert!(chars[0].snippet.contains(" needle "));
+
+        let bytes = Searcher::new(&index)
+            .with_snippet_length(60, SnippetLengthUnit::Bytes)
+            .search("needle");
+        // The 54 bytes around the match shrink to whole three-byte characters
+        assert_eq!(body(&bytes[0].snippet).len(), 2 * (1 + 8 * 3) + 6);
+        assert!(body(&bytes[0].snippet).chars().count() < 60);
+    }
+
+    #[test]
+    fn test_max_phrase_terms() {
+        let mut index = InvertedIndex::new();
+        index.add_document(
+            "Fox".to_string(),
+            "quick brown fox jumps over lazy dogs".to_string(),
+        );
+        index.set_max_phrase_terms(Some(3));
+
+        assert_eq!(index.try_query("\"quick brown fox\"").unwrap().len(), 1);
+        let err = index
+            .try_query("dogs \"quick brown fox jumps\"")
+            .unwrap_err();
+        assert_eq!(err, QueryError::PhraseTooLong { terms: 4, max: 3 });
+        assert_eq!(err.to_string(), "phrase has 4 terms; at most 3 are allowed");
+        assert!(matches!(
+            index.try_query("\"open"),
+            Err(QueryError::Parse(ParseError::UnterminatedQuote { .. }))
+        ));
+        assert!(
+            Searcher::new(&index)
+                .search_boolean(BooleanOperator::Or, "\"quick brown fox jumps\"")
+                .is_err()
+        );
+
+        index.set_max_phrase_terms(None);
+        assert_eq!(
+            index.try_query("\"quick brown fox jumps\"").unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_bm25f_favors_short_title_over_long_body() {
+        let mut index = InvertedIndex::new();
+        let titled = index.add_document(
+            "Rust".to_string(),
+            "notes about memory and types".to_string(),
+        );
+        let buried = index.add_document(
+            "Notes".to_string(),
+            format!(
+                "{} rust {}",
+                "filler words ".repeat(20),
+                "more filler ".repeat(10)
+            ),
+        );
+        index.add_document("Other".to_string(), "unrelated text".to_string());
+        let weights = [(FieldType::Title, 1.0), (FieldType::Content, 1.0)];
+
+        let results = Searcher::new(&index).search_bm25f("rust", &weights);
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].doc_id, titled);
+        assert_eq!(results[1].doc_id, buried);
+        assert!(results[0].score > results[1].score);
+
+        let content_only = Searcher::new(&index).search_bm25f("rust", &[(FieldType::Content, 1.0)]);
+        assert_eq!(content_only.len(), 1);
+        assert_eq!(content_only[0].doc_id, buried);
+    }
+
+    #[test]
+    fn test_highlighter_on_external_text() {
+        let index = InvertedIndex::new();
+        let highlighter = index.highlighter(&["Rust", "memory safety"], SnippetConfig::default());
+
+        assert_eq!(
+            highlighter.highlight("RUST gives Memory Safety; rusty trust in rust."),
+            "<b>RUST</b> gives <b>Memory Safety</b>; rusty trust in <b>rust</b>."
+        );
+        assert_eq!(
+            highlighter.highlight("memory is not safety"),
+            "memory is not safety"
+        );
+
+        let escaped = Highlighter::new(
+            Tokenizer::new(),
+            &["script"],
+            SnippetConfig {
+                pre_tag: "<mark data-term=\"{term}\">".to_string(),
+                post_tag: "</mark>".to_string(),
+                escape_html: true,
+            },
+        );
+        assert_eq!(
+            escaped.highlight("<script> tag"),
+            "&lt;<mark data-term=\"0\">script</mark>&gt; tag"
+        );
+    }
+
+    #[test]
+    fn test_or_coverage_weight_rewards_breadth() {
+        let mut index = InvertedIndex::new();
+        let broad = index.add_document(
+            "Broad".to_string(),
+            "rust cargo crates traits zyzzyva".to_string(),
+        );
+        let rare = index.add_document("Rare".to_string(), "quixotic".to_string());
+        for i in 0..6 {
+            index.add_document(
+                format!("Filler {}", i),
+                "rust cargo crates traits".to_string(),
+            );
+        }
+        let query = Query::Boolean {
+            operator: BooleanOperator::Or,
+            queries: ["rust", "cargo", "crates", "traits", "quixotic"]
+                .iter()
+                .map(|t| Query::Term(t.to_string()))
+                .collect(),
+        };
+
+        let plain = Searcher::new(&index).search_with_query(&query);
+        assert_eq!(plain[0].doc_id, rare);
+
+        let covering = || ScoringOptions {
+            coverage_weight: 1.0,
+            ..Default::default()
+        };
+        let covered = Searcher::with_options(&index, covering()).search_with_query(&query);
+        let position = |id| covered.iter().position(|r| r.doc_id == id).unwrap();
+        assert!(position(broad) < position(rare));
+        let rare_plain = plain.iter().find(|r| r.doc_id == rare).unwrap().score;
+        let rare_covered = covered[position(rare)].score;
+        assert!((rare_covered - rare_plain / 5.0).abs() < 1e-12);
+
+        let explanation = Searcher::with_options(&index, covering())
+            .explain(&query, broad)
+            .unwrap();
+        let total: f64 = explanation.contributions.iter().map(|c| c.score).sum();
+        assert!((total - explanation.score).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_search_terms_skips_analysis() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index);
+        let summary = |results: Vec<SearchResult>| -> Vec<(DocumentId, f64)> {
+            results.into_iter().map(|r| (r.doc_id, r.score)).collect()
+        };
+        let terms = vec!["machine".to_string(), "learning".to_string()];
+
+        for operator in [
+            BooleanOperator::And,
+            BooleanOperator::Or,
+            BooleanOperator::Not,
+        ] {
+            assert_eq!(
+                summary(searcher.search_terms(&terms, operator.clone())),
+                summary(
+                    searcher
+                        .search_boolean(operator, "Machine LEARNING")
+                        .unwrap()
+                )
+            );
+        }
+        // No analysis: an unnormalized term matches nothing
+        assert!(
+            searcher
+                .search_terms(&["Machine".to_string()], BooleanOperator::Or)
+                .is_empty()
+        );
+        // Scoring options apply as they do to boolean queries
+        let options = ScoringOptions {
+            normalize_clauses: true,
+            auto_stop_word_ratio: Some(0.5),
+            ..Default::default()
+        };
+        let tuned = Searcher::with_options(&index, options);
+        for operator in [BooleanOperator::And, BooleanOperator::Or] {
+            let query = Query::Boolean {
+                operator: operator.clone(),
+                queries: vec![
+                    Query::Term("learning".to_string()),
+                    Query::Term("deep".to_string()),
+                ],
+            };
+            assert_eq!(
+                summary(
+                    tuned.search_terms(&["learning".to_string(), "deep".to_string()], operator)
+                ),
+                summary(tuned.search_with_query(&query))
+            );
+        }
+    }
+
+    #[test]
+    fn test_default_operator_controls_query() {
+        let mut index = create_test_index();
+        assert_eq!(index.default_operator(), &BooleanOperator::And);
+
+        let ids = |results: Vec<SearchResult>| {
+            let mut ids: Vec<DocumentId> = results.into_iter().map(|r| r.doc_id).collect();
+            ids.sort();
+            ids
+        };
+        let deep = ids(index.query("deep"));
+        let machine = ids(index.query("machine"));
+
+        let intersection = ids(index.query("deep machine"));
+        assert!(
+            intersection
+                .iter()
+                .all(|id| deep.contains(id) && machine.contains(id))
+        );
+
+        index.set_default_operator(BooleanOperator::Or);
+        let union = ids(index.query("deep machine"));
+        let mut expected: Vec<DocumentId> = deep.iter().chain(&machine).cloned().collect();
+        expected.sort();
+        expected.dedup();
+        assert_eq!(union, expected);
+        assert!(union.len() > intersection.len());
+
+        let response = Searcher::new(&index).search_response("deep machine");
+        assert_eq!(ids(response.results), expected);
+    }
+
+    #[test]
+    fn test_estimate_hits_is_upper_bound() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index);
+        let term = |t: &str| Query::Term(t.to_string());
+        let queries = vec![
+            term("learning"),
+            term("missing"),
+            Query::Phrase(vec!["machine".to_string(), "learning".to_string()]),
+            Query::Wildcard("learn*".to_string()),
+            Query::Boolean {
+                operator: BooleanOperator::And,
+                queries: vec![term("machine"), term("learning")],
+            },
+            Query::Boolean {
+                operator: BooleanOperator::Or,
+                queries: vec![term("machine"), term("learning"), term("deep")],
+            },
+            Query::Boolean {
+                operator: BooleanOperator::Not,
+                queries: vec![term("learning"), term("deep")],
+            },
+            Query::FieldScoped(vec![(FieldType::Title, term("learning"))]),
+            Query::SpanNear {
+                clauses: vec![term("machine"), term("learning")],
+                slop: 1,
+                in_order: true,
+                same_sentence: false,
+            },
+            Query::MatchAll,
+            Query::MatchNone,
+        ];
+
+        for query in &queries {
+            let actual = searcher.search_with_query(query).len();
+            let estimate = searcher.estimate_hits(query);
+            assert!(estimate >= actual, "{:?}: {} < {}", query, estimate, actual);
+            assert!(estimate <= index.total_documents());
+        }
+        assert_eq!(
+            searcher.estimate_hits(&term("learning")),
+            index.get_document_frequency("learning")
+        );
+        assert_eq!(searcher.estimate_hits(&term("missing")), 0);
+    }
+
+    #[test]
+    fn test_idf_never_negative_when_df_exceeds_total() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index);
+
+        assert_eq!(searcher.calculate_tfidf(1, 10, 3), 0.0);
+        assert_eq!(
+            searcher.bm25_weight(1, TermIdf::new(10, 3).bm25, 1.0, 1.2, 0.75),
+            0.0
+        );
+        assert!(searcher.calculate_tfidf(1, 1, 3) > 0.0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "exceeds total documents")]
+    fn test_df_above_total_documents_asserts_in_debug() {
+        let mut index = create_test_index();
+        let total = index.total_documents();
+        index.index.get_mut("learning").unwrap().document_frequency = total + 5;
+
+        Searcher::new(&index).search("learning");
+    }
+
+    #[test]
+    fn test_phrase_highlight_spans_stop_words() {
+        let mut index = InvertedIndex::new();
+        index.add_document(
+            "Survey".to_string(),
+            "a survey of the state of the art in retrieval".to_string(),
+        );
+        let searcher = Searcher::new(&index).with_snippet_config(SnippetConfig::default());
+
+        let results = searcher.search_with_query(&Query::Phrase(
+            ["state", "of", "the", "art"].map(String::from).to_vec(),
+        ));
+        assert_eq!(results.len(), 1);
+        assert!(
+            results[0].snippet.contains("<b>state of the art</b>"),
+            "{}",
+            results[0].snippet
+        );
+        // A phrase of nothing but stop words matches nothing
+        assert!(
+            searcher
+                .search_with_query(&Query::Phrase(vec!["of".to_string(), "the".to_string()]))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_phrase_never_bridges_title_and_content() {
+        let mut index = InvertedIndex::new();
+        let doc = index.add_document("Learn Rust".to_string(), "programming basics".to_string());
+        let phrase = Query::Phrase(vec!["rust".to_string(), "programming".to_string()]);
+        assert!(index.phrase_search("rust programming").is_empty());
+
+        let query = Query::Boolean {
+            operator: BooleanOperator::Or,
+            queries: vec![Query::Term("learn".to_string()), phrase],
+        };
+        let searcher = Searcher::new(&index)
+            .with_snippet_config(SnippetConfig::default())
+            .with_snippet_source(SnippetSource::FullText);
+        let results = searcher.search_with_query(&query);
+        assert_eq!(results[0].doc_id, doc);
+        assert_eq!(results[0].snippet, "<b>Learn</b> Rust | programming basics");
+        // Any visible separator still keeps the phrase from matching
+        let results = searcher
+            .with_full_text_separator(" ")
+            .search_with_query(&query);
+        assert_eq!(results[0].snippet, "<b>Learn</b> Rust programming basics");
+
+        let document = index.get_document(doc).unwrap();
+        let highlighter = Highlighter::new(
+            Tokenizer::new(),
+            &["rust programming"],
+            SnippetConfig::default(),
+        );
+        let separated = document.full_text_with_separator(&FIELD_SEPARATOR.to_string());
+        assert_eq!(highlighter.highlight(&separated), separated);
+        // A plain space would let the phrase run across the junction
+        assert!(highlighter.highlight(&document.full_text()).contains("<b>"));
+    }
+
+    #[test]
+    fn test_snippet_source_title_match() {
+        let mut index = InvertedIndex::new();
+        let content = "An unrelated opening paragraph about gardening, ".repeat(5);
+        index.add_document("Rust ownership explained".to_string(), content.clone());
+        index.add_document("Other".to_string(), "nothing relevant here".to_string());
+        let query = Query::Term("ownership".to_string());
+        let snippet = |source: SnippetSource| {
+            let searcher = Searcher::new(&index).with_snippet_source(source);
+            searcher.search_with_query(&query)[0].snippet.clone()
+        };
+
+        let content_snippet = snippet(SnippetSource::Content);
+        assert!(!content_snippet.contains("ownership"));
+        assert!(content_snippet.starts_with("An unrelated"));
+
+        for source in [SnippetSource::FullText, SnippetSource::Auto] {
+            let snippet = snippet(source);
+            assert!(
+                snippet.starts_with("Rust ownership explained"),
+                "{}",
+                snippet
+            );
+        }
+        assert_eq!(snippet(SnippetSource::Title), "Rust ownership explained");
+
+        // Auto still prefers a content match
+        let query = Query::Term("gardening".to_string());
+        let searcher = Searcher::new(&index).with_snippet_source(SnippetSource::Auto);
+        let snippet = searcher.search_with_query(&query)[0].snippet.clone();
+        assert!(!snippet.contains("Rust ownership"));
+    }
+
+    #[test]
+    fn test_score_matches_search_results() {
+        let index = create_test_index();
+        let searcher = Searcher::with_options(
+            &index,
+            ScoringOptions {
+                model: ScoringModel::bm25(),
+                ..ScoringOptions::default()
+            },
+        );
+        let query = Query::Boolean {
+            operator: BooleanOperator::Or,
             queries: vec![
-                Query::Term("search".to_string()),
-                Query::Term("engine".to_string()),
+                Query::Term("learning".to_string()),
+                Query::Term("algorithms".to_string()),
             ],
         };
-        let phrase_query = Query::Phrase(vec!["machine".to_string(), "learning".to_string()]);
-        let wildcard_query = Query::Wildcard("learn*".to_string());
 
-        match term_query {
-            Query::Term(term) => assert_eq!(term, "search"),
-            _ => panic!("Expected Term query"),
+        let results = searcher.search_with_query(&query);
+        assert!(results.len() >= 3);
+        for result in &results {
+            assert_eq!(searcher.score(&query, result.doc_id), Some(result.score));
         }
+        let unmatched = (0..index.total_documents())
+            .find(|id| results.iter().all(|r| r.doc_id != *id))
+            .unwrap();
+        assert_eq!(searcher.score(&query, unmatched), None);
+    }
 
-        match boolean_query {
-            Query::Boolean { operator, queries } => {
-                assert!(matches!(operator, BooleanOperator::And));
-                assert_eq!(queries.len(), 2);
+    #[test]
+    fn test_proximity_ranks_phrase_then_near_then_scattered() {
+        let mut index = InvertedIndex::new();
+        let scattered = index.add_document(
+            "Scattered".to_string(),
+            "memory is discussed first and much later we talk about safety safety safety"
+                .to_string(),
+        );
+        let near = index.add_document(
+            "Near".to_string(),
+            "safety when handling memory matters".to_string(),
+        );
+        let exact = index.add_document(
+            "Exact".to_string(),
+            "rust offers memory safety without garbage collection overhead".to_string(),
+        );
+        index.add_document("Other".to_string(), "garbage collection".to_string());
+
+        let query = Query::Proximity {
+            terms: vec!["memory".to_string(), "safety".to_string()],
+            window: 2,
+        };
+        let searcher = Searcher::new(&index).with_snippet_config(SnippetConfig::default());
+        let results = searcher.search_with_query(&query);
+        assert_eq!(
+            results.iter().map(|r| r.doc_id).collect::<Vec<_>>(),
+            vec![exact, near, scattered]
+        );
+        assert!(results[0].snippet.contains("<b>memory safety</b>"));
+        assert_eq!(searcher.estimate_hits(&query), 3);
+
+        // A zero window leaves only the phrase above co-occurrence
+        let strict = Query::Proximity {
+            terms: vec!["memory".to_string(), "safety".to_string()],
+            window: 0,
+        };
+        let near_score = searcher.score(&strict, near).unwrap();
+        assert!(near_score < 1.0);
+        assert!(searcher.score(&strict, exact).unwrap() >= 2.0);
+    }
+
+    #[test]
+    fn test_ascending_reverses_ranking() {
+        let index = create_test_index();
+        let query = Query::Boolean {
+            operator: BooleanOperator::Or,
+            queries: vec![
+                Query::Term("learning".to_string()),
+                Query::Term("machine".to_string()),
+                Query::Term("algorithms".to_string()),
+            ],
+        };
+        let ranking = |ascending: bool| -> Vec<(DocumentId, f64)> {
+            let options = ScoringOptions {
+                ascending,
+                ..ScoringOptions::default()
+            };
+            Searcher::with_options(&index, options)
+                .search_with_query(&query)
+                .into_iter()
+                .map(|r| (r.doc_id, r.score))
+                .collect()
+        };
+
+        let descending = ranking(false);
+        assert!(descending.len() >= 3);
+        assert!(descending.windows(2).all(|w| w[0].1 > w[1].1));
+        let mut ascending = ranking(true);
+        ascending.reverse();
+        assert_eq!(ascending, descending);
+    }
+
+    #[test]
+    fn test_top_terms_for_document_prefers_rare_terms() {
+        let mut index = InvertedIndex::new();
+        let doc = index.add_document(
+            "Notes".to_string(),
+            "rust rust borrowck lifetimes programming programming programming".to_string(),
+        );
+        for i in 0..5 {
+            index.add_document(format!("Other {}", i), "programming rust guide".to_string());
+        }
+
+        let top = index.top_terms_for_document(doc, 3);
+        let terms: Vec<&str> = top.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(terms, vec!["borrowck", "lifetimes", "notes"]);
+        assert!(top.windows(2).all(|w| w[0].1 >= w[1].1));
+
+        // The warm idf cache gives the same weights
+        let all = index.top_terms_for_document(doc, usize::MAX);
+        assert_eq!(all.len(), 5);
+        index.warm_up();
+        let searcher = Searcher::new(&index);
+        assert_eq!(searcher.top_terms_for_document(doc, usize::MAX), all);
+        assert_eq!(searcher.idf_computations(), 0);
+        assert!(index.top_terms_for_document(999, 3).is_empty());
+    }
+
+    #[test]
+    fn test_nested_boolean_scores_combine_recursively() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index);
+        let term = |t: &str| Query::Term(t.to_string());
+        let boolean =
+            |operator: BooleanOperator, queries: Vec<Query>| Query::Boolean { operator, queries };
+        // (learning AND (machine OR applications)) OR (algorithms NOT search)
+        let query = boolean(
+            BooleanOperator::Or,
+            vec![
+                boolean(
+                    BooleanOperator::And,
+                    vec![
+                        term("learning"),
+                        boolean(
+                            BooleanOperator::Or,
+                            vec![term("machine"), term("applications")],
+                        ),
+                    ],
+                ),
+                boolean(
+                    BooleanOperator::Not,
+                    vec![term("algorithms"), term("search")],
+                ),
+            ],
+        );
+
+        let leaf = |t: &str, doc: DocumentId| searcher.score(&term(t), doc);
+        let expected = |doc: DocumentId| -> Option<f64> {
+            let inner_or = match (leaf("machine", doc), leaf("applications", doc)) {
+                (None, None) => None,
+                (a, b) => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+            };
+            let and = leaf("learning", doc).zip(inner_or).map(|(a, b)| a + b);
+            let not = leaf("algorithms", doc).filter(|_| leaf("search", doc).is_none());
+            match (and, not) {
+                (None, None) => None,
+                (a, b) => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+            }
+        };
+
+        let mut matched = 0;
+        for doc in index.document_ids() {
+            let actual = searcher.score(&query, doc);
+            assert_eq!(actual.is_some(), expected(doc).is_some(), "doc {}", doc);
+            if let (Some(actual), Some(expected)) = (actual, expected(doc)) {
+                assert!((actual - expected).abs() < 1e-12, "doc {}", doc);
+                matched += 1;
             }
-            _ => panic!("Expected Boolean query"),
         }
-        match phrase_query {
-            Query::Phrase(terms) => {
-                assert_eq!(terms.len(), 2);
-                assert!(terms[0] == "machine" && terms[1] == "learning");
+        // ML matches both sides, Data Science only the AND
+        assert_eq!(matched, 2);
+        assert_eq!(searcher.score(&query, 4), None);
+    }
+
+    #[test]
+    fn test_optimize_flattens_and_deduplicates() {
+        let term = |t: &str| Query::Term(t.to_string());
+        let and = |queries: Vec<Query>| Query::Boolean {
+            operator: BooleanOperator::And,
+            queries,
+        };
+        let or = |queries: Vec<Query>| Query::Boolean {
+            operator: BooleanOperator::Or,
+            queries,
+        };
+
+        assert_eq!(
+            and(vec![and(vec![term("a"), term("b")]), term("c")]).optimize(),
+            and(vec![term("a"), term("b"), term("c")])
+        );
+        assert_eq!(
+            or(vec![
+                term("a"),
+                or(vec![term("b"), or(vec![term("c")])]),
+                term("a")
+            ])
+            .optimize(),
+            or(vec![term("a"), term("b"), term("c")])
+        );
+        // Mixed operators nest as before; single clauses unwrap
+        assert_eq!(
+            and(vec![or(vec![term("a"), term("a")]), term("b")]).optimize(),
+            and(vec![term("a"), term("b")])
+        );
+        let not = Query::Boolean {
+            operator: BooleanOperator::Not,
+            queries: vec![and(vec![and(vec![term("a")])]), term("a")],
+        };
+        assert_eq!(
+            not.optimize(),
+            Query::Boolean {
+                operator: BooleanOperator::Not,
+                queries: vec![term("a"), term("a")],
             }
-            _ => panic!("Expected Phrase query"),
+        );
+        // An empty clause still matches nothing
+        assert_eq!(
+            and(vec![and(vec![]), term("a")]).optimize(),
+            and(vec![and(vec![]), term("a")])
+        );
+    }
+
+    #[test]
+    fn test_optimized_query_returns_same_results() {
+        let index = create_test_index();
+        let searcher = Searcher::new(&index);
+        let term = |t: &str| Query::Term(t.to_string());
+        let query = Query::Boolean {
+            operator: BooleanOperator::And,
+            queries: vec![
+                Query::Boolean {
+                    operator: BooleanOperator::And,
+                    queries: vec![term("learning"), term("algorithms")],
+                },
+                Query::Boolean {
+                    operator: BooleanOperator::Or,
+                    queries: vec![
+                        term("machine"),
+                        Query::Boolean {
+                            operator: BooleanOperator::Or,
+                            queries: vec![term("techniques")],
+                        },
+                    ],
+                },
+            ],
+        };
+
+        let optimized = searcher.optimize(query.clone());
+        let Query::Boolean { queries, .. } = &optimized else {
+            panic!("expected a boolean query, got {:?}", optimized);
+        };
+        assert_eq!(queries.len(), 3);
+        assert_eq!(queries[0], term("algorithms"));
+
+        let expected = searcher.search_with_query(&query);
+        let actual = searcher.search_with_query(&optimized);
+        assert!(!expected.is_empty());
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(&expected) {
+            assert_eq!(a.doc_id, e.doc_id);
+            assert!((a.score - e.score).abs() < 1e-12);
         }
+    }
 
-        match wildcard_query {
-            Query::Wildcard(pattern) => assert_eq!(pattern, "learn*"),
-            _ => panic!("Expected Wildcard query"),
+    #[test]
+    fn test_sentence_snippets() {
+        let mut index = InvertedIndex::new();
+        let content = "Rust is a systems language. It has no garbage collector! \
+                       Ownership rules are checked at compile time. Is it fast? \
+                       Benchmarks say yes.";
+        index.add_document("Rust".to_string(), content.to_string());
+        let snippet = |max_sentences: usize, term: &str| {
+            Searcher::new(&index)
+                .with_snippet_strategy(SnippetStrategy::Sentence { max_sentences })
+                .search_with_query(&Query::Term(term.to_string()))[0]
+                .snippet
+                .clone()
+        };
+
+        assert_eq!(
+            snippet(1, "ownership"),
+            "Ownership rules are checked at compile time."
+        );
+        assert_eq!(
+            snippet(2, "ownership"),
+            "Ownership rules are checked at compile time. Is it fast?"
+        );
+        // At the end of the text, preceding sentences fill the cap
+        assert_eq!(snippet(2, "benchmarks"), "Is it fast? Benchmarks say yes.");
+        assert_eq!(snippet(1, "garbage"), "It has no garbage collector!");
+
+        assert_eq!(
+            sentence_bounds("Use e.g. this. Done"),
+            vec![(0, 14), (15, 19)]
+        );
+    }
+
+    #[test]
+    fn test_boolean_search_paginated() {
+        let mut index = InvertedIndex::new();
+        for i in 0..7 {
+            let extra = "rust ".repeat(i);
+            index.add_document(format!("Doc {}", i), format!("{}rust search engine", extra));
+        }
+        index.add_document("Other".to_string(), "python search".to_string());
+
+        let all = index.boolean_search(BooleanOperator::And, vec!["rust", "search"]);
+        assert_eq!(all.len(), 7);
+        let mut paged = Vec::new();
+        for offset in (0..9).step_by(3) {
+            let (page, total) = index.boolean_search_paginated(
+                BooleanOperator::And,
+                vec!["rust", "search"],
+                offset,
+                3,
+            );
+            assert_eq!(total, 7);
+            assert_eq!(page.len(), 3.min(7usize.saturating_sub(offset)));
+            paged.extend(page);
         }
+        assert_eq!(
+            paged.iter().map(|r| r.doc_id).collect::<Vec<_>>(),
+            all.iter().map(|r| r.doc_id).collect::<Vec<_>>()
+        );
+
+        let (page, total) =
+            index.boolean_search_paginated(BooleanOperator::Or, vec!["python"], 5, 10);
+        assert!(page.is_empty());
+        assert_eq!(total, 1);
     }
 
     #[test]
-    fn test_searcher_creation() {
+    fn test_search_multi_sorted() {
+        let mut index = InvertedIndex::new();
+        let mut add = |title: &str, content: &str, year: Option<&str>| {
+            let metadata = year
+                .map(|year| HashMap::from([("year".to_string(), year.to_string())]))
+                .unwrap_or_default();
+            index.add_document_with_metadata(title.to_string(), content.to_string(), metadata)
+        };
+        let old = add("Old", "rust guide", Some("2015"));
+        let new = add("New", "rust guide", Some("2023"));
+        let undated = add("Undated", "rust guide", None);
+        let best = add("Best", "rust rust rust", Some("1999"));
+        add("Other", "python guide", Some("2024"));
+
+        let query = Query::Term("rust".to_string());
+        let searcher = Searcher::new(&index);
+        let ids = |sorts: &[SortSpec]| -> Vec<DocumentId> {
+            searcher
+                .search_multi_sorted(&query, sorts)
+                .iter()
+                .map(|r| r.doc_id)
+                .collect()
+        };
+        let year = SortKey::Metadata("year".to_string());
+
+        // Relevance first; the three tied guides by year, newest first
+        assert_eq!(
+            ids(&[
+                SortSpec::descending(SortKey::Relevance),
+                SortSpec::descending(year.clone())
+            ]),
+            vec![best, new, old, undated]
+        );
+        assert_eq!(
+            ids(&[SortSpec::ascending(year.clone())]),
+            vec![best, old, new, undated]
+        );
+        // No keys: ties all the way down fall back to document id
+        assert_eq!(ids(&[]), vec![old, new, undated, best]);
+    }
+
+    #[test]
+    fn test_auto_stop_word_ratio_ignores_common_terms() {
+        let mut index = InvertedIndex::new();
+        for (title, content) in [
+            ("Visit", "patient reported headache"),
+            ("Visit", "patient reported fever"),
+            ("Visit", "patient stable"),
+        ] {
+            index.add_document(title.to_string(), content.to_string());
+        }
+        let query = Query::Boolean {
+            operator: BooleanOperator::And,
+            queries: vec![
+                Query::Term("patient".to_string()),
+                Query::Term("fever".to_string()),
+            ],
+        };
+        let plain = Searcher::new(&index);
+        assert_eq!(plain.search("patient").len(), 3);
+
+        let options = ScoringOptions {
+            auto_stop_word_ratio: Some(0.9),
+            ..Default::default()
+        };
+        let searcher = Searcher::with_options(&index, options);
+        assert!(searcher.search("patient").is_empty());
+        // The common term no longer adds to scores but doesn't block matches
+        let results = searcher.search_with_query(&query);
+        assert_eq!(results.len(), 1);
+        let fever_only = searcher.score(&Query::Term("fever".to_string()), results[0].doc_id);
+        assert_eq!(Some(results[0].score), fever_only);
+        assert_eq!(searcher.search("reported").len(), 2);
+        // A query of nothing but stop words matches nothing
+        let common = Query::Boolean {
+            operator: BooleanOperator::Or,
+            queries: vec![Query::Term("patient".to_string())],
+        };
+        assert!(searcher.search_with_query(&common).is_empty());
+    }
+
+    #[test]
+    fn test_normalize_clauses_puts_query_types_on_one_scale() {
+        let mut index = InvertedIndex::new();
+        let phrase_only = index.add_document("A".to_string(), "neural networks".to_string());
+        let term_only = index.add_document("B".to_string(), "rust rust".to_string());
+        let both = index.add_document(
+            "C".to_string(),
+            "neural networks written in rust".to_string(),
+        );
+        let query = Query::Boolean {
+            operator: BooleanOperator::Or,
+            queries: vec![
+                Query::Phrase(vec!["neural".to_string(), "networks".to_string()]),
+                Query::Term("rust".to_string()),
+            ],
+        };
+        let raw = Searcher::new(&index);
+        assert!(
+            (raw.score(&query, phrase_only).unwrap() - raw.score(&query, term_only).unwrap()).abs()
+                > 1e-6
+        );
+
+        let options = ScoringOptions {
+            normalize_clauses: true,
+            ..Default::default()
+        };
+        let searcher = Searcher::with_options(&index, options);
+        let results = searcher.search_with_query(&query);
+
+        // Each clause's best match scores 1, so neither type dominates
+        assert_eq!(results[0].doc_id, both);
+        let phrase_score = searcher.score(&query, phrase_only).unwrap();
+        let term_score = searcher.score(&query, term_only).unwrap();
+        assert!((phrase_score - 1.0).abs() < 1e-9);
+        assert!((term_score - 1.0).abs() < 1e-9);
+        assert!(results.iter().all(|r| r.score <= 2.0 + 1e-9));
+
+        let explanation = searcher.explain(&query, both).unwrap();
+        let total: f64 = explanation.contributions.iter().map(|c| c.score).sum();
+        assert!((total - explanation.score).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_search_exact_with_dual_index() {
+        let mut tokenizer = crate::tokenizer::Tokenizer::new();
+        tokenizer.set_stem_algorithm(crate::tokenizer::StemAlgorithm::Porter);
+        tokenizer.set_dual_index(true);
+        let mut index = InvertedIndex::with_tokenizer(tokenizer);
+        let running = index.add_document("Jog".to_string(), "running every day".to_string());
+        let runs = index.add_document("Race".to_string(), "she runs fast".to_string());
+        let run = index.add_document("Sprint".to_string(), "a short run".to_string());
+        let searcher = Searcher::new(&index);
+        let ids = |results: Vec<SearchResult>| -> Vec<DocumentId> {
+            let mut ids: Vec<DocumentId> = results.iter().map(|r| r.doc_id).collect();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(ids(searcher.search("running")), vec![running, runs, run]);
+        assert_eq!(ids(searcher.search_exact("Running")), vec![running]);
+        assert_eq!(ids(searcher.search_exact("runs")), vec![runs]);
+        assert_eq!(ids(searcher.search_exact("run")), vec![run]);
+        // No document says "runned", so stemmed matches stand in
+        assert_eq!(
+            ids(searcher.search_exact("runned")),
+            vec![running, runs, run]
+        );
+        assert!(searcher.search_exact("running runs").is_empty());
+    }
+
+    #[test]
+    fn test_as_of_hides_expired_documents() {
+        let mut index = InvertedIndex::new();
+        let expired = index.add_document_with_metadata(
+            "Sale".to_string(),
+            "spring discount".to_string(),
+            HashMap::from([("expires_at".to_string(), "1000".to_string())]),
+        );
+        let live = index.add_document_with_metadata(
+            "Sale".to_string(),
+            "summer discount".to_string(),
+            HashMap::from([("expires_at".to_string(), "3000".to_string())]),
+        );
+        let forever = index.add_document("Sale".to_string(), "discount always".to_string());
+        let ids = |options: ScoringOptions| -> Vec<DocumentId> {
+            let mut ids: Vec<DocumentId> = Searcher::with_options(&index, options)
+                .search("discount")
+                .iter()
+                .map(|r| r.doc_id)
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        let options = ScoringOptions {
+            as_of: Some(2000),
+            expiration_key: Some("expires_at".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(options.clone()), vec![live, forever]);
+        let searcher = Searcher::with_options(&index, options.clone());
+        let query = Query::Term("discount".to_string());
+        assert!(searcher.score(&query, expired).is_none());
+        assert!(searcher.explain(&query, expired).is_none());
+        assert!(searcher.score(&query, live).is_some());
+        // Without an as_of, or without a key, nothing expires
+        assert_eq!(
+            ids(ScoringOptions {
+                as_of: None,
+                ..options.clone()
+            }),
+            vec![expired, live, forever]
+        );
+        assert_eq!(
+            ids(ScoringOptions {
+                expiration_key: None,
+                ..options
+            }),
+            vec![expired, live, forever]
+        );
+    }
+
+    #[test]
+    fn test_combine_sums_weighted_scores() {
+        let mut index = InvertedIndex::new();
+        let both = index.add_document(
+            "Rust guide".to_string(),
+            "systems programming in rust".to_string(),
+        );
+        let phrase_only =
+            index.add_document("Notes".to_string(), "systems programming".to_string());
+        let searcher = Searcher::new(&index);
+        let term = Query::Term("rust".to_string());
+        let phrase = Query::Phrase(vec!["systems".to_string(), "programming".to_string()]);
+
+        let results = searcher.combine(&[(term.clone(), 0.5), (phrase.clone(), 2.0)]);
+
+        let ids: Vec<DocumentId> = results.iter().map(|r| r.doc_id).collect();
+        assert_eq!(ids, vec![both, phrase_only]);
+        let expected = 0.5 * searcher.score(&term, both).unwrap()
+            + 2.0 * searcher.score(&phrase, both).unwrap();
+        assert!((results[0].score - expected).abs() < 1e-9);
+        assert!(searcher.combine(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_term_frequencies_over_queries() {
         let index = create_test_index();
         let searcher = Searcher::new(&index);
+        let log = [
+            "Machine Learning",
+            "the machine",
+            "MACHINE learning of the mind",
+            "a the of",
+            "title:mind^2 \"deep learning\"",
+            "\"unterminated machine",
+        ];
+
+        let counts = searcher.term_frequencies_over(log.iter().map(|q| q.to_string()));
+
+        assert_eq!(
+            counts,
+            HashMap::from([
+                ("machine".to_string(), 3),
+                ("learning".to_string(), 3),
+                ("mind".to_string(), 2),
+                ("deep".to_string(), 1),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_case_sensitive_phrase() {
+        let mut index = InvertedIndex::new();
+        let company = index.add_document(
+            "Earnings".to_string(),
+            "Apple reported record sales".to_string(),
+        );
+        let fruit = index.add_document(
+            "Orchard".to_string(),
+            "apple reported ripe by farmers".to_string(),
+        );
+        let searcher = Searcher::new(&index);
+        let words = |text: &str| text.split(' ').map(String::from).collect::<Vec<_>>();
+        let phrase_with_case = |terms| Query::PhraseWith {
+            terms,
+            options: PhraseOptions {
+                case_sensitive: true,
+                ..PhraseOptions::default()
+            },
+        };
+
+        let ids = |query: Query| -> Vec<DocumentId> {
+            let mut ids: Vec<DocumentId> = searcher
+                .search_with_query(&query)
+                .iter()
+                .map(|r| r.doc_id)
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(
+            ids(Query::Phrase(words("Apple reported"))),
+            vec![company, fruit]
+        );
+        assert_eq!(
+            ids(phrase_with_case(words("Apple reported"))),
+            vec![company]
+        );
+        assert_eq!(ids(phrase_with_case(words("apple reported"))), vec![fruit]);
+        assert!(ids(phrase_with_case(words("APPLE reported"))).is_empty());
+        // Stop words are skipped in the comparison as in matching
+        assert_eq!(ids(phrase_with_case(words("ripe by farmers"))), vec![fruit]);
+        // Snippets highlight only the matching case
+        let results = Searcher::new(&index)
+            .with_snippet_config(SnippetConfig::default())
+            .search_with_query(&phrase_with_case(words("apple reported")));
+        assert_eq!(results.len(), 1);
+        assert!(results[0].snippet.contains("<b>apple reported</b>"));
+    }
+
+    #[test]
+    fn test_phrase_respects_position_gaps() {
+        let phrase = Query::Phrase(vec!["quick".to_string(), "brown".to_string()]);
+        let build = |preserve: bool| {
+            let mut tokenizer = crate::tokenizer::Tokenizer::new();
+            tokenizer.set_preserve_position_gaps(preserve);
+            let mut index = InvertedIndex::with_tokenizer(tokenizer);
+            index.add_document("Fox".to_string(), "quick the brown fox".to_string());
+            index.add_document("Dog".to_string(), "a quick brown dog".to_string());
+            index
+        };
+
+        let dense = build(false);
+        assert_eq!(Searcher::new(&dense).search_with_query(&phrase).len(), 2);
+
+        let gapped = build(true);
+        let results = Searcher::new(&gapped).search_with_query(&phrase);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title, "Dog");
+        assert!(results[0].snippet.contains("quick brown dog"));
+    }
+
+    #[test]
+    fn test_phrase_with_stop_words_requires_their_gap() {
+        let mut tokenizer = crate::tokenizer::Tokenizer::new();
+        tokenizer.set_preserve_position_gaps(true);
+        let mut index = InvertedIndex::with_tokenizer(tokenizer);
+        let gapped = index.add_document(
+            "Review".to_string(),
+            "the state of the art system".to_string(),
+        );
+        index.add_document("Gallery".to_string(), "state art collection".to_string());
+        let words = |text: &str| text.split(' ').map(String::from).collect::<Vec<_>>();
+
+        let results = Searcher::new(&index)
+            .with_snippet_config(SnippetConfig::default())
+            .search_with_query(&Query::Phrase(words("state of the art")));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].doc_id, gapped);
+        assert!(results[0].snippet.contains("<b>state of the art</b>"));
+        // The gap must be the same width as in the query
+        let narrower = Query::Phrase(words("state of art"));
+        assert!(
+            Searcher::new(&index)
+                .search_with_query(&narrower)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_weighted_or_search() {
+        let mut index = InvertedIndex::new();
+        let rust = index.add_document("Rust".to_string(), "rust compiler".to_string());
+        let python = index.add_document("Python".to_string(), "python interpreter".to_string());
+        index.add_document("Tools".to_string(), "rust tooling".to_string());
+
+        // "python" is rarer, so it wins with equal weights...
+        let equal = index.weighted_or_search(&[("rust", 1.0), ("python", 1.0)]);
+        assert_eq!(equal[0].doc_id, python);
 
-        assert!(std::ptr::eq(searcher.index, &index));
+        // ...but a heavier weight on "rust" outranks it.
+        let weighted = index.weighted_or_search(&[("rust", 5.0), ("python", 1.0)]);
+        assert_eq!(weighted.len(), 3);
+        assert_eq!(weighted[0].doc_id, rust);
+        let python_score = weighted.iter().find(|r| r.doc_id == python).unwrap().score;
+        assert!(
+            (python_score - equal.iter().find(|r| r.doc_id == python).unwrap().score).abs() < 1e-12
+        );
     }
 
     #[test]
-    fn test_simple_term_search() {
+    fn test_phrase_search() {
         let index = create_test_index();
+        let query = Query::Phrase(vec!["machine".to_string(), "learning".to_string()]);
         let searcher = Searcher::new(&index);
+        let results = searcher.search_with_query(&query);
 
-        let results = searcher.search("machine");
-
-        // Should find 2 documents containing "machine"
-        assert_eq!(results.len(), 2);
-
-        // Results should be sorted by score (highest first)
-        assert!(results[0].score >= results[1].score);
-
-        // All results should contain the search term in title or snippet
+        // Should find documents containing the exact phrase "machine learning"
         for result in &results {
             let text = format!(
                 "{} {}",
                 result.title.to_lowercase(),
                 result.snippet.to_lowercase()
             );
-            assert!(text.contains("machine"));
+            assert!(text.contains("machine learning"));
         }
     }
 
     #[test]
-    fn test_tfidf_scoring() {
-        let index = create_test_index();
-        let searcher = Searcher::new(&index);
+    fn test_phrase_snippet_uses_positional_match() {
+        let mut index = InvertedIndex::new();
+        // "smart history" contains the substring "art history" but not the phrase
+        let content = "A smart history of computing opens the book, followed by a long detour through unrelated material. Only the final chapter covers art history properly.";
+        index.add_document("Book Review".to_string(), content.to_string());
 
-        // Search for a term that appears in multiple documents with different frequencies
-        let results = searcher.search("learning");
+        let results = index.phrase_search("art history");
+        assert_eq!(results.len(), 1);
 
-        assert!(!results.is_empty());
+        let snippet = &results[0].snippet;
+        assert!(snippet.contains("covers art history"));
+        assert!(!snippet.contains("smart history"));
+    }
 
-        // All scores should be positive
-        for result in &results {
-            assert!(result.score > 0.0);
-        }
+    #[test]
+    fn test_phrase_search_repeated_terms() {
+        let mut index = InvertedIndex::new();
+        let repeated =
+            index.add_document("Doubled".to_string(), "big data data pipelines".to_string());
+        index.add_document("Single".to_string(), "big data pipelines".to_string());
+        index.add_document("Apart".to_string(), "data lakes hold raw data".to_string());
 
-        // Results should be sorted by score (descending)
-        for i in 1..results.len() {
-            assert!(results[i - 1].score >= results[i].score);
-        }
+        let results = index.phrase_search("data data");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].doc_id, repeated);
+
+        // Three in a row needs three consecutive positions
+        assert!(index.phrase_search("data data data").is_empty());
     }
 
     #[test]
-    fn test_boolean_and_search() {
-        let index = create_test_index();
-        let query = Query::Boolean {
-            operator: BooleanOperator::And,
-            queries: vec![
-                Query::Term("machine".to_string()),
-                Query::Term("learning".to_string()),
-            ],
-        };
+    fn test_phrase_search_requires_whole_tokens() {
+        let mut index = InvertedIndex::new();
+        index.add_document(
+            "Computing".to_string(),
+            "A smart history of computing".to_string(),
+        );
+        index.add_document("Art".to_string(), "An art history survey".to_string());
+
+        let results = index.phrase_search("art history");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title, "Art");
+    }
+
+    #[test]
+    fn test_field_term_and_boost() {
+        let mut index = InvertedIndex::new();
+        let in_title = index.add_document(
+            "Rust Programming".to_string(),
+            "a systems language".to_string(),
+        );
+        let in_content = index.add_document(
+            "Systems Languages".to_string(),
+            "rust and c are systems languages".to_string(),
+        );
         let searcher = Searcher::new(&index);
-        let results = searcher.search_with_query(&query);
 
-        // Should find documents containing both "machine" AND "learning"
-        assert!(!results.is_empty());
+        let title_only = searcher.search_with_query(&Query::FieldTerm {
+            field: FieldType::Title,
+            term: "Rust".to_string(),
+        });
+        assert_eq!(title_only.len(), 1);
+        assert_eq!(title_only[0].doc_id, in_title);
 
-        for result in &results {
-            let text = format!(
-                "{} {}",
-                result.title.to_lowercase(),
-                result.snippet.to_lowercase()
-            );
-            assert!(text.contains("machine") && text.contains("learning"));
+        let content_only = searcher.search_with_query(&Query::FieldTerm {
+            field: FieldType::Content,
+            term: "rust".to_string(),
+        });
+        assert_eq!(content_only.len(), 1);
+        assert_eq!(content_only[0].doc_id, in_content);
+        assert!(content_only[0].snippet.contains("rust"));
+
+        let plain = searcher.search("systems");
+        let boosted = searcher.search_with_query(&Query::Boost {
+            query: Box::new(Query::Term("systems".to_string())),
+            boost: 3.0,
+        });
+        assert_eq!(plain.len(), boosted.len());
+        for (p, b) in plain.iter().zip(&boosted) {
+            assert_eq!(p.doc_id, b.doc_id);
+            assert!((b.score - p.score * 3.0).abs() < 1e-12);
         }
     }
 
     #[test]
-    fn test_boolean_or_search() {
-        let index = create_test_index();
-        let query = Query::Boolean {
-            operator: BooleanOperator::Or,
-            queries: vec![
-                Query::Term("artificial".to_string()),
-                Query::Term("neural".to_string()),
-            ],
+    fn test_search_filtered_by_metadata_range() {
+        let mut index = InvertedIndex::new();
+        let mut add = |title: &str, year: &str| {
+            index.add_document_with_metadata(
+                title.to_string(),
+                "rust programming guide".to_string(),
+                HashMap::from([("year".to_string(), year.to_string())]),
+            )
         };
+        let old = add("Old", "2012");
+        let mid = add("Mid", "2018");
+        let new = add("New", "2024");
+        index.add_document("Other".to_string(), "python scripting".to_string());
+
+        let query = Query::Term("rust".to_string());
+        let filter = MetadataFilter::new().range("year", Some(2015.0), Some(2020.0));
+        let results = index.search_filtered(&query, &filter);
+        assert_eq!(
+            results.iter().map(|r| r.doc_id).collect::<Vec<_>>(),
+            vec![mid]
+        );
+
+        let filter = MetadataFilter::new()
+            .range("year", Some(2010.0), None)
+            .is_in("year", &["2012", "2024"]);
+        let mut ids: Vec<DocumentId> = index
+            .search_filtered(&query, &filter)
+            .iter()
+            .map(|r| r.doc_id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec![old, new]);
+    }
+
+    #[test]
+    fn test_add_document_with_metadata_filterable() {
+        let mut index = InvertedIndex::new();
+        index.index_metadata_key("category");
+        let tagged = index.add_document_with_metadata(
+            "Borrowing".to_string(),
+            "rust references explained".to_string(),
+            HashMap::from([("category".to_string(), "tutorial".to_string())]),
+        );
+        index.add_document_with_metadata(
+            "Release".to_string(),
+            "rust release notes".to_string(),
+            HashMap::from([("category".to_string(), "news".to_string())]),
+        );
+
+        let doc = index.get_document(tagged).unwrap();
+        assert_eq!(doc.metadata.get("category").unwrap(), "tutorial");
+
+        let filtered = Searcher::new(&index).search_with_query(&Query::FieldScoped(vec![
+            (FieldType::Content, Query::Term("rust".to_string())),
+            (
+                FieldType::Metadata("category".to_string()),
+                Query::Term("tutorial".to_string()),
+            ),
+        ]));
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].doc_id, tagged);
+    }
+
+    #[test]
+    fn test_field_scoped_query() {
+        let mut index = InvertedIndex::new();
+        let scoped = index.add_document(
+            "Rust Handbook".to_string(),
+            "covers ownership in depth".to_string(),
+        );
+        index.add_document(
+            "Handbook".to_string(),
+            "rust ownership explained".to_string(),
+        );
         let searcher = Searcher::new(&index);
+
+        let query = Query::FieldScoped(vec![
+            (FieldType::Title, Query::Term("rust".to_string())),
+            (FieldType::Content, Query::Term("ownership".to_string())),
+        ]);
         let results = searcher.search_with_query(&query);
 
-        // Should find documents containing either "artificial" OR "neural"
-        assert!(!results.is_empty());
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].doc_id, scoped);
+        assert!(results[0].snippet.contains("ownership"));
 
-        for result in &results {
-            let text = format!(
-                "{} {}",
-                result.title.to_lowercase(),
-                result.snippet.to_lowercase()
-            );
-            assert!(text.contains("artificial") || text.contains("neural"));
-        }
+        let phrase_in_title = Query::FieldScoped(vec![(
+            FieldType::Title,
+            Query::Phrase(vec!["rust".to_string(), "handbook".to_string()]),
+        )]);
+        assert_eq!(searcher.search_with_query(&phrase_in_title).len(), 1);
+
+        let conflicting = Query::FieldScoped(vec![(
+            FieldType::Title,
+            Query::FieldTerm {
+                field: FieldType::Content,
+                term: "rust".to_string(),
+            },
+        )]);
+        assert!(searcher.search_with_query(&conflicting).is_empty());
     }
 
     #[test]
-    fn test_boolean_not_search() {
-        let index = create_test_index();
-        let query = Query::Boolean {
-            operator: BooleanOperator::Not,
-            queries: vec![
+    fn test_span_near_ordered_and_unordered() {
+        let mut index = InvertedIndex::new();
+        let doc_id = index.add_document(
+            "Fables".to_string(),
+            "the quick brown fox jumps over the lazy dog".to_string(),
+        );
+        let searcher = Searcher::new(&index);
+        let span = |clauses: Vec<Query>, slop: usize, in_order: bool| Query::SpanNear {
+            clauses,
+            slop,
+            in_order,
+            same_sentence: false,
+        };
+        let term = |t: &str| Query::Term(t.to_string());
+
+        // "quick" precedes "fox" with one word between them
+        let results = searcher.search_with_query(&span(vec![term("quick"), term("fox")], 1, true));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].doc_id, doc_id);
+        assert!(results[0].snippet.contains("quick brown fox"));
+        assert!(
+            searcher
+                .search_with_query(&span(vec![term("quick"), term("fox")], 0, true))
+                .is_empty()
+        );
+
+        // Reversed clauses only match when order doesn't matter
+        assert!(
+            searcher
+                .search_with_query(&span(vec![term("fox"), term("quick")], 1, true))
+                .is_empty()
+        );
+        assert_eq!(
+            searcher
+                .search_with_query(&span(vec![term("fox"), term("quick")], 1, false))
+                .len(),
+            1
+        );
+
+        // Nested spans compose: ("quick" "brown") followed closely by "jumps"
+        let nested = span(
+            vec![
+                span(vec![term("quick"), term("brown")], 0, true),
+                term("jumps"),
+            ],
+            1,
+            true,
+        );
+        assert_eq!(searcher.search_with_query(&nested).len(), 1);
+
+        // Exact adjacency scores higher than a sloppy match
+        let exact = searcher.search_with_query(&span(vec![term("quick"), term("brown")], 2, true));
+        let sloppy = searcher.search_with_query(&span(vec![term("quick"), term("fox")], 2, true));
+        assert!(exact[0].score > sloppy[0].score);
+    }
+
+    #[test]
+    fn test_span_near_same_sentence() {
+        let mut index = InvertedIndex::new();
+        index.add_document(
+            "Notes".to_string(),
+            "the model was trained. learning continued overnight".to_string(),
+        );
+        let searcher = Searcher::new(&index);
+        let near = |same_sentence: bool| Query::SpanNear {
+            clauses: vec![
+                Query::Term("trained".to_string()),
                 Query::Term("learning".to_string()),
-                Query::Term("machine".to_string()),
             ],
+            slop: 0,
+            in_order: true,
+            same_sentence,
         };
-        let searcher = Searcher::new(&index);
-        let results = searcher.search_with_query(&query);
 
-        // Should find documents containing "learning" but NOT "machine"
-        for result in &results {
-            let text = format!(
-                "{} {}",
-                result.title.to_lowercase(),
-                result.snippet.to_lowercase()
-            );
-            assert!(text.contains("learning") && !text.contains("machine"));
-        }
+        assert_eq!(searcher.search_with_query(&near(false)).len(), 1);
+        assert!(searcher.search_with_query(&near(true)).is_empty());
+        assert_eq!(
+            searcher
+                .search_with_query(&Query::Phrase(vec![
+                    "trained".to_string(),
+                    "learning".to_string()
+                ]))
+                .len(),
+            1
+        );
     }
 
     #[test]
-    fn test_phrase_search() {
-        let index = create_test_index();
-        let query = Query::Phrase(vec!["machine".to_string(), "learning".to_string()]);
+    fn test_span_near_prunes_distant_combinations() {
+        let mut index = InvertedIndex::new();
+        let doc_id = index.add_document("Chant".to_string(), "alpha beta ".repeat(200));
+        let term = |t: &str| Query::Term(t.to_string());
+        // Without pruning this would try 200^6 combinations
+        let query = Query::SpanNear {
+            clauses: vec![
+                term("alpha"),
+                term("beta"),
+                term("alpha"),
+                term("beta"),
+                term("alpha"),
+                term("beta"),
+            ],
+            slop: 0,
+            in_order: false,
+            same_sentence: false,
+        };
+
+        let results = Searcher::new(&index).search_with_query(&query);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].doc_id, doc_id);
+    }
+
+    #[test]
+    fn test_phrase_same_sentence() {
+        let mut index = InvertedIndex::new();
+        index.add_document(
+            "Notes".to_string(),
+            "the model was trained. learning continued overnight".to_string(),
+        );
+        let within = index.add_document(
+            "Course".to_string(),
+            "they trained learning models".to_string(),
+        );
         let searcher = Searcher::new(&index);
-        let results = searcher.search_with_query(&query);
+        let phrase = |same_sentence: bool| Query::PhraseWith {
+            terms: vec!["trained".to_string(), "learning".to_string()],
+            options: PhraseOptions {
+                same_sentence,
+                ..PhraseOptions::default()
+            },
+        };
 
-        // Should find documents containing the exact phrase "machine learning"
-        for result in &results {
-            let text = format!(
-                "{} {}",
-                result.title.to_lowercase(),
-                result.snippet.to_lowercase()
-            );
-            assert!(text.contains("machine learning"));
-        }
+        assert_eq!(searcher.search_with_query(&phrase(false)).len(), 2);
+        let results = searcher.search_with_query(&phrase(true));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].doc_id, within);
     }
 
     #[test]
@@ -538,6 +5167,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_wildcard_suffix_index_matches_scan() {
+        let mut plain = create_test_index();
+        let mut indexed = create_test_index();
+        indexed.enable_suffix_index();
+        for index in [&mut plain, &mut indexed] {
+            index.add_document(
+                "Testing".to_string(),
+                "running and jumping while testing".to_string(),
+            );
+        }
+
+        // Only the indexed copy can answer the suffix without a scan
+        let mut suffixed = indexed.terms_with_suffix("ing").unwrap();
+        suffixed.sort();
+        assert_eq!(suffixed, ["jumping", "learning", "running", "testing"]);
+        assert!(plain.terms_with_suffix("ing").is_none());
+
+        let fast = indexed.wildcard_search("*ing");
+        let slow = plain.wildcard_search("*ing");
+
+        assert!(!fast.is_empty());
+        assert_eq!(fast.len(), slow.len());
+        for (a, b) in fast.iter().zip(&slow) {
+            assert_eq!(a.doc_id, b.doc_id);
+            assert!((a.score - b.score).abs() < 1e-12);
+        }
+    }
+
     #[test]
     fn test_wildcard_suffix_search() {
         let index = create_test_index();
@@ -612,6 +5270,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_snippet_ellipsis_only_where_text_was_cut() {
+        let snippet_for = |content: String| {
+            let mut index = InvertedIndex::new();
+            index.add_document("Doc".to_string(), content);
+            let results = Searcher::new(&index)
+                .with_snippet_length(30, SnippetLengthUnit::Chars)
+                .search("needle");
+            results[0].snippet.clone()
+        };
+        let filler = "words ".repeat(20);
+
+        let at_start = snippet_for(format!("needle {}", filler));
+        assert!(at_start.starts_with("needle"));
+        assert!(at_start.ends_with("..."));
+
+        let at_end = snippet_for(format!("{}needle", filler));
+        assert!(at_end.starts_with("..."));
+        assert!(at_end.ends_with("needle"));
+
+        // Cutting only whitespace at either edge leaves no ellipsis there
+        let padded = snippet_for(format!("{}needle{}", " ".repeat(40), " ".repeat(40)));
+        assert!(!padded.contains("..."));
+        assert!(padded.contains("needle"));
+    }
+
     #[test]
     fn test_boolean_empty_queries() {
         let index = create_test_index();
@@ -693,6 +5377,165 @@ mod tests {
         assert_eq!(results.len(), 1);
     }
 
+    #[test]
+    fn test_more_like_this() {
+        let mut index = InvertedIndex::new();
+        let source = index.add_document(
+            "Rust Ownership".to_string(),
+            "rust ownership borrowing lifetimes compiler safety".to_string(),
+        );
+        index.add_document(
+            "Cooking Pasta".to_string(),
+            "boil water salt pasta sauce tomato".to_string(),
+        );
+        let duplicate = index.add_document(
+            "Rust Ownership Explained".to_string(),
+            "rust ownership borrowing lifetimes compiler guarantees".to_string(),
+        );
+        index.add_document(
+            "Compiler Design".to_string(),
+            "compiler parsing optimization codegen".to_string(),
+        );
+
+        let results = index.more_like_this(source, 10);
+
+        assert!(!results.is_empty());
+        assert_eq!(results[0].doc_id, duplicate);
+        assert!(results.iter().all(|r| r.doc_id != source));
+        assert!(results.iter().all(|r| r.title != "Cooking Pasta"));
+        assert!(results[0].score > 0.0 && results[0].score <= 1.0 + 1e-9);
+
+        // Limit is respected
+        assert_eq!(index.more_like_this(source, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_tf_cap_saturates_at_cap_value() {
+        let mut index = InvertedIndex::new();
+        let spam = index.add_document("Spam".to_string(), "deal ".repeat(1000));
+        let at_cap = index.add_document("Fifty".to_string(), "deal ".repeat(50));
+        index.add_document("Other".to_string(), "honest review".to_string());
+        let score_of = |searcher: &Searcher, doc_id| {
+            searcher
+                .search("deal")
+                .into_iter()
+                .find(|r| r.doc_id == doc_id)
+                .unwrap()
+                .score
+        };
+
+        let mut searcher = Searcher::new(&index);
+        let uncapped = score_of(&searcher, spam);
+        searcher.set_tf_cap(Some(50));
+        let capped = score_of(&searcher, spam);
+
+        assert!(uncapped > capped);
+        assert_eq!(capped, score_of(&searcher, at_cap));
+        let built = Searcher::new(&index).with_tf_cap(Some(50));
+        assert_eq!(capped, score_of(&built, spam));
+        assert_eq!(capped, score_of(&Searcher::new(&index), at_cap));
+    }
+
+    #[test]
+    fn test_max_effective_tf_limits_keyword_stuffing() {
+        let mut index = InvertedIndex::new();
+        let natural = index.add_document(
+            "Rust Guide".to_string(),
+            "rust ownership explained, with rust examples and idiomatic rust".to_string(),
+        );
+        let stuffed = index.add_document("Cheap Rust".to_string(), "rust ".repeat(200));
+        index.add_document("Python".to_string(), "python guide".to_string());
+
+        let uncapped = index.search_tfidf("rust");
+        assert_eq!(uncapped[0].doc_id, stuffed);
+        assert!(uncapped[0].score > uncapped[1].score);
+
+        index.set_scoring_options(ScoringOptions {
+            max_effective_tf: Some(3),
+            ..Default::default()
+        });
+        let capped = index.search_tfidf("rust");
+        assert_eq!(capped[0].doc_id, natural);
+
+        let score_of = |id| capped.iter().find(|r| r.doc_id == id).unwrap().score;
+        assert_eq!(score_of(stuffed), score_of(natural));
+    }
+
+    #[test]
+    fn test_bm25_length_normalization() {
+        let mut index = InvertedIndex::new();
+        let short = index.add_document("Rust".to_string(), "rust ownership".to_string());
+        let long = index.add_document(
+            "Languages".to_string(),
+            "rust python java golang kotlin swift haskell ocaml erlang".to_string(),
+        );
+        index.add_document("Cooking".to_string(), "pasta sauce".to_string());
+
+        let options = ScoringOptions {
+            model: ScoringModel::bm25(),
+            ..Default::default()
+        };
+        let searcher = Searcher::with_options(&index, options);
+        let results = searcher.search("rust");
+
+        // Same term frequency in content, but the shorter document wins
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].doc_id, short);
+        assert_eq!(results[1].doc_id, long);
+        assert!(results[0].score > results[1].score);
+    }
+
+    #[test]
+    fn test_bm25_saturates_term_frequency() {
+        let index = create_test_index();
+        let searcher = Searcher::with_options(
+            &index,
+            ScoringOptions {
+                model: ScoringModel::bm25(),
+                ..Default::default()
+            },
+        );
+
+        let once = searcher.bm25_weight(1, TermIdf::new(1, 5).bm25, 1.0, 1.2, 0.75);
+        let many = searcher.bm25_weight(100, TermIdf::new(1, 5).bm25, 1.0, 1.2, 0.75);
+        assert!(many > once);
+        // Bounded by idf * (k1 + 1)
+        let idf = (1.0f64 + (5.0 - 1.0 + 0.5) / 1.5).ln();
+        assert!(many < idf * 2.2);
+    }
+
+    #[test]
+    fn test_corpus_stats_read_once_per_searcher() {
+        let mut index = InvertedIndex::new();
+        for i in 0..50 {
+            index.add_document(format!("Doc {}", i), format!("shared term{} text", i % 7));
+        }
+
+        let searcher = Searcher::new(&index);
+        let results = searcher.search("shared");
+        assert_eq!(results.len(), 50);
+        for result in &results {
+            assert_eq!(result.score, searcher.calculate_tfidf(1, 50, 50));
+        }
+
+        // Every clause scores against the same snapshot of the corpus
+        let query = Query::Boolean {
+            operator: BooleanOperator::Or,
+            queries: vec![
+                Query::Term("text".to_string()),
+                Query::Wildcard("term*".to_string()),
+            ],
+        };
+        let term_score = |term: usize| {
+            let df = (0..50).filter(|i| i % 7 == term).count();
+            searcher.calculate_tfidf(1, df, 50)
+        };
+        for result in searcher.search_with_query(&query) {
+            let expected = searcher.calculate_tfidf(1, 50, 50) + term_score(result.doc_id % 7);
+            assert!((result.score - expected).abs() < 1e-12);
+        }
+    }
+
     #[test]
     fn test_tfidf_calculation() {
         let index = create_test_index();
@@ -1,4 +1,13 @@
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Separates fields joined into one text, as by
+/// [`crate::Document::full_text_with_separator`]. The tokenizer leaves a
+/// position gap and starts a new sentence at it, so phrases can't match
+/// across the junction.
+pub const FIELD_SEPARATOR: char = '\u{2029}';
 
 #[derive(Debug, Clone)]
 pub struct Token {
@@ -6,12 +15,85 @@ pub struct Token {
     pub position: usize,
     pub start_offset: usize,
     pub end_offset: usize,
+    /// Index of the sentence the token belongs to, counted from zero. A
+    /// sentence ends at `.`, `!` or `?` not followed by a letter or digit.
+    pub sentence: usize,
+    /// The word as written, before case folding, stemming and the like.
+    /// Only set when [`Tokenizer::set_keep_original`] is enabled.
+    pub(crate) original: Option<String>,
+    /// The normalized word before plural stripping and stemming, when that
+    /// differs from `text`. Only set when [`Tokenizer::set_dual_index`] is
+    /// enabled.
+    pub(crate) exact: Option<String>,
+}
+
+impl Token {
+    /// The word as written, before case folding, stemming and the like.
+    /// Only set when [`Tokenizer::set_keep_original`] is enabled.
+    pub fn original(&self) -> Option<&str> {
+        self.original.as_deref()
+    }
+
+    /// The normalized word before plural stripping and stemming, when that
+    /// differs from `text`. Only set when [`Tokenizer::set_dual_index`] is
+    /// enabled.
+    pub fn exact(&self) -> Option<&str> {
+        self.exact.as_deref()
+    }
+}
+
+/// Stemming applied to each token after stop-word filtering.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum StemAlgorithm {
+    #[default]
+    None,
+    /// Strips a few common suffixes; cheap, but can over- or under-stem.
+    Simple,
+    /// The full Porter (1980) algorithm, trading precision for recall.
+    Porter,
+}
+
+/// Term that purely numeric tokens become under
+/// [`NumberHandling::Normalize`]. It can't collide with a real word, since
+/// `#` always splits words.
+pub const NUMBER_PLACEHOLDER: &str = "#num";
+
+/// What the tokenizer does with tokens made up only of digits, like "456" or
+/// "2023". Mixed tokens such as "test123" are always indexed as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum NumberHandling {
+    #[default]
+    Index,
+    Skip,
+    /// Replaces every number with [`NUMBER_PLACEHOLDER`], so a query for
+    /// any number matches documents containing some number.
+    Normalize,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tokenizer {
     stop_words: HashSet<String>,
     min_token_length: usize,
     max_token_length: usize,
+    stem_algorithm: StemAlgorithm,
+    #[serde(default = "default_true")]
+    lowercase: bool,
+    #[serde(default)]
+    folding: bool,
+    #[serde(default)]
+    strip_plurals: bool,
+    #[serde(default = "default_true")]
+    strip_apostrophes: bool,
+    #[serde(default)]
+    preserve_position_gaps: bool,
+    #[serde(default)]
+    keep_original: bool,
+    #[serde(default)]
+    keep_short_cjk: bool,
+    #[serde(default)]
+    number_handling: NumberHandling,
+    #[serde(default)]
+    dual_index: bool,
 }
 
 impl Tokenizer {
@@ -32,45 +114,36 @@ impl Tokenizer {
             stop_words,
             min_token_length: 2,
             max_token_length: 50,
+            stem_algorithm: StemAlgorithm::None,
+            lowercase: true,
+            folding: false,
+            strip_plurals: false,
+            strip_apostrophes: true,
+            preserve_position_gaps: false,
+            keep_original: false,
+            keep_short_cjk: false,
+            number_handling: NumberHandling::Index,
+            dual_index: false,
         }
     }
 
     pub fn tokenize(&self, text: &str) -> Vec<Token> {
-        let mut tokens = Vec::new();
-        let mut position = 0;
-
-        let text_chars: Vec<char> = text.chars().collect();
-        let mut current_word = String::new();
-        let mut word_start = 0;
-
-        for (i, ch) in text_chars.iter().enumerate() {
-            if ch.is_alphanumeric() {
-                if current_word.is_empty() {
-                    word_start = i;
-                }
-                current_word.push(*ch);
-            } else {
-                if !current_word.is_empty() {
-                    if let Some(token) =
-                        self.create_token(current_word.clone(), position, word_start, i)
-                    {
-                        tokens.push(token);
-                        position += 1;
-                    }
-                    current_word.clear();
-                }
-            }
-        }
+        self.tokenize_iter(text).collect()
+    }
 
-        if !current_word.is_empty() {
-            if let Some(token) =
-                self.create_token(current_word, position, word_start, text_chars.len())
-            {
-                tokens.push(token);
-            }
+    /// Yields the tokens of `text` one at a time, without buffering the text
+    /// or the token list.
+    pub fn tokenize_iter<'a>(&'a self, text: &'a str) -> impl Iterator<Item = Token> + 'a {
+        TokenIter {
+            tokenizer: self,
+            chars: text.chars().peekable(),
+            offset: 0,
+            position: 0,
+            sentence: 0,
+            sentence_has_words: false,
+            current_word: String::new(),
+            word_start: 0,
         }
-
-        tokens
     }
 
     fn create_token(
@@ -79,22 +152,60 @@ impl Tokenizer {
         position: usize,
         start: usize,
         end: usize,
+        sentence: usize,
     ) -> Option<Token> {
-        let normalized = text.to_lowercase();
+        let original = self.keep_original.then(|| text.clone());
+        let acronym_plural = is_acronym_plural(&text);
+        let mut normalized = if self.lowercase {
+            text.to_lowercase()
+        } else {
+            text
+        };
+        if self.strip_apostrophes {
+            normalized = strip_apostrophes(&normalized);
+        }
+        if self.folding {
+            normalized = fold_diacritics(&normalized);
+        }
 
-        if normalized.len() < self.min_token_length || normalized.len() > self.max_token_length {
+        let length = normalized.len();
+        let short_cjk = self.keep_short_cjk && length > 0 && normalized.chars().all(is_cjk);
+        if (length < self.min_token_length && !short_cjk) || length > self.max_token_length {
             return None;
         }
 
-        if self.stop_words.contains(&normalized) {
+        if self.is_stop_word(&normalized) {
             return None;
         }
 
+        if normalized.chars().all(char::is_numeric) {
+            match self.number_handling {
+                NumberHandling::Index => {}
+                NumberHandling::Skip => return None,
+                NumberHandling::Normalize => normalized = NUMBER_PLACEHOLDER.to_string(),
+            }
+        }
+
+        let unstemmed = self.dual_index.then(|| normalized.clone());
+        if self.strip_plurals {
+            normalized = strip_plural(normalized, acronym_plural);
+        }
+
+        let text = match self.stem_algorithm {
+            StemAlgorithm::None => normalized,
+            StemAlgorithm::Simple => SimpleStemmer::stem(&normalized),
+            StemAlgorithm::Porter => PorterStemmer::stem(&normalized),
+        };
+        let exact = unstemmed.filter(|word| *word != text);
+
         Some(Token {
-            text: normalized,
+            text,
             position,
             start_offset: start,
             end_offset: end,
+            sentence,
+            original,
+            exact,
         })
     }
 
@@ -106,15 +217,323 @@ impl Tokenizer {
         self.stop_words.remove(&word.to_lowercase());
     }
 
+    pub fn is_stop_word(&self, word: &str) -> bool {
+        self.stop_words.contains(&word.to_lowercase())
+    }
+
+    /// The active stop words, lowercased.
+    pub fn stop_words(&self) -> &HashSet<String> {
+        &self.stop_words
+    }
+
+    pub fn min_token_length(&self) -> usize {
+        self.min_token_length
+    }
+
+    pub fn max_token_length(&self) -> usize {
+        self.max_token_length
+    }
+
+    pub fn stem_algorithm(&self) -> StemAlgorithm {
+        self.stem_algorithm
+    }
+
+    pub fn set_stem_algorithm(&mut self, algorithm: StemAlgorithm) {
+        self.stem_algorithm = algorithm;
+    }
+
+    pub fn preserve_position_gaps(&self) -> bool {
+        self.preserve_position_gaps
+    }
+
+    /// When enabled, words dropped by filtering still advance the position
+    /// counter, so "quick the brown" leaves a gap between "quick" and
+    /// "brown" and phrase queries don't treat them as adjacent.
+    pub fn set_preserve_position_gaps(&mut self, preserve: bool) {
+        self.preserve_position_gaps = preserve;
+    }
+
+    pub fn strip_plurals(&self) -> bool {
+        self.strip_plurals
+    }
+
+    /// Reduces regular English plurals to the singular ("APIs" -> "api",
+    /// "libraries" -> "library") without stemming anything else. Words
+    /// ending in "ss", "us" or "is" are taken to be singular.
+    pub fn set_strip_plurals(&mut self, strip_plurals: bool) {
+        self.strip_plurals = strip_plurals;
+    }
+
+    pub fn strip_apostrophes(&self) -> bool {
+        self.strip_apostrophes
+    }
+
+    /// When enabled, the default, drops a trailing possessive "'s" and any
+    /// other apostrophes, so "Rust's" indexes as "rust" and "don't" as
+    /// "dont". Disabled, words keep their apostrophes as written.
+    pub fn set_strip_apostrophes(&mut self, strip: bool) {
+        self.strip_apostrophes = strip;
+    }
+
+    pub fn lowercase(&self) -> bool {
+        self.lowercase
+    }
+
+    /// Controls case folding, on by default. Turning it off keeps "Rust" and
+    /// "rust" as distinct terms; stop words still match in any case, and the
+    /// stemmers always lowercase what they stem.
+    pub fn set_lowercase(&mut self, lowercase: bool) {
+        self.lowercase = lowercase;
+    }
+
+    pub fn folding(&self) -> bool {
+        self.folding
+    }
+
+    /// Strips diacritics from Latin letters so that e.g. "café" and "cafe"
+    /// produce the same term.
+    pub fn set_folding(&mut self, folding: bool) {
+        self.folding = folding;
+    }
+
+    pub fn keep_original(&self) -> bool {
+        self.keep_original
+    }
+
+    /// Makes tokens carry their text as written in [`Token::original`],
+    /// alongside the normalized term.
+    pub fn set_keep_original(&mut self, keep: bool) {
+        self.keep_original = keep;
+    }
+
+    pub fn keep_short_cjk(&self) -> bool {
+        self.keep_short_cjk
+    }
+
+    /// When enabled, words made up entirely of CJK characters are kept even
+    /// if shorter than the minimum token length, since a single ideograph is
+    /// often a whole word. Off by default.
+    pub fn set_keep_short_cjk(&mut self, keep: bool) {
+        self.keep_short_cjk = keep;
+    }
+
+    pub fn number_handling(&self) -> NumberHandling {
+        self.number_handling
+    }
+
+    /// Numbers shorter than the minimum token length are dropped whatever
+    /// the handling.
+    pub fn set_number_handling(&mut self, handling: NumberHandling) {
+        self.number_handling = handling;
+    }
+
+    pub fn dual_index(&self) -> bool {
+        self.dual_index
+    }
+
+    /// Makes tokens also carry their unstemmed form in [`Token::exact`], so
+    /// an index can answer exact-word queries alongside stemmed ones.
+    pub fn set_dual_index(&mut self, dual_index: bool) {
+        self.dual_index = dual_index;
+    }
+
+    /// Token lengths are counted in bytes, so a single CJK character (three
+    /// bytes) already meets the default minimum of 2; for larger minimums
+    /// see [`Self::set_keep_short_cjk`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `length` is zero or greater than the current maximum token
+    /// length, since either would leave the tokenizer unable to emit tokens.
     pub fn set_min_token_length(&mut self, length: usize) {
+        assert!(length > 0, "min token length must be at least 1");
+        assert!(
+            length <= self.max_token_length,
+            "min token length {} exceeds max token length {}",
+            length,
+            self.max_token_length
+        );
         self.min_token_length = length;
     }
 
+    /// # Panics
+    ///
+    /// Panics if `length` is smaller than the current minimum token length.
     pub fn set_max_token_length(&mut self, length: usize) {
+        assert!(
+            length >= self.min_token_length,
+            "max token length {} is below min token length {}",
+            length,
+            self.min_token_length
+        );
         self.max_token_length = length;
     }
 }
 
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_apostrophe(c: char) -> bool {
+    c == '\'' || c == '\u{2019}'
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Ideographs, kana and hangul, where a single character is often a whole
+/// word.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{ac00}'..='\u{d7af}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{20000}'..='\u{2fa1f}')
+}
+
+/// Drops a trailing possessive "'s" and any other apostrophes in a word.
+fn strip_apostrophes(word: &str) -> String {
+    let word = ["'s", "'S", "\u{2019}s", "\u{2019}S"]
+        .iter()
+        .find_map(|suffix| word.strip_suffix(suffix))
+        .unwrap_or(word);
+    word.chars().filter(|&c| !is_apostrophe(c)).collect()
+}
+
+/// Whether `word` is written like the plural of an acronym, such as "APIs".
+fn is_acronym_plural(word: &str) -> bool {
+    word.strip_suffix('s')
+        .is_some_and(|stem| stem.len() >= 2 && stem.chars().all(|c| c.is_ascii_uppercase()))
+}
+
+/// Reduces a regular plural to the singular. Words ending in "ss", "us" or
+/// "is" ("class", "status", "axis") are left alone unless `acronym_plural`
+/// says the "s" was added to an acronym ("GPUs", "APIs").
+fn strip_plural(word: String, acronym_plural: bool) -> String {
+    if acronym_plural {
+        word[..word.len() - 1].to_string()
+    } else if let Some(stem) = word.strip_suffix("ies")
+        && stem.len() > 1
+    {
+        format!("{}y", stem)
+    } else if word.len() > 3
+        && word.ends_with('s')
+        && !["ss", "us", "is"].iter().any(|end| word.ends_with(end))
+    {
+        word[..word.len() - 1].to_string()
+    } else {
+        word
+    }
+}
+
+/// Maps accented Latin letters to their unaccented base letter.
+fn fold_diacritics(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => 'a',
+            'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => 'c',
+            'ď' | 'đ' => 'd',
+            'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => 'e',
+            'ĝ' | 'ğ' | 'ġ' | 'ģ' => 'g',
+            'ĥ' | 'ħ' => 'h',
+            'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => 'i',
+            'ĵ' => 'j',
+            'ķ' => 'k',
+            'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => 'l',
+            'ñ' | 'ń' | 'ņ' | 'ň' => 'n',
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => 'o',
+            'ŕ' | 'ŗ' | 'ř' => 'r',
+            'ś' | 'ŝ' | 'ş' | 'š' => 's',
+            'ţ' | 'ť' | 'ŧ' => 't',
+            'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => 'u',
+            'ŵ' => 'w',
+            'ý' | 'ÿ' | 'ŷ' => 'y',
+            'ź' | 'ż' | 'ž' => 'z',
+            _ => c,
+        })
+        .collect()
+}
+
+struct TokenIter<'a> {
+    tokenizer: &'a Tokenizer,
+    chars: Peekable<Chars<'a>>,
+    /// Char offset of the next char to be read.
+    offset: usize,
+    position: usize,
+    sentence: usize,
+    sentence_has_words: bool,
+    current_word: String,
+    word_start: usize,
+}
+
+impl Iterator for TokenIter<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while let Some(ch) = self.chars.next() {
+            let i = self.offset;
+            self.offset += 1;
+            let next_is_alphanumeric = self.chars.peek().is_some_and(|next| next.is_alphanumeric());
+
+            // An apostrophe between letters ("Rust's", "don't") stays in the
+            // word; elsewhere it separates words like other punctuation.
+            let inner_apostrophe =
+                is_apostrophe(ch) && !self.current_word.is_empty() && next_is_alphanumeric;
+            if ch.is_alphanumeric() || inner_apostrophe {
+                if self.current_word.is_empty() {
+                    self.word_start = i;
+                    self.sentence_has_words = true;
+                }
+                self.current_word.push(ch);
+                continue;
+            }
+
+            let mut token = None;
+            if !self.current_word.is_empty() {
+                let word = std::mem::take(&mut self.current_word);
+                token = self.tokenizer.create_token(
+                    word,
+                    self.position,
+                    self.word_start,
+                    i,
+                    self.sentence,
+                );
+                if token.is_some() || self.tokenizer.preserve_position_gaps {
+                    self.position += 1;
+                }
+            }
+            if ch == FIELD_SEPARATOR {
+                self.position += 1;
+            }
+            let ends_sentence = matches!(ch, '.' | '!' | '?') && !next_is_alphanumeric;
+            if self.sentence_has_words && (ends_sentence || ch == FIELD_SEPARATOR) {
+                self.sentence += 1;
+                self.sentence_has_words = false;
+            }
+            if token.is_some() {
+                return token;
+            }
+        }
+
+        if self.current_word.is_empty() {
+            return None;
+        }
+        let word = std::mem::take(&mut self.current_word);
+        self.tokenizer.create_token(
+            word,
+            self.position,
+            self.word_start,
+            self.offset,
+            self.sentence,
+        )
+    }
+}
+
 pub struct SimpleNormalizer;
 
 impl SimpleNormalizer {
@@ -135,6 +554,7 @@ impl SimpleNormalizer {
 pub struct SimpleStemmer;
 
 impl SimpleStemmer {
+    #[allow(clippy::if_same_then_else)]
     pub fn stem(word: &str) -> String {
         let word = word.to_lowercase();
 
@@ -154,6 +574,196 @@ impl SimpleStemmer {
     }
 }
 
+/// The Porter stemming algorithm as described in M.F. Porter, "An algorithm
+/// for suffix stripping" (1980). Words that aren't plain ASCII letters are
+/// returned lowercased but otherwise unchanged.
+pub struct PorterStemmer;
+
+impl PorterStemmer {
+    pub fn stem(word: &str) -> String {
+        let word = word.to_lowercase();
+        if word.len() <= 2 || !word.bytes().all(|b| b.is_ascii_lowercase()) {
+            return word;
+        }
+
+        let mut w = word.into_bytes();
+        Self::step1a(&mut w);
+        Self::step1b(&mut w);
+        Self::step1c(&mut w);
+        Self::replace_first(&mut w, STEP2_RULES, 0);
+        Self::replace_first(&mut w, STEP3_RULES, 0);
+        Self::step4(&mut w);
+        Self::step5(&mut w);
+        String::from_utf8(w).expect("stemming ASCII input yields ASCII")
+    }
+
+    fn is_consonant(w: &[u8], i: usize) -> bool {
+        match w[i] {
+            b'a' | b'e' | b'i' | b'o' | b'u' => false,
+            b'y' => i == 0 || !Self::is_consonant(w, i - 1),
+            _ => true,
+        }
+    }
+
+    /// The number of vowel-consonant sequences, `m` in Porter's notation.
+    fn measure(w: &[u8]) -> usize {
+        let mut m = 0;
+        let mut previous_vowel = false;
+        for i in 0..w.len() {
+            let vowel = !Self::is_consonant(w, i);
+            if previous_vowel && !vowel {
+                m += 1;
+            }
+            previous_vowel = vowel;
+        }
+        m
+    }
+
+    fn has_vowel(w: &[u8]) -> bool {
+        (0..w.len()).any(|i| !Self::is_consonant(w, i))
+    }
+
+    fn ends_double_consonant(w: &[u8]) -> bool {
+        let n = w.len();
+        n >= 2 && w[n - 1] == w[n - 2] && Self::is_consonant(w, n - 1)
+    }
+
+    /// Consonant-vowel-consonant ending where the last letter isn't w, x or y.
+    fn ends_cvc(w: &[u8]) -> bool {
+        let n = w.len();
+        n >= 3
+            && Self::is_consonant(w, n - 3)
+            && !Self::is_consonant(w, n - 2)
+            && Self::is_consonant(w, n - 1)
+            && !matches!(w[n - 1], b'w' | b'x' | b'y')
+    }
+
+    /// Applies the first rule whose suffix matches, provided the remaining
+    /// stem has a measure above `min_measure`. Later rules are not tried.
+    fn replace_first(w: &mut Vec<u8>, rules: &[(&str, &str)], min_measure: usize) {
+        if let Some((suffix, replacement)) = rules
+            .iter()
+            .find(|(suffix, _)| w.ends_with(suffix.as_bytes()))
+        {
+            let stem_len = w.len() - suffix.len();
+            if Self::measure(&w[..stem_len]) > min_measure {
+                w.truncate(stem_len);
+                w.extend_from_slice(replacement.as_bytes());
+            }
+        }
+    }
+
+    fn step1a(w: &mut Vec<u8>) {
+        if w.ends_with(b"sses") || w.ends_with(b"ies") {
+            w.truncate(w.len() - 2);
+        } else if w.ends_with(b"s") && !w.ends_with(b"ss") {
+            w.pop();
+        }
+    }
+
+    fn step1b(w: &mut Vec<u8>) {
+        if w.ends_with(b"eed") {
+            if Self::measure(&w[..w.len() - 3]) > 0 {
+                w.pop();
+            }
+            return;
+        }
+
+        let suffix_len = if w.ends_with(b"ed") {
+            2
+        } else if w.ends_with(b"ing") {
+            3
+        } else {
+            return;
+        };
+        if !Self::has_vowel(&w[..w.len() - suffix_len]) {
+            return;
+        }
+        w.truncate(w.len() - suffix_len);
+
+        if w.ends_with(b"at") || w.ends_with(b"bl") || w.ends_with(b"iz") {
+            w.push(b'e');
+        } else if Self::ends_double_consonant(w) && !matches!(w[w.len() - 1], b'l' | b's' | b'z') {
+            w.pop();
+        } else if Self::measure(w) == 1 && Self::ends_cvc(w) {
+            w.push(b'e');
+        }
+    }
+
+    fn step1c(w: &mut [u8]) {
+        let n = w.len();
+        if w.ends_with(b"y") && Self::has_vowel(&w[..n - 1]) {
+            w[n - 1] = b'i';
+        }
+    }
+
+    fn step4(w: &mut Vec<u8>) {
+        let Some(suffix) = STEP4_SUFFIXES
+            .iter()
+            .find(|suffix| w.ends_with(suffix.as_bytes()))
+        else {
+            return;
+        };
+        let stem_len = w.len() - suffix.len();
+        let stem = &w[..stem_len];
+        let allowed = *suffix != "ion" || stem.ends_with(b"s") || stem.ends_with(b"t");
+        if allowed && Self::measure(stem) > 1 {
+            w.truncate(stem_len);
+        }
+    }
+
+    fn step5(w: &mut Vec<u8>) {
+        if w.ends_with(b"e") {
+            let stem = &w[..w.len() - 1];
+            let m = Self::measure(stem);
+            if m > 1 || (m == 1 && !Self::ends_cvc(stem)) {
+                w.pop();
+            }
+        }
+        if w.ends_with(b"ll") && Self::measure(w) > 1 {
+            w.pop();
+        }
+    }
+}
+
+const STEP2_RULES: &[(&str, &str)] = &[
+    ("ational", "ate"),
+    ("tional", "tion"),
+    ("enci", "ence"),
+    ("anci", "ance"),
+    ("izer", "ize"),
+    ("abli", "able"),
+    ("alli", "al"),
+    ("entli", "ent"),
+    ("eli", "e"),
+    ("ousli", "ous"),
+    ("ization", "ize"),
+    ("ation", "ate"),
+    ("ator", "ate"),
+    ("alism", "al"),
+    ("iveness", "ive"),
+    ("fulness", "ful"),
+    ("ousness", "ous"),
+    ("aliti", "al"),
+    ("iviti", "ive"),
+    ("biliti", "ble"),
+];
+
+const STEP3_RULES: &[(&str, &str)] = &[
+    ("icate", "ic"),
+    ("ative", ""),
+    ("alize", "al"),
+    ("iciti", "ic"),
+    ("ical", "ic"),
+    ("ful", ""),
+    ("ness", ""),
+];
+
+const STEP4_SUFFIXES: &[&str] = &[
+    "al", "ance", "ence", "er", "ic", "able", "ible", "ant", "ement", "ment", "ent", "ion", "ou",
+    "ism", "ate", "iti", "ous", "ive", "ize",
+];
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -195,6 +805,190 @@ mod tests {
         assert_eq!(tokens[2].end_offset, 16);
     }
 
+    #[test]
+    fn test_tokenizer_sentences() {
+        let tokenizer = Tokenizer::new();
+        let tokens = tokenizer.tokenize("Pi is 3.14 today. Really? Yes!! ok");
+
+        let sentences: Vec<(&str, usize)> = tokens
+            .iter()
+            .map(|t| (t.text.as_str(), t.sentence))
+            .collect();
+        assert_eq!(
+            sentences,
+            vec![
+                ("pi", 0),
+                ("14", 0),
+                ("today", 0),
+                ("really", 1),
+                ("yes", 2),
+                ("ok", 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_iter_matches_tokenize() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_preserve_position_gaps(true);
+        let text = "Rust's borrow checker! It runs... at compile time? Yes: the café's API.";
+
+        let streamed: Vec<Token> = tokenizer.tokenize_iter(text).collect();
+        let collected = tokenizer.tokenize(text);
+
+        let key = |t: &Token| {
+            (
+                t.text.clone(),
+                t.position,
+                t.start_offset,
+                t.end_offset,
+                t.sentence,
+            )
+        };
+        assert_eq!(
+            streamed.iter().map(key).collect::<Vec<_>>(),
+            collected.iter().map(key).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            streamed.last().unwrap().end_offset,
+            text.chars().count() - 1
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_folding() {
+        let mut tokenizer = Tokenizer::new();
+        assert!(!tokenizer.folding());
+        assert_eq!(tokenizer.tokenize("Café")[0].text, "café");
+
+        tokenizer.set_folding(true);
+        let tokens = tokenizer.tokenize("Café Señor naïve");
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["cafe", "senor", "naive"]);
+        assert_eq!(tokens[0].end_offset, 4);
+    }
+
+    #[test]
+    fn test_tokenizer_keep_original() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_folding(true);
+        assert!(tokenizer.tokenize("Machine")[0].original().is_none());
+
+        tokenizer.set_keep_original(true);
+        let tokens = tokenizer.tokenize("Machine CAFÉ");
+        let pairs: Vec<(&str, Option<&str>)> = tokens
+            .iter()
+            .map(|t| (t.text.as_str(), t.original()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![("machine", Some("Machine")), ("cafe", Some("CAFÉ"))]
+        );
+    }
+
+    #[test]
+    fn test_lowercase_and_folding_are_independent() {
+        let term = |lowercase: bool, folding: bool, text: &str| {
+            let mut tokenizer = Tokenizer::new();
+            tokenizer.set_lowercase(lowercase);
+            tokenizer.set_folding(folding);
+            tokenizer.tokenize(text).remove(0).text
+        };
+
+        // Case-insensitive, accent-insensitive
+        assert_eq!(term(true, true, "Café"), term(true, true, "cafe"));
+        // Case-insensitive, accent-sensitive
+        assert_eq!(term(true, false, "Café"), term(true, false, "café"));
+        assert_ne!(term(true, false, "café"), term(true, false, "cafe"));
+        // Case-sensitive, accent-insensitive
+        assert_eq!(term(false, true, "Café"), term(false, true, "Cafe"));
+        assert_ne!(term(false, true, "Café"), term(false, true, "cafe"));
+        // Case-sensitive, accent-sensitive
+        assert_eq!(term(false, false, "Café"), "Café");
+        assert_ne!(term(false, false, "Café"), term(false, false, "Cafe"));
+
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_lowercase(false);
+        assert!(tokenizer.tokenize("The").is_empty());
+        assert_eq!(tokenizer.tokenize("RUST'S")[0].text, "RUST");
+    }
+
+    #[test]
+    fn test_tokenizer_possessives() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_min_token_length(1);
+
+        let texts = |tokenizer: &Tokenizer, text: &str| -> Vec<String> {
+            tokenizer
+                .tokenize(text)
+                .into_iter()
+                .map(|t| t.text)
+                .collect()
+        };
+
+        assert_eq!(texts(&tokenizer, "Rust's API"), vec!["rust", "api"]);
+        assert_eq!(
+            texts(&tokenizer, "Rust\u{2019}s don't"),
+            vec!["rust", "dont"]
+        );
+        assert_eq!(
+            texts(&tokenizer, "the students' 'quoted'"),
+            vec!["students", "quoted"]
+        );
+
+        let tokens = tokenizer.tokenize("Rust's API");
+        assert_eq!((tokens[0].start_offset, tokens[0].end_offset), (0, 6));
+        assert_eq!(tokens[1].position, 1);
+
+        tokenizer.set_strip_apostrophes(false);
+        assert_eq!(
+            texts(&tokenizer, "Rust's API don't"),
+            vec!["rust's", "api", "don't"]
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_strip_plurals() {
+        let mut tokenizer = Tokenizer::new();
+        let texts = |tokenizer: &Tokenizer, text: &str| -> Vec<String> {
+            tokenizer
+                .tokenize(text)
+                .into_iter()
+                .map(|t| t.text)
+                .collect()
+        };
+        assert_eq!(texts(&tokenizer, "APIs"), vec!["apis"]);
+
+        tokenizer.set_strip_plurals(true);
+        assert!(tokenizer.strip_plurals());
+        assert_eq!(
+            texts(
+                &tokenizer,
+                "APIs GPUs libraries class status analysis axis famous"
+            ),
+            vec![
+                "api", "gpu", "library", "class", "status", "analysis", "axis", "famous"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_position_gaps() {
+        let mut tokenizer = Tokenizer::new();
+        let positions = |tokenizer: &Tokenizer| -> Vec<usize> {
+            tokenizer
+                .tokenize("quick the brown fox")
+                .iter()
+                .map(|t| t.position)
+                .collect()
+        };
+        assert_eq!(positions(&tokenizer), vec![0, 1, 2]);
+
+        tokenizer.set_preserve_position_gaps(true);
+        assert!(tokenizer.preserve_position_gaps());
+        assert_eq!(positions(&tokenizer), vec![0, 2, 3]);
+    }
+
     #[test]
     fn test_tokenizer_stop_words() {
         let tokenizer = Tokenizer::new();
@@ -220,6 +1014,36 @@ mod tests {
         assert_eq!(tokens[1].text, "dddd");
     }
 
+    #[test]
+    fn test_field_separator_breaks_adjacency() {
+        let tokenizer = Tokenizer::new();
+        let text = format!("learn rust{}programming basics", FIELD_SEPARATOR);
+        let tokens = tokenizer.tokenize(&text);
+
+        let positions: Vec<(usize, usize)> =
+            tokens.iter().map(|t| (t.position, t.sentence)).collect();
+        assert_eq!(positions, vec![(0, 0), (1, 0), (3, 1), (4, 1)]);
+        assert_eq!(tokens[2].text, "programming");
+    }
+
+    #[test]
+    fn test_tokenizer_keeps_short_cjk() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_min_token_length(4);
+        let texts = |tokenizer: &Tokenizer| -> Vec<String> {
+            tokenizer
+                .tokenize("x é 猫 カ 한 rust")
+                .into_iter()
+                .map(|t| t.text)
+                .collect()
+        };
+
+        assert_eq!(texts(&tokenizer), vec!["rust"]);
+
+        tokenizer.set_keep_short_cjk(true);
+        assert_eq!(texts(&tokenizer), vec!["猫", "カ", "한", "rust"]);
+    }
+
     #[test]
     fn test_tokenizer_max_length() {
         let mut tokenizer = Tokenizer::new();
@@ -233,6 +1057,39 @@ mod tests {
         assert!(!token_texts.contains(&"medium".to_string()));
     }
 
+    #[test]
+    #[should_panic(expected = "max token length 3 is below min token length 5")]
+    fn test_tokenizer_min_greater_than_max_panics() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_min_token_length(5);
+        tokenizer.set_max_token_length(3);
+    }
+
+    #[test]
+    #[should_panic(expected = "min token length 60 exceeds max token length 50")]
+    fn test_tokenizer_min_above_default_max_panics() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_min_token_length(60);
+    }
+
+    #[test]
+    #[should_panic(expected = "min token length must be at least 1")]
+    fn test_tokenizer_zero_min_length_panics() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_min_token_length(0);
+    }
+
+    #[test]
+    fn test_tokenizer_min_equal_to_max() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_max_token_length(4);
+        tokenizer.set_min_token_length(4);
+
+        let tokens = tokenizer.tokenize("abc abcd abcde");
+        let token_texts: Vec<String> = tokens.iter().map(|t| t.text.clone()).collect();
+        assert_eq!(token_texts, vec!["abcd"]);
+    }
+
     #[test]
     fn test_tokenizer_punctuation() {
         let tokenizer = Tokenizer::new();
@@ -265,6 +1122,53 @@ mod tests {
         assert_eq!(token_texts, vec!["test123", "456", "abc", "def789"]);
     }
 
+    #[test]
+    fn test_tokenizer_number_handling() {
+        let mut tokenizer = Tokenizer::new();
+        let texts = |tokenizer: &Tokenizer| -> Vec<String> {
+            tokenizer
+                .tokenize("released 2023 with 456 fixes in v2")
+                .into_iter()
+                .map(|t| t.text)
+                .collect()
+        };
+        assert_eq!(tokenizer.number_handling(), NumberHandling::Index);
+        assert_eq!(
+            texts(&tokenizer),
+            vec!["released", "2023", "456", "fixes", "v2"]
+        );
+
+        tokenizer.set_number_handling(NumberHandling::Skip);
+        assert_eq!(texts(&tokenizer), vec!["released", "fixes", "v2"]);
+
+        tokenizer.set_number_handling(NumberHandling::Normalize);
+        assert_eq!(
+            texts(&tokenizer),
+            vec![
+                "released",
+                NUMBER_PLACEHOLDER,
+                NUMBER_PLACEHOLDER,
+                "fixes",
+                "v2"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_dual_index_keeps_unstemmed_form() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_stem_algorithm(StemAlgorithm::Porter);
+        assert!(tokenizer.tokenize("running")[0].exact().is_none());
+
+        tokenizer.set_dual_index(true);
+        assert!(tokenizer.dual_index());
+        let tokens = tokenizer.tokenize("Running run");
+        assert_eq!(tokens[0].text, "run");
+        assert_eq!(tokens[0].exact(), Some("running"));
+        // Words the stemmer leaves alone have nothing extra to record
+        assert!(tokens[1].exact().is_none());
+    }
+
     #[test]
     fn test_tokenizer_empty_input() {
         let tokenizer = Tokenizer::new();
@@ -314,6 +1218,125 @@ mod tests {
         assert_eq!(token_texts, vec!["the", "quick", "brown", "fox"]);
     }
 
+    #[test]
+    fn test_tokenizer_configuration_getters() {
+        let mut tokenizer = Tokenizer::new();
+        assert_eq!(tokenizer.min_token_length(), 2);
+        assert_eq!(tokenizer.max_token_length(), 50);
+        assert_eq!(tokenizer.stem_algorithm(), StemAlgorithm::None);
+        assert!(tokenizer.is_stop_word("the"));
+        assert!(tokenizer.is_stop_word("The"));
+
+        tokenizer.set_min_token_length(3);
+        tokenizer.set_max_token_length(10);
+        tokenizer.set_stem_algorithm(StemAlgorithm::Simple);
+        tokenizer.add_stop_word("Custom");
+        tokenizer.remove_stop_word("the");
+
+        assert_eq!(tokenizer.min_token_length(), 3);
+        assert_eq!(tokenizer.max_token_length(), 10);
+        assert_eq!(tokenizer.stem_algorithm(), StemAlgorithm::Simple);
+        assert!(tokenizer.is_stop_word("custom"));
+        assert!(!tokenizer.is_stop_word("the"));
+
+        let stop_words = tokenizer.stop_words();
+        assert!(stop_words.contains("custom"));
+        assert!(stop_words.contains("and"));
+        assert!(!stop_words.contains("the"));
+    }
+
+    #[test]
+    fn test_tokenizer_filter_getters() {
+        let mut tokenizer = Tokenizer::new();
+        assert!(tokenizer.lowercase());
+        assert!(!tokenizer.folding());
+        assert!(!tokenizer.strip_plurals());
+        assert!(tokenizer.strip_apostrophes());
+        assert!(!tokenizer.preserve_position_gaps());
+        assert!(!tokenizer.keep_original());
+        assert!(!tokenizer.keep_short_cjk());
+        assert_eq!(tokenizer.number_handling(), NumberHandling::Index);
+
+        tokenizer.set_lowercase(false);
+        tokenizer.set_folding(true);
+        tokenizer.set_strip_plurals(true);
+        tokenizer.set_strip_apostrophes(false);
+        tokenizer.set_preserve_position_gaps(true);
+        tokenizer.set_keep_original(true);
+        tokenizer.set_keep_short_cjk(true);
+        tokenizer.set_number_handling(NumberHandling::Skip);
+
+        assert!(!tokenizer.lowercase());
+        assert!(tokenizer.folding());
+        assert!(tokenizer.strip_plurals());
+        assert!(!tokenizer.strip_apostrophes());
+        assert!(tokenizer.preserve_position_gaps());
+        assert!(tokenizer.keep_original());
+        assert!(tokenizer.keep_short_cjk());
+        assert_eq!(tokenizer.number_handling(), NumberHandling::Skip);
+    }
+
+    #[test]
+    fn test_tokenizer_simple_stemming() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_stem_algorithm(StemAlgorithm::Simple);
+
+        let tokens = tokenizer.tokenize("Walking dogs jumped");
+
+        let token_texts: Vec<String> = tokens.iter().map(|t| t.text.clone()).collect();
+        assert_eq!(token_texts, vec!["walk", "dog", "jump"]);
+    }
+
+    #[test]
+    fn test_tokenizer_stem_algorithms() {
+        let stem = |algorithm: StemAlgorithm, word: &str| {
+            let mut tokenizer = Tokenizer::new();
+            tokenizer.set_stem_algorithm(algorithm);
+            tokenizer.tokenize(word)[0].text.clone()
+        };
+
+        assert_eq!(stem(StemAlgorithm::Porter, "generalization"), "gener");
+        assert_eq!(
+            stem(StemAlgorithm::Simple, "generalization"),
+            "generalization"
+        );
+        assert_eq!(
+            stem(StemAlgorithm::None, "generalizations"),
+            "generalizations"
+        );
+        assert_eq!(stem(StemAlgorithm::Porter, "Running"), "run");
+    }
+
+    #[test]
+    fn test_porter_stemmer_reference_outputs() {
+        let cases = [
+            ("caresses", "caress"),
+            ("ponies", "poni"),
+            ("cats", "cat"),
+            ("feed", "feed"),
+            ("agreed", "agre"),
+            ("plastered", "plaster"),
+            ("motoring", "motor"),
+            ("sing", "sing"),
+            ("conflated", "conflat"),
+            ("hopping", "hop"),
+            ("falling", "fall"),
+            ("filing", "file"),
+            ("happy", "happi"),
+            ("relational", "relat"),
+            ("conditional", "condit"),
+            ("hopefulness", "hope"),
+            ("adjustment", "adjust"),
+            ("adoption", "adopt"),
+            ("controlling", "control"),
+            ("café", "café"),
+        ];
+
+        for (word, expected) in cases {
+            assert_eq!(PorterStemmer::stem(word), expected, "stemming {}", word);
+        }
+    }
+
     #[test]
     fn test_tokenizer_unicode_characters() {
         let tokenizer = Tokenizer::new();